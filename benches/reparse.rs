@@ -0,0 +1,39 @@
+//! Compares a full parse of a large document against an incremental re-parse after a small edit.
+//! Run with `cargo bench --bench reparse`.
+use std::time::Instant;
+
+use pegmd::ast::{parse_document, reparse_document};
+
+const ITERATIONS: u32 = 20;
+
+fn main() {
+    let mut previous_input = String::new();
+    for i in 0..500 {
+        previous_input.push_str(&format!(
+            "## Section {i}\n\nSome *emphasized* text and a [link](https://x.y/{i}).\n\nAnother paragraph with `code`.\n\n"
+        ));
+    }
+    let midpoint = previous_input.len() / 2;
+    let edit_at = midpoint + previous_input[midpoint..].find("Some").unwrap();
+    let mut input = previous_input.clone();
+    input.insert_str(edit_at, "Edited ");
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box(parse_document(&input).unwrap());
+    }
+    let full = start.elapsed() / ITERATIONS;
+
+    // The previous ASTs are consumed by the re-parse, so build them all up front
+    let previous: Vec<_> = (0..ITERATIONS)
+        .map(|_| parse_document(&previous_input).unwrap())
+        .collect();
+    let start = Instant::now();
+    for ast in previous {
+        std::hint::black_box(reparse_document(&previous_input, ast, &input).unwrap());
+    }
+    let incremental = start.elapsed() / ITERATIONS;
+
+    println!("parse_document:   {full:?} per iteration");
+    println!("reparse_document: {incremental:?} per iteration");
+}
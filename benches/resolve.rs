@@ -0,0 +1,39 @@
+//! Compares rendering with the two-pass `HashMap` link resolver against `render_to_html`, which
+//! resolves the few references in the document with a single pass over its blocks.
+//! Run with `cargo bench --bench resolve --features html`.
+use std::time::Instant;
+
+use pegmd::ast::parse_document;
+use pegmd::transformer::{render_to_html, HTMLRenderer, LinkResolver};
+
+const ITERATIONS: u32 = 200;
+
+fn main() {
+    let mut input = String::new();
+    for i in 0..200 {
+        input.push_str(&format!(
+            "## Section {i}\n\nSome *emphasized* text, a [link](https://x.y/{i}), and a [reference].\n\n"
+        ));
+    }
+    input.push_str("[reference]: https://x.y/reference\n");
+    let root = parse_document(&input).unwrap();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let resolver = LinkResolver::default();
+        root.traverse(&resolver);
+        let renderer = HTMLRenderer::with_resolver(resolver);
+        root.traverse(&renderer);
+        std::hint::black_box(renderer.to_string());
+    }
+    let hash_map = start.elapsed() / ITERATIONS;
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box(render_to_html(&root));
+    }
+    let linear = start.elapsed() / ITERATIONS;
+
+    println!("HashMap resolver: {hash_map:?} per iteration");
+    println!("render_to_html:   {linear:?} per iteration");
+}
@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pegmd::ast::{parse_document, parse_document_lenient};
+use pegmd::transformer::render_to_html;
+
+// Spans are sliced out of the input by byte offset, so any input that's valid UTF-8 has to come
+// back as either a tree or an error, never a panic from slicing inside of a character.
+fuzz_target!(|input: &str| {
+    let _ = parse_document(input);
+    let root = parse_document_lenient(input);
+    for (_, node) in root.descendants_with_depth() {
+        let _ = node.outer_source();
+        let _ = node.inner_source();
+    }
+    let _ = render_to_html(&root);
+});
@@ -0,0 +1,113 @@
+//! Functions for building trees by hand, like test fixtures or documents generated by a program.
+//! Nodes built this way don't come from any input, so containers have empty spans. Leaves are
+//! spanned by a literal of what they stand for instead: text by the text itself, line breaks by
+//! `"\n"`, and thematic breaks by `"---"`.
+//!
+//! ```
+//! use pegmd::ast::builder::*;
+//!
+//! let root = document(vec![
+//!     heading(1, vec![text("Title")]),
+//!     paragraph(vec![text("Some "), emphasis(vec![text("text")])]),
+//! ]);
+//! assert_eq!(root.children().map(|c| c.len()), Some(2));
+//! ```
+
+use std::borrow::Cow;
+
+use super::model::{
+    Attributes, BlockQuote, Children, Code, CodeBlock, Document, Emphasis, Heading, Linebreak,
+    Link, List, ListItem, Node, Paragraph, SoftLinebreak, Strong, Text, ThematicBreak,
+};
+
+pub fn document(children: Vec<Node<'_>>) -> Node<'_> {
+    Node::Document(Document::new(children.into(), ""))
+}
+
+pub fn paragraph(children: Vec<Node<'_>>) -> Node<'_> {
+    Node::Paragraph(Paragraph::new(children.into(), ""))
+}
+
+pub fn heading(level: u8, children: Vec<Node<'_>>) -> Node<'_> {
+    Node::Heading(Heading::new(
+        children.into(),
+        "",
+        level,
+        Attributes::default(),
+    ))
+}
+
+pub fn block_quote(children: Vec<Node<'_>>) -> Node<'_> {
+    Node::BlockQuote(BlockQuote::new(children.into(), ""))
+}
+
+/// A tight list with one item for each of `items`. Ordered lists start at 1.
+pub fn list(ordered: bool, items: Vec<Vec<Node<'_>>>) -> Node<'_> {
+    let items: Vec<Node<'_>> = (1..)
+        .zip(items)
+        .map(|(index, children)| Node::ListItem(ListItem::new(children.into(), "", index, None)))
+        .collect();
+    Node::List(List::new(items.into(), "", true, ordered, 1))
+}
+
+/// A code block containing `code` as is.
+pub fn code_block<'input>(language: Option<&'input str>, code: &'input str) -> Node<'input> {
+    let children = Children::from(vec![text(code)]);
+    Node::CodeBlock(CodeBlock::new(children, "", language))
+}
+
+pub fn emphasis(children: Vec<Node<'_>>) -> Node<'_> {
+    Node::Emphasis(Emphasis::new(children.into(), ""))
+}
+
+pub fn strong(children: Vec<Node<'_>>) -> Node<'_> {
+    Node::Strong(Strong::new(children.into(), ""))
+}
+
+pub fn code(code: &str) -> Node<'_> {
+    Node::Code(Code::new(vec![text(code)].into(), ""))
+}
+
+pub fn link<'input>(source: &'input str, children: Vec<Node<'input>>) -> Node<'input> {
+    Node::Link(Link::new(children.into(), "", source, None, false))
+}
+
+pub fn text<'input>(literal: impl Into<Cow<'input, str>>) -> Node<'input> {
+    Node::Text(Text::new(literal))
+}
+
+pub fn linebreak() -> Node<'static> {
+    Node::Linebreak(Linebreak::new("\n"))
+}
+
+pub fn soft_linebreak() -> Node<'static> {
+    Node::SoftLinebreak(SoftLinebreak::new("\n"))
+}
+
+pub fn thematic_break() -> Node<'static> {
+    Node::ThematicBreak(ThematicBreak::new("---"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::parse_document;
+
+    #[test]
+    pub fn builder_test() {
+        let input = "# Hi\n\nSome *text* and [a link](https://x.y)\n\n1. First\n2. Second\n";
+        let parsed =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let built = document(vec![
+            heading(1, vec![text("Hi")]),
+            paragraph(vec![
+                text("Some "),
+                emphasis(vec![text("text")]),
+                text(" and "),
+                link("https://x.y", vec![text("a link")]),
+            ]),
+            list(true, vec![vec![text("First")], vec![text("Second")]]),
+        ]);
+        assert_eq!(built.debug_tree(), parsed.debug_tree());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::error::ParseError;
+
+use super::model::Node;
+use super::parse_document;
+
+/// Supplies the documents that directives like `{{include: intro.md}}` refer to, for
+/// [`resolve_includes`]. The sources have to outlive the tree that they're spliced into, since
+/// its nodes borrow from them.
+pub trait IncludeResolver<'input> {
+    /// Find the markdown source of the document called `name`, or `None` if there isn't one.
+    fn resolve(&self, name: &str) -> Option<&'input str>;
+}
+
+/// Replace every [`Node::Include`] in the tree with the blocks of the document that it names, so
+/// that a document can be assembled from partials. Included documents are parsed on their own and
+/// can include others in turn.
+///
+/// ### Parameters
+/// - `root` - The tree to resolve includes in, usually a document.
+/// - `resolver` - Supplies the source of each included document.
+///
+/// ### Returns
+/// A result that on success contains the tree with its includes spliced in, and on failure a
+/// [`ParseError`] if an included document can't be found or parsed, or if documents include each
+/// other in a cycle.
+pub fn resolve_includes<'input>(
+    mut root: Node<'input>,
+    resolver: &impl IncludeResolver<'input>,
+) -> Result<Node<'input>, ParseError> {
+    splice(&mut root, resolver, &mut Vec::new())?;
+    Ok(root)
+}
+
+fn splice<'input>(
+    node: &mut Node<'input>,
+    resolver: &impl IncludeResolver<'input>,
+    // The names of the documents that are being included, from the outermost one in
+    including: &mut Vec<&'input str>,
+) -> Result<(), ParseError> {
+    let Some(children) = node.children_mut() else {
+        return Ok(());
+    };
+    let mut spliced = Vec::with_capacity(children.len());
+    for mut child in std::mem::take(&mut **children) {
+        match child {
+            Node::Include(include) => {
+                spliced.extend(included(include.name(), resolver, including)?);
+            }
+            _ => {
+                splice(&mut child, resolver, including)?;
+                spliced.push(child);
+            }
+        }
+    }
+    **children = spliced;
+    Ok(())
+}
+
+fn included<'input>(
+    name: &'input str,
+    resolver: &impl IncludeResolver<'input>,
+    including: &mut Vec<&'input str>,
+) -> Result<Vec<Node<'input>>, ParseError> {
+    if including.contains(&name) {
+        let cycle = [including.as_slice(), &[name]].concat().join(" -> ");
+        return Err(ParseError::SyntaxError(format!(
+            "Documents include each other in a cycle: {cycle}"
+        )));
+    }
+    let source = resolver.resolve(name).ok_or_else(|| {
+        ParseError::SyntaxError(format!(r#"No document found to include for "{name}""#))
+    })?;
+
+    let mut document = parse_document(source)?;
+    including.push(name);
+    splice(&mut document, resolver, including)?;
+    including.pop();
+    Ok(document
+        .into_children()
+        .map(|mut children| std::mem::take(&mut *children))
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    struct Partials(HashMap<&'static str, &'static str>);
+
+    impl IncludeResolver<'static> for Partials {
+        fn resolve(&self, name: &str) -> Option<&'static str> {
+            self.0.get(name).copied()
+        }
+    }
+
+    #[test]
+    pub fn resolve_includes_test() {
+        let partials = Partials(HashMap::from([
+            ("intro.md", "Some *intro*\n\n{{include: footer.md}}"),
+            ("footer.md", "---\n"),
+        ]));
+        let root = parse_document("# Title\n\n{{include: intro.md}}\n\n- Last\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert!(matches!(root.children().unwrap()[1], Node::Include(_)));
+
+        let root = resolve_includes(root, &partials)
+            .unwrap_or_else(|e| panic!("Failed to resolve includes: {e}"));
+        let blocks = root.children().unwrap();
+        let names = blocks.iter().map(Node::type_name).collect::<Vec<_>>();
+        assert_eq!(names, ["Heading", "Paragraph", "ThematicBreak", "List"]);
+        assert_eq!(blocks[1].as_span(), "Some *intro*\n\n");
+    }
+
+    #[test]
+    pub fn include_cycle_test() {
+        let partials = Partials(HashMap::from([
+            ("a.md", "{{include: b.md}}"),
+            ("b.md", "{{include: a.md}}"),
+        ]));
+        let root = parse_document("{{include: a.md}}")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let error = resolve_includes(root, &partials).expect_err("Expected an include cycle");
+        assert!(error.to_string().contains("a.md -> b.md -> a.md"));
+
+        let root = parse_document("{{include: missing.md}}")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert!(resolve_includes(root, &partials).is_err());
+    }
+}
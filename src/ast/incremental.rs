@@ -0,0 +1,372 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::error::ParseError;
+use crate::first_child;
+use crate::parser::{MarkdownParser, Rule};
+use pest::Parser;
+
+use super::model::{Attributes, Children, Document, ErrorBlock, Node};
+use super::parse_document;
+
+/// Re-parse a document after its source was edited, reusing the top-level blocks of the previous
+/// AST that weren't touched by the edit. Only the region of `input` between the unchanged leading
+/// and trailing blocks is parsed again, which makes this much cheaper than [`parse_document`] for
+/// small edits to large documents, like keystrokes in an editor.
+///
+/// The untouched block on either side of the edit is parsed again along with it, to check that
+/// the edit didn't change where the blocks around it start or end, like a paragraph absorbing the
+/// line after it or a code fence that's opened but never closed. If either one comes out
+/// differently, the whole document is parsed again instead. Either way, the resulting tree is
+/// structurally equal to the one [`parse_document`] would produce for `input`, and the reused
+/// blocks are moved onto `input` so that all of its spans are slices of `input`.
+///
+/// ### Parameters
+/// - `previous_input` - The markdown source that `previous` was parsed from.
+/// - `previous` - The root of the AST for `previous_input`.
+/// - `input` - The edited markdown source.
+///
+/// ### Returns
+/// A result that on success contains the root of the AST for `input`, and on failure a [`ParseError`].
+pub fn reparse_document<'a>(
+    previous_input: &'a str,
+    previous: Node<'a>,
+    input: &'a str,
+) -> Result<Node<'a>, ParseError> {
+    let mut blocks = match previous {
+        Node::Document(mut document) => std::mem::take(document.children_mut()),
+        _ => return parse_document(input),
+    };
+
+    let ranges = match block_ranges(previous_input, &blocks) {
+        Some(ranges) => ranges,
+        // The previous AST wasn't parsed from the previous input, so nothing can be reused
+        None => return parse_document(input),
+    };
+
+    let common_prefix = previous_input
+        .bytes()
+        .zip(input.bytes())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let max_suffix = std::cmp::min(previous_input.len(), input.len()) - common_prefix;
+    let common_suffix = previous_input
+        .bytes()
+        .rev()
+        .zip(input.bytes().rev())
+        .take(max_suffix)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix_start = previous_input.len() - common_suffix;
+    // Where a byte of the unchanged suffix of the previous input is in the new one
+    let moved = |offset: usize| offset + input.len() - previous_input.len();
+
+    let untouched_leading = ranges
+        .iter()
+        .take_while(|range| range.end < common_prefix)
+        .count();
+    let untouched_trailing = ranges[untouched_leading..]
+        .iter()
+        .rev()
+        .take_while(|range| range.start >= suffix_start)
+        .count();
+
+    // The blocks from `first` up to `last` are parsed again, including the untouched block on
+    // either side of the edit
+    let first = untouched_leading.saturating_sub(1);
+    let last = blocks.len() - untouched_trailing.saturating_sub(1);
+    let region_start = match first {
+        0 => 0,
+        n => ranges[n - 1].end,
+    };
+    let region_end = match ranges.get(last) {
+        Some(range) => moved(range.start),
+        None => input.len(),
+    };
+
+    let mut raw_tokens = MarkdownParser::parse(Rule::document, &input[region_start..region_end])?;
+    let mut region = match Node::try_from(first_child!(raw_tokens)?)? {
+        Node::Document(mut document) => std::mem::take(document.children_mut()),
+        _ => Children::default(),
+    };
+
+    // If the edit changed where the blocks around it start or end, like by opening a code fence
+    // that runs to the end of the document, the blocks past them can't be reused either
+    let leading_moved = untouched_leading > 0
+        && !region
+            .first()
+            .is_some_and(|parsed| same_block(input, parsed, &blocks[first], ranges[first].start));
+    let trailing_moved = untouched_trailing > 0
+        && !region.last().is_some_and(|parsed| {
+            same_block(
+                input,
+                parsed,
+                &blocks[last - 1],
+                moved(ranges[last - 1].start),
+            )
+        });
+    if leading_moved || trailing_moved {
+        return parse_document(input);
+    }
+
+    let mut trailing_blocks = blocks.split_off(last);
+    blocks.truncate(first);
+    let leading = Rebaser::new(previous_input, input, 0);
+    blocks.iter_mut().for_each(|block| block.rebase(&leading));
+    let trailing = Rebaser::new(
+        previous_input,
+        input,
+        input.len() as isize - previous_input.len() as isize,
+    );
+    trailing_blocks
+        .iter_mut()
+        .for_each(|block| block.rebase(&trailing));
+
+    blocks.append(&mut region);
+    blocks.append(&mut trailing_blocks);
+    Ok(Node::Document(Document::new(blocks, input)))
+}
+
+/// Whether a block that was just parsed from `input` came out the same as the block it was in
+/// the previous tree, starting at `start` in `input`.
+fn same_block(input: &str, parsed: &Node, previous: &Node, start: usize) -> bool {
+    let span = parsed.as_span();
+    span.as_ptr() as usize == input.as_ptr() as usize + start
+        && span.len() == previous.as_span().len()
+        && parsed.structurally_eq(previous)
+}
+
+/// Find the byte range that each block occupies in the input, or `None` if any of the blocks
+/// aren't slices of the input.
+fn block_ranges(input: &str, blocks: &[Node]) -> Option<Vec<Range<usize>>> {
+    let base = input.as_ptr() as usize;
+    blocks
+        .iter()
+        .map(|block| {
+            let span = block.as_span();
+            let start = (span.as_ptr() as usize).checked_sub(base)?;
+            let end = start + span.len();
+            (end <= input.len()).then_some(start..end)
+        })
+        .collect()
+}
+
+/// Moves the slices of a previous input that a reused node borrows onto the same bytes of the
+/// edited input, which are `moved_by` bytes later in it.
+pub(crate) struct Rebaser<'a> {
+    previous_input: &'a str,
+    input: &'a str,
+    moved_by: isize,
+}
+
+impl<'a> Rebaser<'a> {
+    fn new(previous_input: &'a str, input: &'a str, moved_by: isize) -> Self {
+        Self {
+            previous_input,
+            input,
+            moved_by,
+        }
+    }
+
+    /// The same bytes as `literal` in the edited input. Slices that aren't from the previous
+    /// input, like the empty strings of hand-built nodes, are left as they are.
+    fn rebase(&self, literal: &'a str) -> &'a str {
+        let rebased = (literal.as_ptr() as usize)
+            .checked_sub(self.previous_input.as_ptr() as usize)
+            .filter(|&offset| offset + literal.len() <= self.previous_input.len())
+            .and_then(|offset| offset.checked_add_signed(self.moved_by))
+            .and_then(|start| self.input.get(start..start + literal.len()));
+        rebased.unwrap_or(literal)
+    }
+}
+
+/// Types that can have the slices of the input that they borrow moved onto an edited input.
+pub(crate) trait Rebase<'a> {
+    fn rebase(&mut self, rebaser: &Rebaser<'a>);
+}
+
+impl<'a> Rebase<'a> for &'a str {
+    fn rebase(&mut self, rebaser: &Rebaser<'a>) {
+        *self = rebaser.rebase(self);
+    }
+}
+
+impl<'a> Rebase<'a> for Cow<'a, str> {
+    fn rebase(&mut self, rebaser: &Rebaser<'a>) {
+        if let Cow::Borrowed(literal) = self {
+            literal.rebase(rebaser);
+        }
+    }
+}
+
+impl<'a, T: Rebase<'a>> Rebase<'a> for Option<T> {
+    fn rebase(&mut self, rebaser: &Rebaser<'a>) {
+        if let Some(value) = self {
+            value.rebase(rebaser);
+        }
+    }
+}
+
+// Fields that don't borrow from the input
+macro_rules! owned_rebase {
+    ($($ty: ty),+) => {
+        $(impl<'a> Rebase<'a> for $ty {
+            fn rebase(&mut self, _: &Rebaser<'a>) {}
+        })+
+    };
+}
+
+owned_rebase!(bool, u8, u32);
+
+impl<'a> Rebase<'a> for Children<'a> {
+    fn rebase(&mut self, rebaser: &Rebaser<'a>) {
+        self.iter_mut().for_each(|child| child.rebase(rebaser));
+    }
+}
+
+impl<'a> Rebase<'a> for ErrorBlock<'a> {
+    fn rebase(&mut self, rebaser: &Rebaser<'a>) {
+        self.literal.rebase(rebaser);
+    }
+}
+
+impl<'a> Rebase<'a> for Attributes<'a> {
+    fn rebase(&mut self, rebaser: &Rebaser<'a>) {
+        self.span.rebase(rebaser);
+        self.id.rebase(rebaser);
+        self.classes
+            .iter_mut()
+            .for_each(|class| class.rebase(rebaser));
+        for (key, value) in &mut self.pairs {
+            key.rebase(rebaser);
+            value.rebase(rebaser);
+        }
+    }
+}
+
+impl<'a> Rebase<'a> for Node<'a> {
+    fn rebase(&mut self, rebaser: &Rebaser<'a>) {
+        match self {
+            Self::Document(c) => c.rebase(rebaser),
+            Self::Paragraph(p) => p.rebase(rebaser),
+            Self::BlockQuote(bq) => bq.rebase(rebaser),
+            Self::Heading(h) => h.rebase(rebaser),
+            Self::List(l) => l.rebase(rebaser),
+            Self::ListItem(li) => li.rebase(rebaser),
+            Self::LineBlock(lb) => lb.rebase(rebaser),
+            Self::Line(l) => l.rebase(rebaser),
+            Self::CodeBlock(cb) => cb.rebase(rebaser),
+            Self::Emphasis(emp) => emp.rebase(rebaser),
+            Self::Strong(strong) => strong.rebase(rebaser),
+            Self::Subscript(sub) => sub.rebase(rebaser),
+            Self::Superscript(sup) => sup.rebase(rebaser),
+            Self::Highlight(highlight) => highlight.rebase(rebaser),
+            Self::Label(l) => l.rebase(rebaser),
+            Self::Link(l) => l.rebase(rebaser),
+            Self::Code(c) => c.rebase(rebaser),
+            Self::Image(img) => img.rebase(rebaser),
+            Self::Text(txt) => txt.rebase(rebaser),
+            Self::Linebreak(lb) => lb.rebase(rebaser),
+            Self::SoftLinebreak(slb) => slb.rebase(rebaser),
+            Self::ThematicBreak(tb) => tb.rebase(rebaser),
+            Self::Reference(r) => r.rebase(rebaser),
+            Self::InlineMath(m) => m.rebase(rebaser),
+            Self::DisplayMath(m) => m.rebase(rebaser),
+            Self::ErrorBlock(e) => e.rebase(rebaser),
+            Self::FootnoteReference(f) => f.rebase(rebaser),
+            Self::Include(i) => i.rebase(rebaser),
+            Self::FootnoteDefinition(f) => f.rebase(rebaser),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Reparse `input` after editing `previous_input`, checking that the tree is the same as a full
+    /// parse and that all of its spans are slices of `input`.
+    fn check_reparse<'a>(previous_input: &'a str, input: &'a str) -> Node<'a> {
+        let previous = parse_document(previous_input)
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let reparsed = reparse_document(previous_input, previous, input)
+            .unwrap_or_else(|e| panic!("Failed to reparse document: {e}"));
+        let expected =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert!(reparsed.structurally_eq(&expected), "{reparsed:#?}");
+        assert_eq!(reparsed, expected);
+
+        let range = input.as_ptr() as usize..input.as_ptr() as usize + input.len();
+        for (_, node) in reparsed.descendants_with_depth() {
+            let span = node.as_span();
+            let start = span.as_ptr() as usize;
+            assert!(
+                range.contains(&start) || start == range.end,
+                "{span:?} isn't a slice of the input"
+            );
+        }
+        reparsed
+    }
+
+    #[test]
+    pub fn reparse_test() {
+        let previous_input = "# Title\n\nFirst paragraph\n\nSecond paragraph\n\nThird paragraph\n\n- A list\n- Of items\n";
+        let input = "# Title\n\nFirst paragraph\n\nSecond *edited* paragraph\n\nThird paragraph\n\n- A list\n- Of items\n";
+        let reparsed = check_reparse(previous_input, input);
+
+        // The heading and the list were far enough from the edit to be reused, and now point into
+        // the new input
+        let children = reparsed.children().unwrap();
+        assert_eq!(children[0].as_span().as_ptr(), input.as_ptr());
+        assert_eq!(
+            children[4].as_span().as_ptr(),
+            input[input.find("- A list").unwrap()..].as_ptr()
+        );
+    }
+
+    #[test]
+    pub fn reparse_fence_test() {
+        // An unclosed fence swallows the rest of the document
+        check_reparse("A\n\nB\n\nC\n\nD\n\nE\n", "A\n\n```\nB\n\nC\n\nD\n\nE\n");
+        // And so does one that loses its closing fence
+        check_reparse(
+            "# T\n\nA\n\n```\ncode\n```\n\nB\n\nC\n\nD\n",
+            "# T\n\nA\n\n```\ncode\n\nB\n\nC\n\nD\n",
+        );
+        // Closing the fence again splits it back up
+        check_reparse(
+            "A\n\n```\nB\n\nC\n\nD\n\nE\n",
+            "A\n\n```\nB\n```\n\nC\n\nD\n\nE\n",
+        );
+    }
+
+    #[test]
+    pub fn reparse_edge_blocks_test() {
+        let previous_input = "# Title\n\nA\n\nB\n\nC\n\nD\n";
+        check_reparse(previous_input, "# New *title*\n\nA\n\nB\n\nC\n\nD\n");
+        check_reparse(previous_input, "# Title\n\nA\n\nB\n\nC\n\nD and more\n");
+        check_reparse(previous_input, "# Title\n\nA\n\nB\n\nC\n\nD\n\n- New\n");
+        check_reparse(previous_input, "A\n\nB\n\nC\n\nD\n");
+    }
+
+    #[test]
+    pub fn reparse_top_level_blocks_test() {
+        let previous_input = "# Title\n\nFirst\n\nSecond\n\nThird\n\nFourth\n";
+        let input = "# Title\n\nFirst\n\nSecond, but *longer*\n\nThird\n\nFourth\n";
+        let reparsed = check_reparse(previous_input, input);
+        let expected =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let blocks = |root: &Node<'_>| match root {
+            Node::Document(document) => document
+                .top_level_blocks()
+                .map(|(range, _)| range)
+                .collect::<Vec<_>>(),
+            other => panic!("Expected a document, got {other:?}"),
+        };
+        assert_eq!(blocks(&reparsed), blocks(&expected));
+        assert_eq!(
+            blocks(&reparsed).last().map(|range| range.end),
+            Some(input.len())
+        );
+    }
+}
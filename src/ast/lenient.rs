@@ -0,0 +1,93 @@
+use pest::error::InputLocation;
+use pest::Parser;
+
+use crate::error::ParseError;
+use crate::parser::{MarkdownParser, Rule};
+
+use super::model::{Children, Document, ErrorBlock, Node};
+
+/// Generate an AST for the markdown document like [`parse_document`](super::parse_document), but
+/// without failing the whole document because of one bad block, for things like live previews in
+/// an editor. Each top-level block that can't be parsed becomes a [`Node::ErrorBlock`] holding
+/// its source and the error, and parsing continues with the next block.
+///
+/// ### Parameters
+/// - `input` - The markdown source.
+///
+/// ### Returns
+/// The root of the AST, which is always a document.
+pub fn parse_document_lenient(input: &str) -> Node<'_> {
+    let mut blocks = Vec::new();
+    parse_blocks(input, &mut blocks);
+    Node::Document(Document::new(Children::from(blocks), input))
+}
+
+fn parse_blocks<'a>(input: &'a str, blocks: &mut Vec<Node<'a>>) {
+    let mut rest = input;
+    while !rest.is_empty() {
+        let error = match MarkdownParser::parse(Rule::document, rest) {
+            Ok(mut pairs) => {
+                let document = pairs.next().into_iter().flat_map(|pair| pair.into_inner());
+                for pair in document.filter(|pair| pair.as_rule() != Rule::EOI) {
+                    let span = pair.as_str();
+                    blocks.push(Node::try_from(pair).unwrap_or_else(|e| error_block(span, &e)));
+                }
+                return;
+            }
+            Err(error) => error,
+        };
+
+        // The tokenizer can only say where it gave up, so the bad block is taken to be the
+        // paragraph around that spot, from the blank line before it to the blank line after it.
+        let position = match error.location {
+            InputLocation::Pos(position) => position,
+            InputLocation::Span((start, _)) => start,
+        };
+        let start = rest[..position].rfind("\n\n").map_or(0, |i| i + 2);
+        let end = rest[position..]
+            .find("\n\n")
+            .map_or(rest.len(), |i| position + i + 2);
+        if start > 0 {
+            // Everything before the bad block parsed up to here, but might fail on its own now
+            // that it ends somewhere else
+            parse_blocks(&rest[..start], blocks);
+        }
+        blocks.push(error_block(&rest[start..end], &ParseError::from(error)));
+        rest = &rest[end..];
+    }
+}
+
+fn error_block<'a>(span: &'a str, error: &ParseError) -> Node<'a> {
+    Node::ErrorBlock(ErrorBlock::new(span, error))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::parse_document;
+
+    #[test]
+    pub fn lenient_parse_test() {
+        let input = "# Title\n\nSome *text*\n\n  ";
+        assert!(parse_document(input).is_err());
+
+        let root = parse_document_lenient(input);
+        let blocks = root.children().unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert!(matches!(blocks[0], Node::Heading(_)));
+        assert!(matches!(blocks[1], Node::Paragraph(_)));
+        match &blocks[2] {
+            Node::ErrorBlock(error_block) => {
+                assert_eq!(error_block.as_span(), "  ");
+                assert!(error_block.error().contains("Failed to lex"));
+            }
+            other => panic!("Expected an error block, got {other:?}"),
+        }
+
+        // Documents that parse are the same as with the strict parser
+        let input = "# Title\n\nSome *text*";
+        let strict =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert_eq!(parse_document_lenient(input), strict);
+    }
+}
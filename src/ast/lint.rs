@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+
+use super::model::Node;
+
+/// The kinds of authoring mistakes that [`lint`] can find.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningKind {
+    /// A line in a list is indented with a mix of tabs and spaces. Only spaces are used to decide
+    /// how deeply a list item is nested, so sublists indented like this usually end up as text
+    /// in their parent item instead.
+    MixedListIndentation,
+    /// A reference is defined more than once. Like in CommonMark, links use the first definition,
+    /// so the later ones are ignored.
+    DuplicateReference,
+}
+
+/// Something in a document that parsed successfully, but probably not into what the author meant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning<'input> {
+    kind: WarningKind,
+    span: &'input str,
+}
+
+impl<'input> Warning<'input> {
+    pub fn kind(&self) -> WarningKind {
+        self.kind
+    }
+
+    /// The slice of the input that the warning is about.
+    pub fn span(&self) -> &'input str {
+        self.span
+    }
+}
+
+impl<'input> Display for Warning<'input> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            WarningKind::MixedListIndentation => write!(
+                f,
+                "List indentation mixes tabs and spaces in {:?}",
+                self.span
+            ),
+            WarningKind::DuplicateReference => write!(
+                f,
+                "Reference is already defined, so this definition is ignored: {:?}",
+                self.span
+            ),
+        }
+    }
+}
+
+/// Check a tree for common authoring mistakes that don't stop the document from parsing, but
+/// that tend to produce surprising output.
+///
+/// ### Parameters
+/// - `root` - The node to check, usually the root of the AST.
+///
+/// ### Returns
+/// The warnings in document order, which is empty if nothing was found.
+pub fn lint<'input>(root: &Node<'input>) -> Vec<Warning<'input>> {
+    let mut warnings = Vec::new();
+    mixed_list_indentation(root, &mut warnings);
+    duplicate_references(root, &mut warnings);
+    warnings.sort_by_key(|warning| warning.span.as_ptr());
+    warnings
+}
+
+fn mixed_list_indentation<'input>(node: &Node<'input>, warnings: &mut Vec<Warning<'input>>) {
+    let span = match node {
+        Node::List(list) => list.as_span(),
+        _ => {
+            for child in node {
+                mixed_list_indentation(child, warnings);
+            }
+            return;
+        }
+    };
+
+    // Nested lists are part of their parent's span, so each line only needs to be checked once.
+    // The first line's indentation is outside of the span.
+    for line in span.split('\n').skip(1) {
+        let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+        if indent.contains(' ') && indent.contains('\t') {
+            warnings.push(Warning {
+                kind: WarningKind::MixedListIndentation,
+                span: line.strip_suffix('\r').unwrap_or(line),
+            });
+        }
+    }
+}
+
+fn duplicate_references<'input>(node: &Node<'input>, warnings: &mut Vec<Warning<'input>>) {
+    // Only definitions at the top level of the document are used for links
+    let mut names = HashSet::new();
+    for child in node {
+        if let Node::Reference(reference) = child {
+            if !names.insert(reference.normalized_name()) {
+                warnings.push(Warning {
+                    kind: WarningKind::DuplicateReference,
+                    span: reference.as_span(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::parse_document;
+
+    #[test]
+    pub fn mixed_list_indentation_test() {
+        let input = "- First\n\t - Nested\n  - Fine\n\nNot\t in a list\n";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let warnings = lint(&root);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind(), WarningKind::MixedListIndentation);
+        assert_eq!(warnings[0].span(), "\t - Nested");
+
+        let root = parse_document("- First\n  - Nested\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert!(lint(&root).is_empty());
+    }
+
+    #[test]
+    pub fn duplicate_reference_test() {
+        let input = "[x]\n\n[x]: /first\n[X]: /second\n[y]: /other";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let warnings = lint(&root);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind(), WarningKind::DuplicateReference);
+        assert_eq!(warnings[0].span(), "[X]: /second");
+    }
+}
@@ -28,14 +28,18 @@ macro_rules! first_child {
 /// are specified since there's no general way to parse those fields from the Pair.
 ///
 /// If extra fields are provided, the macro will create a non-mutating getter method for each field.
+/// Fields listed after a `;` don't have to be [`Copy`], and their getters return a reference.
 #[macro_export]
 macro_rules! container_type {
     ($name:ident) => {
-        #[derive(std::fmt::Debug, PartialEq)]
+        #[derive(std::fmt::Debug, Clone, PartialEq)]
         #[cfg_attr(feature = "serde_support", derive(serde::Serialize, serde::Deserialize))]
+        #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
         pub struct $name<'input> {
+            #[cfg_attr(feature = "serde_support", serde(borrow))]
             children: Children<'input>,
-            #[cfg_attr(feature = "serde_support", serde(skip_serializing))]
+            // Spans point into the input, so they aren't serialized and are empty when deserialized
+            #[cfg_attr(feature = "serde_support", serde(skip))]
             span: &'input str,
         }
 
@@ -58,28 +62,57 @@ macro_rules! container_type {
                 }
             }
 
-            pub fn children(&self) -> &Children {
+            pub fn children(&self) -> &Children<'input> {
                 &self.children
             }
 
-            pub fn children_mut(&'input mut self) -> &mut Children {
+            pub fn children_mut(&mut self) -> &mut Children<'input> {
                 &mut self.children
             }
 
-            pub fn as_span(&self) -> &str {
+            /// Consume this node, returning ownership of its children.
+            pub fn into_children(self) -> Children<'input> {
+                self.children
+            }
+
+            /// Add a node to the end of this node's children, returning the updated node.
+            pub fn with_child(mut self, child: Node<'input>) -> Self {
+                self.children.push(child);
+                self
+            }
+
+            pub fn as_span(&self) -> &'input str {
                 self.span
             }
+
+            /// Copy this node without its children, giving the copy `children` instead.
+            pub fn clone_with_children(&self, children: Children<'input>) -> Self {
+                Self {
+                    children,
+                    span: self.span,
+                }
+            }
+        }
+
+        impl <'input> Rebase<'input> for $name<'input> {
+            fn rebase(&mut self, rebaser: &Rebaser<'input>) {
+                self.span.rebase(rebaser);
+                self.children.rebase(rebaser);
+            }
         }
     };
 
-    ($name: ident $(, ($field_name: ident, $ty: ty))+) => {
-        #[derive(std::fmt::Debug, PartialEq)]
+    ($name: ident $(, ($field_name: ident, $ty: ty))+ $(; $(($ref_name: ident, $ref_ty: ty)),+)?) => {
+        #[derive(std::fmt::Debug, Clone, PartialEq)]
         #[cfg_attr(feature = "serde_support", derive(serde::Serialize, serde::Deserialize))]
+        #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
         pub struct $name<'input> {
+            #[cfg_attr(feature = "serde_support", serde(borrow))]
             children: Children<'input>,
-            #[cfg_attr(feature = "serde_support", serde(skip_serializing))]
+            #[cfg_attr(feature = "serde_support", serde(skip))]
             span: &'input str,
             $($field_name: $ty,)+
+            $($($ref_name: $ref_ty,)+)?
         }
 
         impl <'input> $name<'input> {
@@ -89,29 +122,65 @@ macro_rules! container_type {
                 children: Children<'input>,
                 span: &'input str
                 $(, $field_name: $ty)+
+                $($(, $ref_name: $ref_ty)+)?
             ) -> Self {
                 Self {
                     children,
                     span,
                     $($field_name,)+
+                    $($($ref_name,)+)?
                 }
             }
 
-            pub fn as_span(&self) -> &str {
+            pub fn as_span(&self) -> &'input str {
                 self.span
             }
 
-            pub fn children(&self) -> &Children {
+            /// Copy this node without its children, giving the copy `children` instead.
+            pub fn clone_with_children(&self, children: Children<'input>) -> Self {
+                Self {
+                    children,
+                    span: self.span,
+                    $($field_name: self.$field_name,)+
+                    $($($ref_name: self.$ref_name.clone(),)+)?
+                }
+            }
+
+            pub fn children(&self) -> &Children<'input> {
                 &self.children
             }
 
-            pub fn children_mut(&'input mut self) -> &mut Children {
+            pub fn children_mut(&mut self) -> &mut Children<'input> {
                 &mut self.children
             }
 
+            /// Consume this node, returning ownership of its children.
+            pub fn into_children(self) -> Children<'input> {
+                self.children
+            }
+
+            /// Add a node to the end of this node's children, returning the updated node.
+            pub fn with_child(mut self, child: Node<'input>) -> Self {
+                self.children.push(child);
+                self
+            }
+
             $(pub fn $field_name(&self) -> $ty {
                 self.$field_name
             })+
+
+            $($(pub fn $ref_name(&self) -> &$ref_ty {
+                &self.$ref_name
+            })+)?
+        }
+
+        impl <'input> Rebase<'input> for $name<'input> {
+            fn rebase(&mut self, rebaser: &Rebaser<'input>) {
+                self.span.rebase(rebaser);
+                self.children.rebase(rebaser);
+                $(self.$field_name.rebase(rebaser);)+
+                $($(self.$ref_name.rebase(rebaser);)+)?
+            }
         }
     };
 }
@@ -125,12 +194,14 @@ macro_rules! container_type {
 /// are specified since there's no general way to parse those fields from the Pair.
 ///
 /// If extra fields are provided, the macro will create a non-mutating getter method for each field.
+/// Fields listed after a `;` don't have to be [`Copy`], and their getters return a reference.
 #[macro_export]
 macro_rules! leaf_type {
     ($name: ident) => {
 
-        #[derive(std::fmt::Debug, PartialEq)]
+        #[derive(std::fmt::Debug, Clone, PartialEq)]
         #[cfg_attr(feature = "serde_support", derive(serde::Serialize, serde::Deserialize))]
+        #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
         pub struct $name<'input> {
             literal: &'input str,
         }
@@ -148,19 +219,87 @@ macro_rules! leaf_type {
         }
 
         impl <'input> $name<'input> {
+            #[allow(dead_code)]
+            pub fn new(literal: &'input str) -> Self {
+                Self { literal }
+            }
+
             pub fn as_span(&self) -> &'input str {
                 &self.literal
             }
         }
+
+        impl <'input> Rebase<'input> for $name<'input> {
+            fn rebase(&mut self, rebaser: &Rebaser<'input>) {
+                self.literal.rebase(rebaser);
+            }
+        }
+    };
+
+    // Leaves whose literal normally borrows from the input, but can also own a string that was
+    // synthesized from it, like text with its escapes or entities decoded.
+    (cow $name: ident) => {
+
+        #[derive(std::fmt::Debug, Clone, PartialEq)]
+        #[cfg_attr(feature = "serde_support", derive(serde::Serialize, serde::Deserialize))]
+        #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+        pub struct $name<'input> {
+            #[cfg_attr(feature = "serde_support", serde(borrow))]
+            literal: std::borrow::Cow<'input, str>,
+        }
+
+        impl <'input> From<Pair<'input, Rule>> for $name<'input> {
+            fn from(value: Pair<'input, Rule>) -> Self {
+                Self { literal: std::borrow::Cow::Borrowed(value.as_str()) }
+            }
+        }
+
+        impl <'input> AsRef<str> for $name<'input> {
+            fn as_ref(&self) -> &str {
+                &self.literal
+            }
+        }
+
+        impl <'input> $name<'input> {
+            /// Create the leaf from either a slice of the input or an owned string.
+            #[allow(dead_code)]
+            pub fn new(literal: impl Into<std::borrow::Cow<'input, str>>) -> Self {
+                Self { literal: literal.into() }
+            }
+
+            pub fn as_span(&self) -> &str {
+                &self.literal
+            }
+
+            /// The literal as a slice of the input, or `None` if it was synthesized.
+            pub fn as_borrowed(&self) -> Option<&'input str> {
+                match self.literal {
+                    std::borrow::Cow::Borrowed(literal) => Some(literal),
+                    std::borrow::Cow::Owned(_) => None,
+                }
+            }
+
+            pub fn into_literal(self) -> std::borrow::Cow<'input, str> {
+                self.literal
+            }
+        }
+
+        impl <'input> Rebase<'input> for $name<'input> {
+            fn rebase(&mut self, rebaser: &Rebaser<'input>) {
+                self.literal.rebase(rebaser);
+            }
+        }
     };
 
-    ($name: ident $(, ($field_name: ident, $ty: ty))+) => {
+    ($name: ident $(, ($field_name: ident, $ty: ty))+ $(; $(($ref_name: ident, $ref_ty: ty)),+)?) => {
 
-        #[derive(std::fmt::Debug, PartialEq)]
+        #[derive(std::fmt::Debug, Clone, PartialEq)]
         #[cfg_attr(feature = "serde_support", derive(serde::Serialize, serde::Deserialize))]
+        #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
         pub struct $name<'input> {
             literal: &'input str,
             $($field_name: $ty,)+
+            $($($ref_name: $ref_ty,)+)?
         }
 
         impl <'input> AsRef<str> for $name<'input> {
@@ -179,6 +318,20 @@ macro_rules! leaf_type {
                     self.$field_name
                 }
             )+
+
+            $($(
+                pub fn $ref_name(&self) -> &$ref_ty {
+                    &self.$ref_name
+                }
+            )+)?
+        }
+
+        impl <'input> Rebase<'input> for $name<'input> {
+            fn rebase(&mut self, rebaser: &Rebaser<'input>) {
+                self.literal.rebase(rebaser);
+                $(self.$field_name.rebase(rebaser);)+
+                $($(self.$ref_name.rebase(rebaser);)+)?
+            }
         }
     };
 }
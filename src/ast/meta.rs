@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use crate::error::ParseError;
+
+use super::model::{Node, NodeKind};
+use super::parse_document;
+
+/// A summary of what's in a document, like how many of each kind of node it has, for things like
+/// dashboards that would otherwise need a traversal of their own to find out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentMeta {
+    counts: HashMap<NodeKind, usize>,
+    max_heading_level: Option<u8>,
+}
+
+impl DocumentMeta {
+    /// Summarize the tree under `root`, including `root` itself, in a single pass over it.
+    pub fn of(root: &Node) -> Self {
+        let mut meta = Self::default();
+        for (_, node) in root.descendants_with_depth() {
+            *meta.counts.entry(node.kind()).or_insert(0) += 1;
+            if let Node::Heading(heading) = node {
+                meta.max_heading_level = meta.max_heading_level.max(Some(heading.level()));
+            }
+        }
+        meta
+    }
+
+    /// How many nodes of a kind the document has, like [`NodeKind::Heading`] for the number of
+    /// [`Node::Heading`]s.
+    pub fn count(&self, kind: NodeKind) -> usize {
+        self.counts.get(&kind).copied().unwrap_or(0)
+    }
+
+    pub fn has_code_blocks(&self) -> bool {
+        self.count(NodeKind::CodeBlock) > 0
+    }
+
+    pub fn has_images(&self) -> bool {
+        self.count(NodeKind::Image) > 0
+    }
+
+    pub fn has_links(&self) -> bool {
+        self.count(NodeKind::Link) > 0
+    }
+
+    /// The level of the most deeply nested heading, like 3 for a `###` heading, or `None` if the
+    /// document has no headings.
+    pub fn max_heading_level(&self) -> Option<u8> {
+        self.max_heading_level
+    }
+}
+
+/// Generate an AST for the markdown document like [`parse_document`], along with a
+/// [`DocumentMeta`] summarizing it.
+///
+/// ### Parameters
+/// - `input` - The markdown source.
+///
+/// ### Returns
+/// A result that on success contains the root of the AST and its summary, and on failure a
+/// [`ParseError`].
+pub fn parse_document_with_meta(input: &str) -> Result<(Node<'_>, DocumentMeta), ParseError> {
+    let root = parse_document(input)?;
+    let meta = DocumentMeta::of(&root);
+    Ok((root, meta))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn document_meta_test() {
+        let input = "# Title\n\nSome *text* with a [link](/x) and [another](/y)\n\n### Deeper\n\n```\ncode\n```";
+        let (root, meta) = parse_document_with_meta(input)
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert_eq!(meta, DocumentMeta::of(&root));
+        assert_eq!(root.kind(), NodeKind::Document);
+        assert_eq!(NodeKind::CodeBlock.name(), "CodeBlock");
+        assert_eq!(meta.count(NodeKind::Document), 1);
+        assert_eq!(meta.count(NodeKind::Heading), 2);
+        assert_eq!(meta.count(NodeKind::Paragraph), 1);
+        assert_eq!(meta.count(NodeKind::Emphasis), 1);
+        assert_eq!(meta.count(NodeKind::Link), 2);
+        assert_eq!(meta.count(NodeKind::Image), 0);
+        assert!(meta.has_code_blocks());
+        assert!(meta.has_links());
+        assert!(!meta.has_images());
+        assert_eq!(meta.max_heading_level(), Some(3));
+
+        let (_, meta) = parse_document_with_meta("Just text")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert_eq!(meta.max_heading_level(), None);
+        assert!(!meta.has_code_blocks());
+    }
+}
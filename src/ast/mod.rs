@@ -5,11 +5,31 @@ use pest::Parser;
 
 use self::model::Node;
 
+mod include;
+mod incremental;
+mod lenient;
 mod macros;
+mod meta;
+mod options;
+mod parsed;
+mod transform;
 
+#[cfg(any(test, feature = "builder"))]
+pub mod builder;
+pub mod lint;
 pub mod model;
+#[cfg(feature = "schema")]
+pub mod schema;
 pub mod traversal;
 
+pub use include::{resolve_includes, IncludeResolver};
+pub use incremental::reparse_document;
+pub use lenient::parse_document_lenient;
+pub use meta::{parse_document_with_meta, DocumentMeta};
+pub use options::{parse_document_with_options, ParseOptions};
+pub use parsed::ParsedDocument;
+pub use transform::{transform_inline, EmojiShortcodes, InlineTransform};
+
 /// Generate an abstract syntax tree (AST) for the markdown document. Since the AST nodes
 /// store segments of the slice in their leaves, the lifetime of the AST is tied to that of
 /// the input.
@@ -32,7 +52,9 @@ pub mod test {
     use crate::test_utils::read_file_to_string;
     use pretty_assertions::assert_eq;
 
+    // The fixtures are externally tagged, so they only match without `serde_tagged`
     #[test]
+    #[cfg(not(feature = "serde_tagged"))]
     pub fn markup_test() {
         let input = read_file_to_string("markdown/markup.md");
         let root =
@@ -44,6 +66,7 @@ pub mod test {
     }
 
     #[test]
+    #[cfg(not(feature = "serde_tagged"))]
     pub fn list_test() {
         let input = read_file_to_string("markdown/lists.md");
         let document =
@@ -55,6 +78,7 @@ pub mod test {
     }
 
     #[test]
+    #[cfg(not(feature = "serde_tagged"))]
     pub fn blocks_test() {
         let input = read_file_to_string("markdown/blocks.md");
         let document =
@@ -65,4 +89,142 @@ pub mod test {
 
         assert_eq!(&actual, &expected);
     }
+
+    #[test]
+    #[cfg(not(feature = "serde_tagged"))]
+    pub fn subscript_superscript_test() {
+        let document = parse_document("H~2~O e = mc^2^")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let actual = serde_json::to_value(&document)
+            .unwrap_or_else(|e| panic!("Failed to serialize AST: {e}"));
+        let expected = serde_json::json!({
+            "Document": {
+                "children": [
+                    {
+                        "Paragraph": {
+                            "children": [
+                                { "Text": { "literal": "H" } },
+                                { "Subscript": { "children": [{ "Text": { "literal": "2" } }] } },
+                                { "Text": { "literal": "O e = mc" } },
+                                { "Superscript": { "children": [{ "Text": { "literal": "2" } }] } }
+                            ]
+                        }
+                    }
+                ]
+            }
+        });
+        assert_eq!(&actual, &expected);
+    }
+
+    #[test]
+    #[cfg(not(feature = "serde_tagged"))]
+    pub fn highlight_test() {
+        let document = parse_document("An ==important== x = y `a==b==`")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let actual = serde_json::to_value(&document)
+            .unwrap_or_else(|e| panic!("Failed to serialize AST: {e}"));
+        let expected = serde_json::json!({
+            "Document": {
+                "children": [
+                    {
+                        "Paragraph": {
+                            "children": [
+                                { "Text": { "literal": "An " } },
+                                { "Highlight": { "children": [{ "Text": { "literal": "important" } }] } },
+                                { "Text": { "literal": " x = y " } },
+                                { "Code": { "children": [{ "Text": { "literal": "a==b==" } }] } }
+                            ]
+                        }
+                    }
+                ]
+            }
+        });
+        assert_eq!(&actual, &expected);
+    }
+
+    #[test]
+    #[cfg(not(feature = "serde_tagged"))]
+    pub fn mixed_nested_list_test() {
+        let document = parse_document("- a\n  1. b\n     - c\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let actual = serde_json::to_value(&document)
+            .unwrap_or_else(|e| panic!("Failed to serialize AST: {e}"));
+        let item = |text: &str, sublist: Option<serde_json::Value>| {
+            let mut children = vec![serde_json::json!({ "Text": { "literal": text } })];
+            children.extend(sublist);
+            serde_json::json!({ "ListItem": { "children": children, "index": 1, "checked": null } })
+        };
+        let list = |ordered: bool, item: serde_json::Value| {
+            serde_json::json!({
+                "List": { "children": [item], "tight": true, "ordered": ordered, "start": 1 }
+            })
+        };
+        let expected = serde_json::json!({
+            "Document": {
+                "children": [
+                    list(false, item("a", Some(list(true, item("b", Some(list(false, item("c", None))))))))
+                ]
+            }
+        });
+        assert_eq!(&actual, &expected);
+    }
+
+    #[test]
+    pub fn text_round_trip_test() {
+        use model::Text;
+
+        for text in [Text::new("borrowed"), Text::new(String::from("owned"))] {
+            let node = Node::Text(text);
+            let json = serde_json::to_string(&node)
+                .unwrap_or_else(|e| panic!("Failed to serialize AST: {e}"));
+            let round_tripped: Node = serde_json::from_str(&json)
+                .unwrap_or_else(|e| panic!("Failed to deserialize AST: {e}"));
+            assert_eq!(round_tripped, node);
+        }
+    }
+
+    #[test]
+    pub fn no_end_of_input_in_json_test() {
+        // The end of input marker is a parser artifact, so it never becomes a node
+        for file in [
+            "markdown/markup.md",
+            "markdown/lists.md",
+            "markdown/blocks.md",
+        ] {
+            let input = read_file_to_string(file);
+            let root =
+                parse_document(&input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            let json = serde_json::to_string(&root)
+                .unwrap_or_else(|e| panic!("Failed to serialize AST: {e}"));
+            assert!(!json.contains("EOI"), "Found EOI in the AST for {file}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde_tagged")]
+    pub fn tagged_blocks_test() {
+        let input = read_file_to_string("markdown/blocks.md");
+        let document =
+            parse_document(&input).unwrap_or_else(|e| panic!("Failed to parse document: {e:?}"));
+        let actual = serde_json::to_string_pretty(&document)
+            .unwrap_or_else(|e| panic!("Failed to serialize AST: {e}"));
+        let expected = read_file_to_string("ast_json/tagged/blocks.json");
+
+        assert_eq!(&actual, &expected);
+    }
+
+    #[test]
+    #[cfg(feature = "serde_tagged")]
+    pub fn tagged_round_trip_test() {
+        let root = parse_document("## Title\n\nSome *text* and `code`\n\n- [x] done\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let json =
+            serde_json::to_string(&root).unwrap_or_else(|e| panic!("Failed to serialize AST: {e}"));
+        assert!(json.starts_with(r#"{"type":"Document","children":[{"type":"Heading","children""#));
+        assert!(json.contains(r#""level":2"#));
+
+        let round_tripped: Node = serde_json::from_str(&json)
+            .unwrap_or_else(|e| panic!("Failed to deserialize AST: {e}"));
+        assert!(round_tripped.structurally_eq(&root));
+    }
 }
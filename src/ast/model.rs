@@ -1,15 +1,19 @@
 use pest::iterators::{Pair, Pairs};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
 
+use super::incremental::{Rebase, Rebaser};
 use crate::{container_type, error::ParseError, first_child, leaf_type, parser::Rule};
 
 /// A newtype wrapper over a Vec<Node>, largely so that we can implement conversion traits
 /// between a [`Pair`] and a Vec. This type implements [`std::ops::Deref`] to its wrapped
 /// vector to improve developer ergonomics.
-#[derive(PartialEq)]
+#[derive(Default, Clone, PartialEq)]
 #[cfg_attr(
     feature = "serde_support",
     derive(serde::Serialize, serde::Deserialize)
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Children<'input>(
     #[cfg_attr(feature = "serde_support", serde(borrow))] Vec<Node<'input>>,
 );
@@ -24,6 +28,12 @@ impl<'input> TryFrom<Pair<'input, Rule>> for Children<'input> {
     }
 }
 
+impl<'input> From<Vec<Node<'input>>> for Children<'input> {
+    fn from(value: Vec<Node<'input>>) -> Self {
+        Self(value)
+    }
+}
+
 impl<'input> std::ops::Deref for Children<'input> {
     type Target = Vec<Node<'input>>;
 
@@ -61,14 +71,30 @@ impl<'input> Children<'input> {
         let mut children = Vec::new();
 
         for child in pairs {
+            // The end of input marker is a parser artifact rather than part of the document
+            if child.as_rule() == Rule::EOI {
+                continue;
+            }
+            // Attribute lists are stored on the node that they belong to rather than as a child
+            if child.as_rule() == Rule::attribute_list {
+                continue;
+            }
+
             let child_start = child.as_span().start();
             let child_end = child.as_span().end();
 
             if child.as_rule().is_plaintext() {
                 // If the child's start is after the running segment's end, then
                 // the child is the start of a new run so we need to update the
-                // start pos of the running segment.
+                // start pos of the running segment. Silent tokens in the grammar
+                // can cause gaps between plaintext runs, so flush the previous
+                // run first if there was one.
                 if child_start > running_segment_end {
+                    if running_segment_start != running_segment_end {
+                        let start_index = running_segment_start - start_index;
+                        let end_index = start_index + (running_segment_end - running_segment_start);
+                        children.push(Node::Text(Text::new(&span[start_index..end_index])));
+                    }
                     running_segment_start = child_start;
                 }
                 // Always update the end since we always want to have the running
@@ -88,9 +114,7 @@ impl<'input> Children<'input> {
                 // Convert from absolute position in the input to the absolute position within the span
                 let start_index = running_segment_start - start_index;
                 let end_index = start_index + (running_segment_end - running_segment_start);
-                children.push(Node::Text(Text {
-                    literal: &span[start_index..end_index],
-                }));
+                children.push(Node::Text(Text::new(&span[start_index..end_index])));
             }
 
             // Now, we can push the non-plaintext node that came after the stretch of plaintext.
@@ -106,13 +130,47 @@ impl<'input> Children<'input> {
             // Convert from absolute position in the input to the absolute position within the span
             let start_index = running_segment_start - start_index;
             let end_index = start_index + (running_segment_end - running_segment_start);
-            children.push(Node::Text(Text {
-                literal: &span[start_index..end_index],
-            }));
+            children.push(Node::Text(Text::new(&span[start_index..end_index])));
         }
 
         Ok(Children(children))
     }
+
+    /// The first child that isn't a [`Node::Text`] of only whitespace, for questions about how
+    /// content starts, like whether a paragraph starts with an image.
+    pub fn first_non_text(&self) -> Option<&Node<'input>> {
+        self.iter().find(|child| !is_whitespace_text(child))
+    }
+
+    /// The last child that isn't a [`Node::Text`] of only whitespace, like
+    /// [`Children::first_non_text`] from the other end.
+    pub fn last_non_text(&self) -> Option<&Node<'input>> {
+        self.iter().rev().find(|child| !is_whitespace_text(child))
+    }
+
+    /// Merge each run of adjacent [`Node::Text`] children into a single text node, like after
+    /// replacing emphasis with its text. The merged text owns its literal, since the pieces don't
+    /// have to be next to each other in the input. The children of other nodes aren't changed.
+    pub fn coalesce_text(&mut self) {
+        let mut coalesced: Vec<Node<'input>> = Vec::with_capacity(self.len());
+        for child in std::mem::take(&mut self.0) {
+            match (coalesced.last_mut(), child) {
+                (Some(Node::Text(last)), Node::Text(text)) => {
+                    let mut literal = std::mem::replace(last, Text::new(""))
+                        .into_literal()
+                        .into_owned();
+                    literal.push_str(text.as_span());
+                    *last = Text::new(literal);
+                }
+                (_, child) => coalesced.push(child),
+            }
+        }
+        self.0 = coalesced;
+    }
+}
+
+fn is_whitespace_text(node: &Node) -> bool {
+    matches!(node, Node::Text(text) if text.as_span().trim().is_empty())
 }
 
 // Create all the different AST node types. See the macros.rs file for how they're defined
@@ -121,19 +179,45 @@ container_type!(Document);
 container_type!(Paragraph);
 container_type!(BlockQuote);
 container_type!(List, (tight, bool), (ordered, bool), (start, u32));
-container_type!(ListItem, (index, u32));
+// Task items like `- [x] done` are `Some` with whether they're checked, and other items are `None`
+container_type!(ListItem, (index, u32), (checked, Option<bool>));
 container_type!(Emphasis);
 container_type!(Strong);
+container_type!(Subscript);
+container_type!(Superscript);
+container_type!(Highlight);
 container_type!(Label);
 container_type!(Code);
+container_type!(FootnoteDefinition, (label, &'input str));
+container_type!(LineBlock);
+container_type!(Line, (indent, u32));
 container_type!(CodeBlock, (language, Option<&'input str>));
-container_type!(Heading, (level, u8));
-container_type!(Link, (source, &'input str), (title, Option<&'input str>));
-leaf_type!(Text);
+container_type!(Heading, (level, u8); (attributes, Attributes<'input>));
+container_type!(
+    Link,
+    (source, &'input str),
+    (title, Option<&'input str>),
+    (is_reference, bool)
+);
+leaf_type!(cow Text);
 leaf_type!(Linebreak);
 leaf_type!(SoftLinebreak);
-leaf_type!(Image, (source, &'input str), (title, Option<&'input str>));
+// Images are spanned by their description, and `markdown` is the whole image as it was written.
+// The `alt_text` is the description's plain text, with any inline markup like `**` stripped out,
+// which is what belongs in an `alt` attribute since the description is shown in place of the image.
+leaf_type!(
+    Image,
+    (source, &'input str),
+    (title, Option<&'input str>),
+    (is_reference, bool),
+    (markdown, &'input str);
+    (alt_text, std::borrow::Cow<'input, str>)
+);
 leaf_type!(ThematicBreak);
+leaf_type!(InlineMath, (expression, &'input str));
+leaf_type!(FootnoteReference, (label, &'input str));
+leaf_type!(DisplayMath, (expression, &'input str));
+leaf_type!(Include, (name, &'input str));
 leaf_type!(
     Reference,
     (name, &'input str),
@@ -141,17 +225,57 @@ leaf_type!(
     (title, Option<&'input str>)
 );
 
-/// for that type, except for EOI since EOI contains nothing by definition.
+/// A top-level block that [`parse_document_lenient`](super::parse_document_lenient) couldn't
+/// parse, kept as its raw source along with why it failed so that the rest of the document can
+/// still be used. The error is stored as its message, since a [`ParseError`] can't be cloned.
+#[derive(std::fmt::Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde_support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ErrorBlock<'input> {
+    pub(super) literal: &'input str,
+    error: String,
+}
+
+impl<'input> AsRef<str> for ErrorBlock<'input> {
+    fn as_ref(&self) -> &str {
+        self.literal
+    }
+}
+
+impl<'input> ErrorBlock<'input> {
+    pub fn new(literal: &'input str, error: &ParseError) -> Self {
+        Self {
+            literal,
+            error: error.to_string(),
+        }
+    }
+
+    pub fn as_span(&self) -> &'input str {
+        self.literal
+    }
+
+    /// The message of the error that the block failed with.
+    pub fn error(&self) -> &str {
+        &self.error
+    }
+}
+
+/// A node in the AST, where each variant wraps the struct for that type of node.
 ///
 /// ### Lifetime Parameters
 ///
 /// * `'input` - The lifetime is constrained to the lifetime of the input to the parser
-///              since leaf nodes like Text contain a string slice from the original input.
-#[derive(Debug, PartialEq)]
+///   since leaf nodes like Text usually contain a string slice from the original input.
+#[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(
     feature = "serde_support",
     derive(serde::Deserialize, serde::Serialize)
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde_tagged", serde(tag = "type"))]
 pub enum Node<'input> {
     // Containers
     #[cfg_attr(feature = "serde_support", serde(borrow))]
@@ -164,32 +288,124 @@ pub enum Node<'input> {
     CodeBlock(CodeBlock<'input>),
     Emphasis(Emphasis<'input>),
     Strong(Strong<'input>),
+    Subscript(Subscript<'input>),
+    Superscript(Superscript<'input>),
+    /// Text between double equals signs, like `==important==`.
+    Highlight(Highlight<'input>),
+    /// The bracketed text of a link. The parser folds labels into the [`Link`] that they belong
+    /// to, so a standalone label only shows up in trees that were built by hand.
     Label(Label<'input>),
     Link(Link<'input>),
     // Leaves
     Image(Image<'input>),
     Text(Text<'input>),
+    /// A hard line break, written as two or more spaces or a backslash at the end of a line.
+    /// Its literal includes the newline.
     Linebreak(Linebreak<'input>),
+    /// The end of a line that continues the same paragraph, which renders as a space or a newline.
     SoftLinebreak(SoftLinebreak<'input>),
     Code(Code<'input>),
     ThematicBreak(ThematicBreak<'input>),
     Reference(Reference<'input>),
-    // End of input
-    EOI,
+    /// Math between single dollar signs, like `$x^2$`. The expression is kept as it was written.
+    InlineMath(InlineMath<'input>),
+    /// Math between double dollar signs that's set as a block of its own.
+    DisplayMath(DisplayMath<'input>),
+    /// A block that couldn't be parsed, which only appears in lenient parses.
+    ErrorBlock(ErrorBlock<'input>),
+    /// A use of a footnote, like `[^1]`.
+    FootnoteReference(FootnoteReference<'input>),
+    /// The text of a footnote, like `[^1]: Some text`. Renderers usually collect these at the end
+    /// of the document rather than where they were written.
+    FootnoteDefinition(FootnoteDefinition<'input>),
+    /// A directive like `{{include: intro.md}}` that stands in for the blocks of another document
+    /// until it's replaced by [`resolve_includes`](super::resolve_includes).
+    Include(Include<'input>),
+    /// A block of lines that each start with a `|`, like an address or a poem, where the breaks
+    /// between the lines are kept.
+    LineBlock(LineBlock<'input>),
+    /// One line of a [`Node::LineBlock`], along with how many spaces it was indented by.
+    Line(Line<'input>),
 }
 
+macro_rules! node_kinds {
+    ($($variant: ident),+) => {
+        /// Which variant of [`Node`] a node is, without the node itself, for things like counting
+        /// the nodes of each kind in [`DocumentMeta`](super::DocumentMeta).
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub enum NodeKind {
+            $($variant,)+
+        }
+
+        impl NodeKind {
+            /// The name of the [`Node`] variant, like `"Heading"` for [`NodeKind::Heading`].
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Self::$variant => stringify!($variant),)+
+                }
+            }
+        }
+
+        impl<'input> Node<'input> {
+            pub fn kind(&self) -> NodeKind {
+                match self {
+                    $(Self::$variant(_) => NodeKind::$variant,)+
+                }
+            }
+        }
+    };
+}
+
+node_kinds!(
+    Document,
+    Paragraph,
+    BlockQuote,
+    Heading,
+    List,
+    ListItem,
+    CodeBlock,
+    Emphasis,
+    Strong,
+    Subscript,
+    Superscript,
+    Highlight,
+    Label,
+    Link,
+    Image,
+    Text,
+    Linebreak,
+    SoftLinebreak,
+    Code,
+    ThematicBreak,
+    Reference,
+    InlineMath,
+    DisplayMath,
+    ErrorBlock,
+    FootnoteReference,
+    FootnoteDefinition,
+    Include,
+    LineBlock,
+    Line
+);
+
 impl<'input> Node<'input> {
-    pub fn children(&self) -> Option<&Children> {
+    pub fn children(&self) -> Option<&Children<'input>> {
         match self {
             Self::Document(c) => Some(c.children()),
             Self::Paragraph(p) => Some(p.children()),
             Self::BlockQuote(bq) => Some(bq.children()),
             Self::Heading(h) => Some(h.children()),
+            Self::FootnoteDefinition(f) => Some(f.children()),
             Self::List(l) => Some(l.children()),
             Self::ListItem(li) => Some(li.children()),
+            Self::LineBlock(lb) => Some(lb.children()),
+            Self::Line(l) => Some(l.children()),
             Self::CodeBlock(cb) => Some(cb.children()),
             Self::Emphasis(emp) => Some(emp.children()),
             Self::Strong(strong) => Some(strong.children()),
+            Self::Subscript(sub) => Some(sub.children()),
+            Self::Superscript(sup) => Some(sup.children()),
+            Self::Highlight(highlight) => Some(highlight.children()),
             Self::Label(l) => Some(l.children()),
             Self::Link(l) => Some(l.children()),
             Self::Code(c) => Some(c.children()),
@@ -199,21 +415,31 @@ impl<'input> Node<'input> {
             Self::SoftLinebreak(_) => None,
             Self::ThematicBreak(_) => None,
             Self::Reference(_) => None,
-            Self::EOI => None,
+            Self::InlineMath(_) => None,
+            Self::DisplayMath(_) => None,
+            Self::ErrorBlock(_) => None,
+            Self::FootnoteReference(_) => None,
+            Self::Include(_) => None,
         }
     }
 
-    pub fn children_mut(&'input mut self) -> Option<&mut Children> {
+    pub fn children_mut(&mut self) -> Option<&mut Children<'input>> {
         match self {
             Self::Document(c) => Some(c.children_mut()),
             Self::Paragraph(p) => Some(p.children_mut()),
             Self::BlockQuote(bq) => Some(bq.children_mut()),
             Self::Heading(h) => Some(h.children_mut()),
+            Self::FootnoteDefinition(f) => Some(f.children_mut()),
             Self::List(l) => Some(l.children_mut()),
             Self::ListItem(li) => Some(li.children_mut()),
+            Self::LineBlock(lb) => Some(lb.children_mut()),
+            Self::Line(l) => Some(l.children_mut()),
             Self::CodeBlock(cb) => Some(cb.children_mut()),
             Self::Emphasis(emp) => Some(emp.children_mut()),
             Self::Strong(strong) => Some(strong.children_mut()),
+            Self::Subscript(sub) => Some(sub.children_mut()),
+            Self::Superscript(sup) => Some(sup.children_mut()),
+            Self::Highlight(highlight) => Some(highlight.children_mut()),
             Self::Label(l) => Some(l.children_mut()),
             Self::Link(l) => Some(l.children_mut()),
             Self::Code(c) => Some(c.children_mut()),
@@ -223,10 +449,200 @@ impl<'input> Node<'input> {
             Self::SoftLinebreak(_) => None,
             Self::ThematicBreak(_) => None,
             Self::Reference(_) => None,
-            Self::EOI => None,
+            Self::InlineMath(_) => None,
+            Self::DisplayMath(_) => None,
+            Self::ErrorBlock(_) => None,
+            Self::FootnoteReference(_) => None,
+            Self::Include(_) => None,
         }
     }
 
+    /// Consume the node and take ownership of its children, or `None` if it's a leaf. Useful for
+    /// restructuring the tree, like moving a node's children under a different container.
+    pub fn into_children(self) -> Option<Children<'input>> {
+        match self {
+            Self::Document(c) => Some(c.into_children()),
+            Self::Paragraph(p) => Some(p.into_children()),
+            Self::BlockQuote(bq) => Some(bq.into_children()),
+            Self::Heading(h) => Some(h.into_children()),
+            Self::FootnoteDefinition(f) => Some(f.into_children()),
+            Self::List(l) => Some(l.into_children()),
+            Self::ListItem(li) => Some(li.into_children()),
+            Self::LineBlock(lb) => Some(lb.into_children()),
+            Self::Line(l) => Some(l.into_children()),
+            Self::CodeBlock(cb) => Some(cb.into_children()),
+            Self::Emphasis(emp) => Some(emp.into_children()),
+            Self::Strong(strong) => Some(strong.into_children()),
+            Self::Subscript(sub) => Some(sub.into_children()),
+            Self::Superscript(sup) => Some(sup.into_children()),
+            Self::Highlight(highlight) => Some(highlight.into_children()),
+            Self::Label(l) => Some(l.into_children()),
+            Self::Link(l) => Some(l.into_children()),
+            Self::Code(c) => Some(c.into_children()),
+            Self::Image(_) => None,
+            Self::Text(_) => None,
+            Self::Linebreak(_) => None,
+            Self::SoftLinebreak(_) => None,
+            Self::ThematicBreak(_) => None,
+            Self::Reference(_) => None,
+            Self::InlineMath(_) => None,
+            Self::DisplayMath(_) => None,
+            Self::ErrorBlock(_) => None,
+            Self::FootnoteReference(_) => None,
+            Self::Include(_) => None,
+        }
+    }
+
+    /// Whether this is an ordered list, or `None` if the node isn't a list.
+    pub fn is_ordered(&self) -> Option<bool> {
+        match self {
+            Self::List(l) => Some(l.ordered()),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a tight list, or `None` if the node isn't a list.
+    pub fn is_tight(&self) -> Option<bool> {
+        match self {
+            Self::List(l) => Some(l.tight()),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a block, which takes up whole lines of the document, like a paragraph or a
+    /// list. Every node is either a block or [inline](Node::is_inline). Reference definitions are
+    /// blocks, even though they aren't rendered.
+    pub fn is_block(&self) -> bool {
+        match self {
+            Self::Document(_)
+            | Self::Paragraph(_)
+            | Self::BlockQuote(_)
+            | Self::Heading(_)
+            | Self::List(_)
+            | Self::ListItem(_)
+            | Self::LineBlock(_)
+            | Self::Line(_)
+            | Self::CodeBlock(_)
+            | Self::ThematicBreak(_)
+            | Self::Reference(_)
+            | Self::DisplayMath(_)
+            | Self::ErrorBlock(_)
+            | Self::Include(_)
+            | Self::FootnoteDefinition(_) => true,
+            Self::Emphasis(_)
+            | Self::Strong(_)
+            | Self::Subscript(_)
+            | Self::Superscript(_)
+            | Self::Highlight(_)
+            | Self::Label(_)
+            | Self::Link(_)
+            | Self::Code(_)
+            | Self::Image(_)
+            | Self::Text(_)
+            | Self::Linebreak(_)
+            | Self::SoftLinebreak(_)
+            | Self::InlineMath(_)
+            | Self::FootnoteReference(_) => false,
+        }
+    }
+
+    /// Whether this is inline content that's part of a block's text, like emphasis or a link.
+    pub fn is_inline(&self) -> bool {
+        !self.is_block()
+    }
+
+    /// Compare two trees by their structure and content alone, ignoring the spans of the input
+    /// that they were parsed from. Trees parsed from documents that only differ in things like
+    /// surrounding blank lines are structurally equal, even though they aren't `==`. Line breaks
+    /// and thematic breaks are compared by their type and not by how they were written.
+    pub fn structurally_eq(&self, other: &Node<'_>) -> bool {
+        let same_node = match (self, other) {
+            (Self::Heading(a), Node::Heading(b)) => {
+                a.level() == b.level() && a.attributes() == b.attributes()
+            }
+            (Self::List(a), Node::List(b)) => {
+                a.tight() == b.tight() && a.ordered() == b.ordered() && a.start() == b.start()
+            }
+            (Self::ListItem(a), Node::ListItem(b)) => {
+                a.index() == b.index() && a.checked() == b.checked()
+            }
+            (Self::Line(a), Node::Line(b)) => a.indent() == b.indent(),
+            (Self::CodeBlock(a), Node::CodeBlock(b)) => a.language() == b.language(),
+            (Self::Link(a), Node::Link(b)) => {
+                a.source() == b.source()
+                    && a.title() == b.title()
+                    && a.is_reference() == b.is_reference()
+            }
+            (Self::Text(a), Node::Text(b)) => a.as_span() == b.as_span(),
+            (Self::Image(a), Node::Image(b)) => {
+                a.as_span() == b.as_span()
+                    && a.source() == b.source()
+                    && a.title() == b.title()
+                    && a.is_reference() == b.is_reference()
+            }
+            (Self::Reference(a), Node::Reference(b)) => {
+                a.name() == b.name() && a.source() == b.source() && a.title() == b.title()
+            }
+            (Self::InlineMath(a), Node::InlineMath(b)) => a.expression() == b.expression(),
+            (Self::FootnoteReference(a), Node::FootnoteReference(b)) => a.label() == b.label(),
+            (Self::FootnoteDefinition(a), Node::FootnoteDefinition(b)) => a.label() == b.label(),
+            (Self::DisplayMath(a), Node::DisplayMath(b)) => a.expression() == b.expression(),
+            (Self::Include(a), Node::Include(b)) => a.name() == b.name(),
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        };
+        same_node
+            && match (self.children(), other.children()) {
+                (Some(a), Some(b)) => {
+                    a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.structurally_eq(b))
+                }
+                (None, None) => true,
+                _ => false,
+            }
+    }
+
+    /// A hash of the tree's structure and content that ignores spans like
+    /// [`Node::structurally_eq`] does, so that trees that are structurally equal have the same
+    /// hash. Useful as a key for caching the rendered output of content that's repeated across
+    /// documents. The hash is only stable within a single build of the program, so it shouldn't be
+    /// persisted.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.hash_structure(&mut hasher);
+        hasher.finish()
+    }
+
+    fn hash_structure<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Self::Heading(h) => (h.level(), h.attributes()).hash(state),
+            Self::List(l) => (l.tight(), l.ordered(), l.start()).hash(state),
+            Self::ListItem(li) => (li.index(), li.checked()).hash(state),
+            Self::Line(l) => l.indent().hash(state),
+            Self::CodeBlock(cb) => cb.language().hash(state),
+            Self::Link(l) => (l.source(), l.title(), l.is_reference()).hash(state),
+            Self::Text(t) => t.as_span().hash(state),
+            Self::Image(i) => (i.as_span(), i.source(), i.title(), i.is_reference()).hash(state),
+            Self::Reference(r) => (r.name(), r.source(), r.title()).hash(state),
+            Self::InlineMath(m) => m.expression().hash(state),
+            Self::DisplayMath(m) => m.expression().hash(state),
+            Self::FootnoteReference(f) => f.label().hash(state),
+            Self::FootnoteDefinition(f) => f.label().hash(state),
+            Self::Include(i) => i.name().hash(state),
+            _ => {}
+        }
+        if let Some(children) = self.children() {
+            children.len().hash(state);
+            for child in children.iter() {
+                child.hash_structure(state);
+            }
+        }
+    }
+
+    /// The slice of the input that the node was parsed from. For nodes with children, that's all
+    /// of their source, including delimiters like the `*`s around emphasis or a heading's `#`s,
+    /// and blocks also include any blank lines that follow them. Leaves are their literal, which
+    /// is the text itself for [`Node::Text`] and just the description for [`Node::Image`]. See
+    /// [`Node::outer_source`] and [`Node::inner_source`] for slices with a stricter meaning.
     pub fn as_span(&self) -> &str {
         match self {
             Self::Document(c) => c.as_span(),
@@ -235,9 +651,14 @@ impl<'input> Node<'input> {
             Self::Heading(h) => h.as_span(),
             Self::List(l) => l.as_span(),
             Self::ListItem(li) => li.as_span(),
+            Self::LineBlock(lb) => lb.as_span(),
+            Self::Line(l) => l.as_span(),
             Self::CodeBlock(cb) => cb.as_span(),
             Self::Emphasis(emp) => emp.as_span(),
             Self::Strong(strong) => strong.as_span(),
+            Self::Subscript(sub) => sub.as_span(),
+            Self::Superscript(sup) => sup.as_span(),
+            Self::Highlight(highlight) => highlight.as_span(),
             Self::Label(l) => l.as_span(),
             Self::Link(l) => l.as_span(),
             Self::Code(c) => c.as_span(),
@@ -247,11 +668,55 @@ impl<'input> Node<'input> {
             Self::SoftLinebreak(slb) => slb.as_span(),
             Self::ThematicBreak(tb) => tb.as_span(),
             Self::Reference(r) => r.as_span(),
-            Self::EOI => "EOI",
+            Self::InlineMath(m) => m.as_span(),
+            Self::DisplayMath(m) => m.as_span(),
+            Self::ErrorBlock(e) => e.as_span(),
+            Self::FootnoteReference(f) => f.as_span(),
+            Self::Include(i) => i.as_span(),
+            Self::FootnoteDefinition(f) => f.as_span(),
         }
     }
 }
 
+impl<'input> Node<'input> {
+    /// The node's full source, including its delimiters, like all of `*x*` for emphasis. Blocks
+    /// don't include the whitespace after them. Images only keep their description and text
+    /// can be synthesized, so this is `None` for images and text that isn't a slice of the input.
+    pub fn outer_source(&self) -> Option<&str> {
+        match self {
+            Self::Image(_) => None,
+            Self::Text(text) => text.as_borrowed(),
+            node if node.is_block() => Some(node.as_span().trim_end()),
+            node => Some(node.as_span()),
+        }
+    }
+
+    /// The source between the node's delimiters, like just `x` for `*x*`. For nodes with
+    /// children, that's from the start of the first child to the end of the last one, or `None`
+    /// if either end can't be found, like in a tree built by hand. Math is its expression, and
+    /// other leaves are their [outer source](Node::outer_source).
+    pub fn inner_source(&self) -> Option<&str> {
+        let children = match self {
+            Self::InlineMath(math) => return Some(math.expression()),
+            Self::DisplayMath(math) => return Some(math.expression()),
+            node => match node.children() {
+                Some(children) => children,
+                None => return node.outer_source(),
+            },
+        };
+        let (first, last) = match (children.first(), children.last()) {
+            (Some(first), Some(last)) => (first.outer_source()?, last.outer_source()?),
+            _ => return Some(""),
+        };
+
+        let span = self.as_span();
+        let base = span.as_ptr() as usize;
+        let start = (first.as_ptr() as usize).checked_sub(base)?;
+        let end = (last.as_ptr() as usize + last.len()).checked_sub(base)?;
+        (start <= end && end <= span.len()).then(|| &span[start..end])
+    }
+}
+
 impl<'input> TryFrom<Pair<'input, Rule>> for Node<'input> {
     type Error = ParseError;
 
@@ -281,30 +746,44 @@ impl<'input> TryFrom<Pair<'input, Rule>> for Node<'input> {
             }
             Rule::emphasis => Ok(Node::Emphasis(Emphasis::try_from(value)?)),
             Rule::strong => Ok(Node::Strong(Strong::try_from(value)?)),
+            Rule::subscript => Ok(Node::Subscript(Subscript::try_from(value)?)),
+            Rule::superscript => Ok(Node::Superscript(Superscript::try_from(value)?)),
+            Rule::highlight => Ok(Node::Highlight(Highlight::try_from(value)?)),
             Rule::label => Ok(Node::Label(Label::try_from(value)?)),
             Rule::link => Ok(Node::Link(Link::try_from(first_child!(
                 value.into_inner()
             )?)?)),
-            Rule::image => Ok(Node::Image(Image::try_from(first_child!(
-                value.into_inner()
-            )?)?)),
+            Rule::image => Ok(Node::Image(Image::try_from(value)?)),
             Rule::code => Ok(Node::Code(Code::try_from(value)?)),
-            // Leaf nodes
+            // Leaf nodes. Newlines inside of code are matched as `linebreak_literal` and kept as
+            // part of the code's text, since code never contains breaks.
             Rule::str
             | Rule::space
             | Rule::symbol
             | Rule::control_character
             | Rule::source
             | Rule::linebreak_literal => Ok(Node::Text(Text::from(value))),
-            Rule::linebreak => Ok(Node::Linebreak(Linebreak::from(value))),
-            Rule::normal_endline => Ok(Node::SoftLinebreak(SoftLinebreak::from(value))),
+            Rule::linebreak | Rule::list_linebreak | Rule::blockquote_linebreak => {
+                Ok(Node::Linebreak(Linebreak::from(value)))
+            }
+            Rule::normal_endline | Rule::list_endline | Rule::blockquote_endline => {
+                Ok(Node::SoftLinebreak(SoftLinebreak::from(value)))
+            }
             Rule::thematic_break => Ok(Node::ThematicBreak(ThematicBreak::from(value))),
             Rule::reference => Ok(Node::Reference(Reference::from(value))),
-            // End of input
-            Rule::EOI => Ok(Node::EOI),
+            Rule::inline_math => Ok(Node::InlineMath(InlineMath::from(value))),
+            Rule::display_math => Ok(Node::DisplayMath(DisplayMath::from(value))),
+            Rule::footnote_reference => Ok(Node::FootnoteReference(FootnoteReference::from(value))),
+            Rule::include => Ok(Node::Include(Include::from(value))),
+            Rule::line_block => Ok(Node::LineBlock(LineBlock::try_from(value)?)),
+            Rule::line_block_line => Ok(Node::Line(Line::try_from(value)?)),
+            Rule::footnote_definition => Ok(Node::FootnoteDefinition(
+                FootnoteDefinition::try_from(value)?,
+            )),
             // Error
             ty => Err(ParseError::SyntaxError(format!(
-                r#"Failed to find a node to represent "{pair_as_str}" as a {ty:?}. Error occurred at: {location:?}"#
+                r#"Failed to find a node to represent "{pair_as_str}" as a {}. Error occurred at: {location:?}"#,
+                ty.human_name()
             ))),
         }
     }
@@ -328,7 +807,8 @@ impl<'input> TryFrom<Pair<'input, Rule>> for List<'input> {
             Rule::ordered_list => true,
             ty => {
                 return Err(ParseError::SyntaxError(format!(
-                    r#"Expected a list node for "{span}", but got {ty:?}. Error occurred at {location:?}"#
+                    r#"Expected a list node for "{span}", but got {}. Error occurred at {location:?}"#,
+                    ty.human_name()
                 )))
             }
         };
@@ -339,7 +819,8 @@ impl<'input> TryFrom<Pair<'input, Rule>> for List<'input> {
             Rule::list_loose => false,
             ty => {
                 return Err(ParseError::SyntaxError(format!(
-                    r#"Expected a list node for "{span}", but got {ty:?}. Error occurred at {location:?}"#
+                    r#"Expected a list node for "{span}", but got {}. Error occurred at {location:?}"#,
+                    ty.human_name()
                 )))
             }
         };
@@ -379,28 +860,44 @@ impl<'input> TryFrom<Pair<'input, Rule>> for ListItem<'input> {
         };
 
         let index = match bullet_or_enumerator.as_rule() {
-            Rule::list_index => bullet_or_enumerator.as_str().parse()?,
+            Rule::list_index => {
+                let (line, column) = bullet_or_enumerator.line_col();
+                let digits = bullet_or_enumerator.as_str();
+                digits.parse().map_err(|e| {
+                    ParseError::SyntaxError(format!(
+                        r#"List index "{digits}" at line {line}, column {column} isn't a valid number: {e}"#
+                    ))
+                })?
+            }
             Rule::dash_bullet | Rule::star_bullet | Rule::plus_bullet => 1,
             other => {
                 return Err(ParseError::SyntaxError(format!(
-                    r#"Expected a bullet or list index in {span}, but found {other:?}. Error occurred at {location:?}"#
+                    r#"Expected a bullet or list index in {span}, but found {}. Error occurred at {location:?}"#,
+                    other.human_name()
                 )));
             }
         };
 
+        let checked = match list_item_pairs.peek() {
+            Some(pair) if pair.as_rule() == Rule::task_marker => {
+                let marker = pair.as_str();
+                list_item_pairs.next();
+                Some(!marker.starts_with("[ ]"))
+            }
+            _ => None,
+        };
+
+        // Empty list items, like `-` on a line by itself, are allowed and just have no children
         let children = match list_item_pairs.next() {
             Some(pair) => Children::try_from(pair)?,
-            None => {
-                return Err(ParseError::SyntaxError(format!(
-                    r#"Expected list contents in "{span}", but found none. Error occurred at {location:?}"#
-                )));
-            }
+            None => Children::default(),
         };
 
         Ok(Self {
             children,
             span,
             index,
+            checked,
         })
     }
 }
@@ -424,7 +921,8 @@ impl<'input> TryFrom<Pair<'input, Rule>> for CodeBlock<'input> {
             Rule::fenced_codeblock => value.into_inner(),
             other => {
                 return Err(ParseError::SyntaxError(format!(
-                    r#"Expected a codeblock type in "{span}", but found {other:?}. Error occurred at {location:?}"#
+                    r#"Expected a codeblock type in "{span}", but found {}. Error occurred at {location:?}"#,
+                    other.human_name()
                 )))
             }
         };
@@ -458,16 +956,94 @@ impl<'input> TryFrom<Pair<'input, Rule>> for Heading<'input> {
         )))?;
 
         let level = hashes.as_str().len() as u8;
+        let attributes = children
+            .clone()
+            .find(|pair| pair.as_rule() == Rule::attribute_list)
+            .map(Attributes::from)
+            .unwrap_or_default();
         let children = Children::try_from_pairs(children, span, start_index)?;
 
         Ok(Self {
             children,
             span,
             level,
+            attributes,
         })
     }
 }
 
+/// The id, classes, and other attributes that an author set on a node with an attribute list,
+/// like `## Heading {#custom-id .note lang=en}`. Values can be quoted to include spaces. Headings
+/// without an attribute list have empty attributes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde_support",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Attributes<'input> {
+    pub(super) span: Option<&'input str>,
+    pub(super) id: Option<&'input str>,
+    #[cfg_attr(feature = "serde_support", serde(borrow))]
+    pub(super) classes: Vec<&'input str>,
+    #[cfg_attr(feature = "serde_support", serde(borrow))]
+    pub(super) pairs: Vec<(&'input str, &'input str)>,
+}
+
+impl<'input> From<Pair<'input, Rule>> for Attributes<'input> {
+    fn from(value: Pair<'input, Rule>) -> Self {
+        let mut attributes = Self {
+            span: Some(value.as_str()),
+            ..Self::default()
+        };
+        for pair in value.into_inner() {
+            match pair.as_rule() {
+                // Drop the leading `#` or `.`
+                Rule::attribute_id => attributes.id = Some(&pair.as_str()[1..]),
+                Rule::attribute_class => attributes.classes.push(&pair.as_str()[1..]),
+                Rule::attribute_pair => {
+                    let mut inner = pair.into_inner();
+                    let key = inner.next().map_or("", |key| key.as_str());
+                    let value = inner.next().map_or("", |value| value.as_str());
+                    let value = value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .unwrap_or(value);
+                    attributes.pairs.push((key, value));
+                }
+                _ => {}
+            }
+        }
+        attributes
+    }
+}
+
+impl<'input> Attributes<'input> {
+    pub fn is_empty(&self) -> bool {
+        self.span.is_none()
+    }
+
+    /// The attribute list as it was written, like `{#id .class}`.
+    pub fn as_span(&self) -> Option<&'input str> {
+        self.span
+    }
+
+    /// The id set with `#id`. If there's more than one, the last one wins.
+    pub fn id(&self) -> Option<&'input str> {
+        self.id
+    }
+
+    /// The classes set with `.class`, in the order they were written.
+    pub fn classes(&self) -> impl Iterator<Item = &'input str> + '_ {
+        self.classes.iter().copied()
+    }
+
+    /// The other attributes set with `key=value`, with the quotes removed from quoted values.
+    pub fn pairs(&self) -> impl Iterator<Item = (&'input str, &'input str)> + '_ {
+        self.pairs.iter().copied()
+    }
+}
+
 impl<'input> TryFrom<Pair<'input, Rule>> for Link<'input> {
     type Error = ParseError;
 
@@ -475,6 +1051,11 @@ impl<'input> TryFrom<Pair<'input, Rule>> for Link<'input> {
         let location = value.line_col();
         let span = value.as_str();
 
+        let is_reference = matches!(
+            value.as_rule(),
+            Rule::full_reference_link | Rule::shortcut_reference_link
+        );
+        let is_autolink = value.as_rule() == Rule::autolink;
         let mut inner_nodes = value.into_inner();
 
         // All links have labels. Autolinks are a special case where their label
@@ -499,48 +1080,173 @@ impl<'input> TryFrom<Pair<'input, Rule>> for Link<'input> {
         });
 
         // Now we can do this since we've extracted the source as a str in the case where
-        // the link is an autolink.
-        let children = Children::try_from(label_node)?;
+        // the link is an autolink. Autolinks are atomic, so their text is the whole label.
+        let children = if is_autolink {
+            Children::from(vec![Node::Text(Text::new(label_node.as_str()))])
+        } else {
+            Children::try_from(label_node)?
+        };
 
         Ok(Self {
             children,
             span,
             source,
             title,
+            is_reference,
         })
     }
 }
 
+impl<'input> Link<'input> {
+    /// Whether this is an email autolink like `<a@b.com>`. Its source is the bare address, so it
+    /// needs a `mailto:` scheme to be used as a URL.
+    pub fn is_email_autolink(&self) -> bool {
+        self.span.starts_with('<') && !self.source.contains(':')
+    }
+}
+
 impl<'input> TryFrom<Pair<'input, Rule>> for Image<'input> {
     type Error = ParseError;
 
     fn try_from(value: Pair<'input, Rule>) -> Result<Self, Self::Error> {
+        let markdown = value.as_str();
+        let value = first_child!(value.into_inner())?;
         let location = value.line_col();
         let link_as_str = value.as_str();
 
+        let is_reference = value.as_rule() != Rule::directed_link;
+
         let mut children = value.into_inner();
-        let alt = children
-            .next()
-            .ok_or(ParseError::SyntaxError(format!(
-                r#"No label node found in "{link_as_str}". Error occurred at: {location:?}"#
-            )))?
-            .as_str();
-        let source = children
-            .next()
-            .ok_or(ParseError::SyntaxError(format!(
-                r#"No source found for link in "{link_as_str}". Error occurred at: {location:?}"#
-            )))?
-            .as_str();
-        let title = children.next().map(|node| node.as_str());
+        let label = children.next().ok_or(ParseError::SyntaxError(format!(
+            r#"No label node found in "{link_as_str}". Error occurred at: {location:?}"#
+        )))?;
+        let alt = label.as_str();
+        let alt_text = match Children::try_from(label)?
+            .iter()
+            .flat_map(Node::text_leaves)
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            [only] if *only == alt => std::borrow::Cow::Borrowed(alt),
+            leaves => std::borrow::Cow::Owned(leaves.concat()),
+        };
+        // Shortcut references like `![logo]` use their alt text as the name of the reference
+        let source = match children.next() {
+            Some(node) => node.as_str(),
+            None if is_reference => alt,
+            None => {
+                return Err(ParseError::SyntaxError(format!(
+                    r#"No source found for link in "{link_as_str}". Error occurred at: {location:?}"#
+                )))
+            }
+        };
+        let title = children.next().map(|node| {
+            let title_with_quotes = node.as_str();
+            let total_length = title_with_quotes.len();
+            &title_with_quotes[1..(total_length - 1)]
+        });
 
         Ok(Self {
             literal: alt,
             source,
             title,
+            is_reference,
+            markdown,
+            alt_text,
         })
     }
 }
 
+impl<'input> BlockQuote<'input> {
+    /// Split an attribution like `— Author` off of the end of the quote. It has to be the last
+    /// line of the quote, start with an em dash, and be plain text, so that quotes that just
+    /// happen to contain a dash aren't split.
+    ///
+    /// ### Returns
+    /// The children of the quote before the attribution, without the break before it, along with
+    /// the attribution without its dash, or `None` if the quote doesn't end with one.
+    pub fn citation(&self) -> Option<(&[Node<'input>], &str)> {
+        let (last, rest) = self.children().split_last()?;
+        let author = match last {
+            Node::Text(text) => text.as_span().trim().strip_prefix('\u{2014}')?.trim_start(),
+            _ => return None,
+        };
+        let quote_len = rest
+            .iter()
+            .rposition(|node| !matches!(node, Node::SoftLinebreak(_) | Node::Linebreak(_)))?
+            + 1;
+        if author.is_empty() || quote_len == rest.len() {
+            return None;
+        }
+        Some((&rest[..quote_len], author))
+    }
+}
+
+impl<'input> Paragraph<'input> {
+    /// Split the paragraph's source back into the lines it was written on, using the positions of
+    /// the line breaks inside of it. Line endings are left out of the lines, but anything before
+    /// them is kept, like the trailing spaces or backslash of a hard break.
+    pub fn source_lines(&self) -> impl Iterator<Item = &'input str> {
+        let span = self.as_span();
+        let base = span.as_ptr() as usize;
+        let breaks = self
+            .children()
+            .iter()
+            .flat_map(Node::descendants_with_depth)
+            .filter_map(|(_, node)| match node {
+                Node::SoftLinebreak(linebreak) => Some(linebreak.as_span()),
+                Node::Linebreak(linebreak) => Some(linebreak.as_span()),
+                _ => None,
+            })
+            // Breaks in hand-built trees might not be slices of the paragraph's span
+            .filter_map(|literal| {
+                let offset = (literal.as_ptr() as usize).checked_sub(base)?;
+                (offset + literal.len() <= span.len()).then_some((offset, literal))
+            });
+
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for (offset, literal) in breaks {
+            let content = literal.trim_end_matches(['\r', '\n']).len();
+            lines.push(&span[start..offset + content]);
+            start = offset + literal.len();
+        }
+        if start < span.len() {
+            lines.push(&span[start..]);
+        }
+        lines.into_iter()
+    }
+}
+
+impl<'input> Document<'input> {
+    /// The byte range of the document that each top-level block was parsed from, for editors that
+    /// only want to re-parse and re-render the block under the cursor. Each range runs from the
+    /// start of its block to the start of the next one, so the blank lines between blocks belong
+    /// to the block before them. The first range starts at 0 and the last runs to the end of the
+    /// document, so the ranges cover the whole document without overlapping.
+    ///
+    /// Blocks in hand-built trees that aren't slices of the document's span are taken to start
+    /// where the block before them does, leaving that block with an empty range.
+    pub fn top_level_blocks(&self) -> impl Iterator<Item = (Range<usize>, &Node<'input>)> {
+        let span = self.as_span();
+        let base = span.as_ptr() as usize;
+        let mut starts = Vec::with_capacity(self.children().len() + 1);
+        for (i, block) in self.children().iter().enumerate() {
+            let previous = starts.last().copied().unwrap_or(0);
+            let start = (block.as_span().as_ptr() as usize)
+                .checked_sub(base)
+                .filter(|&start| i > 0 && start <= span.len())
+                .map_or(previous, |start| start.max(previous));
+            starts.push(start);
+        }
+        starts.push(span.len());
+        self.children()
+            .iter()
+            .enumerate()
+            .map(move |(i, block)| (starts[i]..starts[i + 1], block))
+    }
+}
+
 impl<'input> From<Pair<'input, Rule>> for Reference<'input> {
     fn from(value: Pair<'input, Rule>) -> Self {
         let literal = value.as_str();
@@ -562,3 +1268,640 @@ impl<'input> From<Pair<'input, Rule>> for Reference<'input> {
         }
     }
 }
+
+impl<'input> Reference<'input> {
+    /// The name that links use to find this definition. Like in CommonMark, names are matched
+    /// case insensitively and any runs of whitespace in them are treated as a single space.
+    pub fn normalized_name(&self) -> String {
+        normalize_label(self.name)
+    }
+}
+
+/// Normalize a reference name or a link's label so that they can be compared, see
+/// [`Reference::normalized_name`].
+pub(crate) fn normalize_label(name: &str) -> String {
+    name.split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl<'input> From<Pair<'input, Rule>> for FootnoteReference<'input> {
+    fn from(value: Pair<'input, Rule>) -> Self {
+        let literal = value.as_str();
+        let label = value.into_inner().next().map_or("", |node| node.as_str());
+        Self { literal, label }
+    }
+}
+
+impl<'input> From<Pair<'input, Rule>> for Include<'input> {
+    fn from(value: Pair<'input, Rule>) -> Self {
+        let literal = value.as_str();
+        let name = value.into_inner().next().map_or("", |node| node.as_str());
+        Self { literal, name }
+    }
+}
+
+impl<'input> TryFrom<Pair<'input, Rule>> for FootnoteDefinition<'input> {
+    type Error = ParseError;
+
+    fn try_from(value: Pair<'input, Rule>) -> Result<Self, Self::Error> {
+        let location = value.line_col();
+        let start_index = value.as_span().start();
+        let span = value.as_str();
+
+        let mut children = value.into_inner();
+        let label = children
+            .next()
+            .ok_or(ParseError::SyntaxError(format!(
+                r#"No footnote label found in "{span}". Error occurred at: {location:?}"#
+            )))?
+            .as_str();
+        let children = Children::try_from_pairs(children, span, start_index)?;
+
+        Ok(Self {
+            children,
+            span,
+            label,
+        })
+    }
+}
+
+impl<'input> TryFrom<Pair<'input, Rule>> for Line<'input> {
+    type Error = ParseError;
+
+    fn try_from(value: Pair<'input, Rule>) -> Result<Self, Self::Error> {
+        let start_index = value.as_span().start();
+        let span = value.as_str();
+
+        let mut children = value.into_inner();
+        let indent = match children.peek() {
+            Some(pair) if pair.as_rule() == Rule::line_block_indent => {
+                let indent = pair.as_str().len() as u32;
+                children.next();
+                indent
+            }
+            _ => 0,
+        };
+        let children = Children::try_from_pairs(children, span, start_index)?;
+
+        Ok(Self {
+            children,
+            span,
+            indent,
+        })
+    }
+}
+
+impl<'input> From<Pair<'input, Rule>> for InlineMath<'input> {
+    fn from(value: Pair<'input, Rule>) -> Self {
+        let literal = value.as_str();
+        let expression = value.into_inner().next().map_or("", |node| node.as_str());
+        Self {
+            literal,
+            expression,
+        }
+    }
+}
+
+impl<'input> From<Pair<'input, Rule>> for DisplayMath<'input> {
+    fn from(value: Pair<'input, Rule>) -> Self {
+        let literal = value.as_str();
+        let expression = value.into_inner().next().map_or("", |node| node.as_str());
+        Self {
+            literal,
+            expression,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::parse_document;
+
+    #[test]
+    pub fn into_children_test() {
+        let input = "Some *moved* text\n\nAnother paragraph";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let paragraph = root
+            .into_children()
+            .and_then(|mut blocks| blocks.drain(..).next())
+            .unwrap_or_else(|| panic!("Document has no blocks"));
+        let children = paragraph
+            .into_children()
+            .unwrap_or_else(|| panic!("Paragraph has no children"));
+        let block_quote = Node::BlockQuote(BlockQuote::new(children, input));
+
+        let expected = Node::BlockQuote(BlockQuote::new(
+            Children::from(vec![
+                Node::Text(Text::new("Some ")),
+                Node::Emphasis(Emphasis::new(
+                    Children::from(vec![Node::Text(Text::new("moved"))]),
+                    "*moved*",
+                )),
+                Node::Text(Text::new(" text")),
+            ]),
+            input,
+        ));
+        assert_eq!(block_quote, expected);
+        assert!(Node::Text(Text::new("leaf")).into_children().is_none());
+    }
+
+    #[test]
+    pub fn list_properties_test() {
+        let root = parse_document("Some text\n\n1. First\n2. Second\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let blocks = root.children().unwrap();
+        assert_eq!(blocks[0].is_ordered(), None);
+        assert_eq!(blocks[0].is_tight(), None);
+        assert_eq!(blocks[1].is_ordered(), Some(true));
+        assert_eq!(blocks[1].is_tight(), Some(true));
+
+        // The list's last item takes in the text after the blank line, so the paragraph is in it
+        let root = parse_document("1. First\n2. Second\n\nSome text\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let blocks = root.children().unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].is_ordered(), Some(true));
+        let item = blocks[0].children().unwrap().last().unwrap();
+        assert_eq!(item.is_ordered(), None);
+        let paragraph = item.children().unwrap().last().unwrap();
+        assert!(matches!(paragraph, Node::Paragraph(_)), "{paragraph:?}");
+        assert_eq!(paragraph.is_ordered(), None);
+        assert_eq!(paragraph.is_tight(), None);
+    }
+
+    #[test]
+    pub fn image_alt_text_test() {
+        let root = parse_document("![**bold** alt](i.png) ![plain](p.png)")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let images: Vec<&Image> = root
+            .descendants_with_depth()
+            .filter_map(|(_, node)| match node {
+                Node::Image(image) => Some(image),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(images[0].alt_text(), "bold alt");
+        // Descriptions without markup are still borrowed from the input
+        assert!(matches!(
+            images[1].alt_text(),
+            std::borrow::Cow::Borrowed("plain")
+        ));
+    }
+
+    #[test]
+    pub fn borrowed_and_owned_text_test() {
+        let input = "Plain text";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let parsed = match &root.children().unwrap()[0].children().unwrap()[0] {
+            Node::Text(text) => text,
+            other => panic!("Expected a text node, found {other:?}"),
+        };
+        assert_eq!(parsed.as_span(), "Plain text");
+        assert_eq!(parsed.as_borrowed().map(str::as_ptr), Some(input.as_ptr()));
+
+        let owned = Text::new(String::from("Synthesized ") + parsed.as_span());
+        assert_eq!(owned.as_span(), "Synthesized Plain text");
+        assert_eq!(owned.as_borrowed(), None);
+        assert_eq!(owned.into_literal(), "Synthesized Plain text");
+    }
+
+    #[test]
+    pub fn ast_is_send_and_sync_test() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Node>();
+        assert_send_sync::<Children>();
+
+        // Parsed trees can be shared between threads for reading
+        let root = parse_document("Shared *between* threads")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let text: Vec<String> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..2)
+                .map(|_| scope.spawn(|| root.text_leaves().collect::<String>()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert_eq!(text, vec!["Shared between threads"; 2]);
+    }
+
+    #[test]
+    pub fn no_end_of_input_test() {
+        let root = parse_document("A paragraph at the end")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let children = root.children().unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(
+            children[0].children().map(|c| c.len()),
+            Some(1),
+            "The paragraph should only contain its text"
+        );
+    }
+
+    #[test]
+    pub fn paragraph_source_lines_test() {
+        let root = parse_document("First *line\nsecond*  \n  third line\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let paragraph = match &root.children().unwrap()[0] {
+            Node::Paragraph(paragraph) => paragraph,
+            other => panic!("Expected a paragraph, got {other:?}"),
+        };
+        let lines: Vec<&str> = paragraph.source_lines().collect();
+        assert_eq!(lines, vec!["First *line", "second*  ", "  third line"]);
+    }
+
+    #[test]
+    pub fn math_test() {
+        let root = parse_document("Costs $5 and $10, but $a \\$ b$ is math")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let math: Vec<&str> = root
+            .descendants_with_depth()
+            .filter_map(|(_, node)| match node {
+                Node::InlineMath(math) => Some(math.expression()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(math, vec![r"a \$ b"]);
+
+        let root = parse_document("$$\nx = 1\n$$\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        match &root.children().unwrap()[0] {
+            Node::DisplayMath(math) => {
+                assert_eq!(math.expression(), "\nx = 1\n");
+                assert_eq!(math.as_span(), "$$\nx = 1\n$$\n");
+            }
+            other => panic!("Expected display math, got {other:?}"),
+        }
+    }
+
+    #[test]
+    pub fn heading_attributes_test() {
+        let root = parse_document("# Title {#top .a .b data-x=\"one two\"}\n\n# Not {attributes")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let headings: Vec<&Heading> = root
+            .children()
+            .unwrap()
+            .iter()
+            .filter_map(|node| match node {
+                Node::Heading(heading) => Some(heading),
+                _ => None,
+            })
+            .collect();
+
+        let attributes = headings[0].attributes();
+        assert_eq!(attributes.id(), Some("top"));
+        assert_eq!(attributes.classes().collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(
+            attributes.pairs().collect::<Vec<_>>(),
+            vec![("data-x", "one two")]
+        );
+        assert_eq!(
+            headings[0].children().to_vec(),
+            vec![Node::Text(Text::new("Title"))]
+        );
+
+        assert!(headings[1].attributes().is_empty());
+        assert_eq!(
+            headings[1]
+                .children()
+                .iter()
+                .flat_map(Node::text_leaves)
+                .collect::<String>(),
+            "Not {attributes"
+        );
+    }
+
+    #[test]
+    pub fn structurally_eq_test() {
+        let compact = parse_document("# Title\n\nSome *text*\n\n- a\n- b")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let spaced = parse_document("\n\n  # Title #\n\n\n\nSome *text*\n\n\n- a\n- b\n\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert_ne!(compact, spaced);
+        assert!(compact.structurally_eq(&spaced));
+        assert!(spaced.structurally_eq(&compact));
+
+        let different = parse_document("# Title\n\nSome **text**\n\n- a\n- b")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert!(!compact.structurally_eq(&different));
+    }
+
+    #[test]
+    pub fn outer_and_inner_source_test() {
+        let root = parse_document("*x* and [a __link__](/u)\n\n- item")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let paragraph = &root.children().unwrap()[0];
+        let children = paragraph.children().unwrap();
+
+        // Emphasis keeps its delimiters in its span, and its text doesn't
+        let emphasis = &children[0];
+        assert_eq!(emphasis.as_span(), "*x*");
+        assert_eq!(emphasis.outer_source(), Some("*x*"));
+        assert_eq!(emphasis.inner_source(), Some("x"));
+        assert_eq!(emphasis.children().unwrap()[0].as_span(), "x");
+
+        let link = &children[2];
+        assert_eq!(link.outer_source(), Some("[a __link__](/u)"));
+        assert_eq!(link.inner_source(), Some("a __link__"));
+
+        // Blocks don't include trailing blank lines in their outer source
+        assert_eq!(paragraph.as_span(), "*x* and [a __link__](/u)\n\n");
+        assert_eq!(paragraph.outer_source(), Some("*x* and [a __link__](/u)"));
+        let list = &root.children().unwrap()[1];
+        assert_eq!(list.children().unwrap()[0].outer_source(), Some("- item"));
+        assert_eq!(list.children().unwrap()[0].inner_source(), Some("item"));
+
+        let root = parse_document("![alt](/i.png)")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let paragraph = &root.children().unwrap()[0];
+        assert_eq!(paragraph.children().unwrap()[0].outer_source(), None);
+        assert_eq!(paragraph.inner_source(), None);
+    }
+
+    #[test]
+    pub fn autolink_test() {
+        let root = parse_document("<https://x.com> <a@b.com>")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let links: Vec<(&str, bool, Vec<&str>)> = root
+            .descendants_with_depth()
+            .filter_map(|(_, node)| match node {
+                Node::Link(link) => Some((
+                    link.source(),
+                    link.is_email_autolink(),
+                    node.text_leaves().collect(),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                ("https://x.com", false, vec!["https://x.com"]),
+                ("a@b.com", true, vec!["a@b.com"]),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn linebreak_rules_test() {
+        let leaves = |input: &str| {
+            let root =
+                parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            root.children().unwrap()[0]
+                .descendants_with_depth()
+                .skip(1)
+                .map(|(_, node)| node.debug_tree().trim().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        // Both ways of writing a hard break produce a break node
+        assert_eq!(
+            leaves("a  \nb"),
+            vec![r#"Text("a")"#, r#"Linebreak("  \n")"#, r#"Text("b")"#]
+        );
+        assert_eq!(
+            leaves("a\\\nb"),
+            vec![r#"Text("a")"#, r#"Linebreak("\\\n")"#, r#"Text("b")"#]
+        );
+        // A backslash at the end of a paragraph is just a backslash
+        assert_eq!(leaves("a\\"), vec![r#"Text("a\\")"#]);
+        // Newlines in code are part of the code's text
+        assert_eq!(
+            leaves("`a\nb`")[1..],
+            [r#"Text("a")"#, r#"Text("\n")"#, r#"Text("b")"#]
+        );
+    }
+
+    #[test]
+    pub fn block_and_inline_test() {
+        let root = parse_document("# Title\n\nSome *text*\n\n---\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let blocks = root.children().unwrap();
+        assert!(root.is_block());
+        assert!(blocks[0].is_block());
+        assert!(blocks[2].is_block());
+
+        let paragraph = &blocks[1];
+        assert!(paragraph.is_block() && !paragraph.is_inline());
+        let inlines = paragraph.children().unwrap();
+        assert!(inlines[0].is_inline());
+        assert!(inlines[1].is_inline() && !inlines[1].is_block());
+    }
+
+    #[test]
+    pub fn multibyte_boundary_test() {
+        // Spans are sliced by byte offset, so markers right next to multibyte characters are the
+        // most likely place for a slice to land inside of a character
+        let inputs = [
+            "😀*emphasis*😀",
+            "*😀*",
+            "**😀**_é_",
+            "`😀`日本`",
+            "[😀](😀 \"😀\")",
+            "ß  \n日本\\\n😀",
+            "# 😀 {#é .ß}",
+            "- 😀\n  - é\n\n> 日本  \n> ß",
+            "==😀== ~é~ ^ß^ $日本$",
+            "😀[^é] [^é]: ß",
+            "    😀\n\te\u{301}",
+        ];
+        for input in inputs {
+            let root =
+                parse_document(input).unwrap_or_else(|e| panic!("Failed to parse {input:?}: {e}"));
+            for (_, node) in root.descendants_with_depth() {
+                let _ = node.outer_source();
+                let _ = node.inner_source();
+            }
+        }
+    }
+
+    #[test]
+    pub fn long_list_index_test() {
+        let error = parse_document("Text\n\n- a\n  999999999999. x")
+            .expect_err("Expected the index to overflow");
+        assert_eq!(
+            error.to_string(),
+            r#"Invalid structure found in document: List index "999999999999" at line 4, column 3 isn't a valid number: number too large to fit in target type"#
+        );
+
+        let root = parse_document("Text\n\n4294967295. x")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        match &root.children().unwrap()[1] {
+            Node::List(list) => assert_eq!(list.start(), u32::MAX),
+            other => panic!("Expected a list, got {other:?}"),
+        }
+    }
+
+    #[test]
+    pub fn space_run_test() {
+        let texts = |input: &str| {
+            let root =
+                parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            root.text_leaves().map(str::to_owned).collect::<Vec<_>>()
+        };
+        // Runs of spaces between words stay in a single text node, as they were written
+        assert_eq!(texts("a    b"), ["a    b"]);
+        assert_eq!(texts("*a    b*  c"), ["a    b", "  c"]);
+        assert_eq!(texts("- x    y"), ["x    y"]);
+        // Up to three leading spaces are indentation rather than text
+        assert_eq!(texts("   a    b"), ["a    b"]);
+    }
+
+    #[test]
+    pub fn block_indentation_test() {
+        fn first_block(input: &str) -> Node<'_> {
+            let root =
+                parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            root.children().unwrap()[0].clone()
+        }
+        // Up to three spaces can come before a block's marker
+        assert!(matches!(first_block("   # H"), Node::Heading(_)));
+        assert!(matches!(first_block("  - x"), Node::List(_)));
+        assert!(matches!(first_block("   1. x"), Node::List(_)));
+        assert!(matches!(first_block("   > q"), Node::BlockQuote(_)));
+        // Any more and it's an indented code block
+        for input in ["    # H", "    - x", "    1. x", "    > q", "\t- x"] {
+            match first_block(input) {
+                Node::CodeBlock(code_block) => assert_eq!(
+                    code_block.children()[0].as_span(),
+                    input.trim_start(),
+                    "Wrong code in {input:?}"
+                ),
+                other => panic!("Expected a code block for {input:?}, got {other:?}"),
+            }
+        }
+
+        // Nested lists are indented relative to their parent item, so they're still lists
+        match first_block("- a\n    - b") {
+            Node::List(list) => {
+                let item = &list.children()[0];
+                assert!(matches!(item.children().unwrap()[1], Node::List(_)));
+            }
+            other => panic!("Expected a list, got {other:?}"),
+        }
+    }
+
+    #[test]
+    pub fn no_end_of_input_span_test() {
+        // The end of input marker isn't a node, so no span in the tree is the "EOI" sentinel
+        for input in ["Some text", "# Heading\n\n- item\n", "EOI"] {
+            let root =
+                parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            let last = root.children().and_then(|children| children.last());
+            assert!(last.is_some_and(Node::is_block), "{input:?}");
+            let spans = root
+                .descendants_with_depth()
+                .map(|(_, node)| node.as_span())
+                .collect::<Vec<_>>();
+            assert_eq!(spans.contains(&"EOI"), input == "EOI", "{input:?}");
+        }
+    }
+
+    #[test]
+    pub fn first_non_text_test() {
+        let root = parse_document("![logo](logo.png) and text")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let mut children = root.children().unwrap()[0].children().unwrap().clone();
+        children.insert(0, Node::Text(Text::new("  ")));
+        children.push(Node::Text(Text::new(" \t")));
+
+        assert!(matches!(children.first_non_text(), Some(Node::Image(_))));
+        match children.last_non_text() {
+            Some(Node::Text(text)) => assert_eq!(text.as_span(), " and text"),
+            other => panic!("Expected the text after the image, got {other:?}"),
+        }
+        assert!(Children::from(vec![Node::Text(Text::new(" "))])
+            .first_non_text()
+            .is_none());
+    }
+
+    #[test]
+    pub fn coalesce_text_test() {
+        let root = parse_document("Some *emphasized* text")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let mut children = root.children().unwrap()[0].children().unwrap().clone();
+        // Replace the emphasis with its text, leaving three text nodes in a row
+        let emphasis = children.remove(1);
+        let text = emphasis.into_children().unwrap().remove(0);
+        children.insert(1, text);
+        children.push(Node::Text(Text::new(String::from("!"))));
+        assert_eq!(children.len(), 4);
+
+        children.coalesce_text();
+        assert_eq!(children.len(), 1);
+        match &children[0] {
+            Node::Text(text) => {
+                assert_eq!(text.as_span(), "Some emphasized text!");
+                assert!(text.as_borrowed().is_none());
+            }
+            other => panic!("Expected text, got {other:?}"),
+        }
+
+        // Text that isn't next to other text is left alone
+        let mut children = root.children().unwrap()[0].children().unwrap().clone();
+        children.coalesce_text();
+        assert_eq!(&children, root.children().unwrap()[0].children().unwrap());
+    }
+
+    #[test]
+    pub fn top_level_blocks_test() {
+        let input = "# Title\n\nSome *text*\nthat wraps\n\n\n- A list\n- Of items\n\n---\n\n";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let document = match &root {
+            Node::Document(document) => document,
+            other => panic!("Expected a document, got {other:?}"),
+        };
+
+        let blocks: Vec<_> = document.top_level_blocks().collect();
+        assert_eq!(blocks.len(), 4);
+        let mut end = 0;
+        for (range, block) in &blocks {
+            assert_eq!(range.start, end);
+            assert!(input[range.clone()].starts_with(block.as_span()));
+            end = range.end;
+        }
+        assert_eq!(end, input.len());
+        assert_eq!(&input[blocks[1].0.clone()], "Some *text*\nthat wraps\n\n\n");
+        assert!(matches!(blocks[2].1, Node::List(_)));
+    }
+
+    #[test]
+    pub fn line_block_test() {
+        let root = parse_document("| One\n|   Two\n|\n| Three *four*\n\nAfter")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let blocks = root.children().unwrap();
+        assert_eq!(blocks.len(), 2);
+        let lines = match &blocks[0] {
+            Node::LineBlock(line_block) => line_block.children(),
+            other => panic!("Expected a line block, got {other:?}"),
+        };
+        let indents: Vec<u32> = lines
+            .iter()
+            .map(|line| match line {
+                Node::Line(line) => line.indent(),
+                other => panic!("Expected a line, got {other:?}"),
+            })
+            .collect();
+        assert_eq!(indents, vec![0, 2, 0, 0]);
+        assert_eq!(lines[1].text_leaves().collect::<Vec<_>>(), vec!["Two"]);
+        assert!(lines[2].children().is_some_and(|c| c.is_empty()));
+        assert!(matches!(lines[3].children().unwrap()[1], Node::Emphasis(_)));
+        assert!(matches!(blocks[1], Node::Paragraph(_)));
+    }
+
+    #[test]
+    pub fn content_hash_test() {
+        let root = parse_document("- Some *text*\n- Some **text**\n- Some *text*")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let items = root.children().unwrap()[0].children().unwrap();
+        // The last item's span doesn't end with a newline
+        assert_ne!(items[0], items[2]);
+        assert!(items[0].structurally_eq(&items[2]));
+        assert_eq!(items[0].content_hash(), items[2].content_hash());
+        assert_ne!(items[0].content_hash(), items[1].content_hash());
+    }
+}
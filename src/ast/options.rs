@@ -0,0 +1,275 @@
+use crate::error::ParseError;
+
+use super::parsed::ParsedDocument;
+
+/// Options for the indentation sensitive parts of parsing, for documents that don't follow the
+/// CommonMark convention of 4 space indents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// The number of columns between tab stops when measuring indentation. Values less than 1
+    /// are treated as 1.
+    pub tab_width: usize,
+    /// The number of columns that a line has to be indented by to start an indented code block.
+    /// Values less than 1 are treated as 1.
+    pub code_indent: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            tab_width: 4,
+            code_indent: 4,
+        }
+    }
+}
+
+// The indent that the grammar needs to start an indented code block
+const GRAMMAR_CODE_INDENT: usize = 4;
+
+/// Generate an AST for the markdown document like [`parse_document`](super::parse_document), but
+/// using `options` to measure indentation. Before the document is parsed, the tabs in the
+/// indentation of each line are expanded to spaces at `tab_width` stops, and indents of at least
+/// `code_indent` columns are widened or narrowed to the grammar's own 4 column code indent, so
+/// that lists, code blocks, and everything else inside of them all follow the same options.
+/// Lines inside of fenced code blocks keep their indentation relative to the fence.
+///
+/// Since the source that's parsed isn't the same as `input`, it's owned by the result along with
+/// the AST, and it's the source that the AST's spans are slices of.
+///
+/// ### Parameters
+/// - `input` - The markdown source.
+/// - `options` - The indentation settings to parse with.
+///
+/// ### Returns
+/// A result that on success contains the source that was parsed along with its AST, and on
+/// failure a [`ParseError`].
+pub fn parse_document_with_options(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<ParsedDocument, ParseError> {
+    let indenter = Indenter {
+        tab_width: options.tab_width.max(1),
+        code_indent: options.code_indent.max(1),
+    };
+    ParsedDocument::parse(indenter.normalize(input))
+}
+
+struct Indenter {
+    tab_width: usize,
+    code_indent: usize,
+}
+
+/// An open code fence, along with how far it was indented before and after normalizing.
+struct Fence<'a> {
+    marker: &'a str,
+    indent: usize,
+    normalized_indent: usize,
+}
+
+impl Indenter {
+    /// Rewrite the indentation of each line into the form that the grammar expects.
+    fn normalize(&self, input: &str) -> String {
+        let mut normalized = String::with_capacity(input.len());
+        let mut fence: Option<Fence> = None;
+        for line in input.split_inclusive('\n') {
+            let content = line.trim_start_matches([' ', '\t']);
+            let indent = self.columns(&line[..line.len() - content.len()]);
+            if content.trim().is_empty() {
+                normalized.push_str(line);
+                continue;
+            }
+
+            if let Some(open) = &fence {
+                if !content.starts_with(open.marker) {
+                    // Code keeps whatever indentation it has past the fence's, tabs and all
+                    let extra = self.skip_columns(line, open.indent);
+                    let indent = (open.normalized_indent + indent).saturating_sub(open.indent);
+                    normalized.push_str(&" ".repeat(indent.min(open.normalized_indent)));
+                    normalized.push_str(&line[extra..]);
+                    continue;
+                }
+                fence = None;
+            } else if let Some(marker) = fence_marker(content) {
+                fence = Some(Fence {
+                    marker,
+                    indent,
+                    normalized_indent: self.normalized_indent(indent),
+                });
+            }
+            normalized.push_str(&" ".repeat(self.normalized_indent(indent)));
+            normalized.push_str(content);
+        }
+        normalized
+    }
+
+    /// Map an indent in columns onto the grammar's, moving the start of code to its code indent.
+    fn normalized_indent(&self, indent: usize) -> usize {
+        if indent >= self.code_indent {
+            GRAMMAR_CODE_INDENT + indent - self.code_indent
+        } else {
+            indent.min(GRAMMAR_CODE_INDENT - 1)
+        }
+    }
+
+    /// The width of some whitespace in columns, starting from the start of a line.
+    fn columns(&self, whitespace: &str) -> usize {
+        whitespace.chars().fold(0, |columns, c| match c {
+            '\t' => columns + self.tab_width - columns % self.tab_width,
+            _ => columns + 1,
+        })
+    }
+
+    /// The number of bytes of leading whitespace that it takes to reach `columns`.
+    fn skip_columns(&self, line: &str, columns: usize) -> usize {
+        let mut width = 0;
+        for (offset, c) in line.char_indices() {
+            if width >= columns || !matches!(c, ' ' | '\t') {
+                return offset;
+            }
+            width = self.columns(&line[..offset + 1]);
+        }
+        line.len()
+    }
+}
+
+/// The backticks or tildes of a line that opens a code fence, which it has to be closed with.
+fn fence_marker(content: &str) -> Option<&str> {
+    let c = content.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = content.len() - content.trim_start_matches(c).len();
+    (len >= 3).then(|| &content[..len])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::model::Node;
+    use crate::ast::parse_document;
+
+    fn parse(input: &str, tab_width: usize, code_indent: usize) -> ParsedDocument {
+        let options = ParseOptions {
+            tab_width,
+            code_indent,
+        };
+        parse_document_with_options(input, &options)
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"))
+    }
+
+    fn types(node: &Node) -> Vec<&'static str> {
+        node.descendants_with_depth()
+            .map(|(_, node)| node.type_name())
+            .collect()
+    }
+
+    #[test]
+    pub fn code_indent_test() {
+        let input = "Some text\n\n  let x = 1;\n\n  let y = 2;\n\nMore text\n";
+        let parsed = parse(input, 4, 2);
+        let blocks = parsed.ast().children().unwrap();
+        assert!(matches!(blocks[0], Node::Paragraph(_)));
+        assert!(matches!(blocks[2], Node::Paragraph(_)));
+
+        let code: String = blocks[1].text_leaves().collect();
+        assert_eq!(code, "let x = 1;\n\nlet y = 2;");
+    }
+
+    #[test]
+    pub fn tab_width_test() {
+        let parsed = parse("\tNot *code*\n", 2, 4);
+        let blocks = parsed.ast().children().unwrap();
+        assert!(matches!(blocks[0], Node::Paragraph(_)));
+        let text: Vec<&str> = blocks[0].text_leaves().collect();
+        assert_eq!(text, vec!["Not ", "code"]);
+
+        // Inline markup can span the indented lines, just like with spaces
+        let parsed = parse("\t*a\n\tb*", 2, 4);
+        assert_eq!(
+            types(parsed.ast()),
+            [
+                "Document",
+                "Paragraph",
+                "Emphasis",
+                "Text",
+                "SoftLinebreak",
+                "Text"
+            ]
+        );
+        let parsed = parse("\t[a\n\tb](/x)", 2, 4);
+        let spaces = parse_document("  [a\n  b](/x)")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert!(parsed.ast().structurally_eq(&spaces));
+    }
+
+    #[test]
+    pub fn nested_indent_test() {
+        // A tab is the same as the spaces it stands for
+        let tabs = parse("- a\n\n\t- b", 4, 4);
+        let spaces = parse("- a\n\n    - b", 4, 4);
+        assert!(tabs.ast().structurally_eq(spaces.ast()));
+        assert_eq!(
+            types(tabs.ast()),
+            ["Document", "List", "ListItem", "Text", "List", "ListItem", "Text"]
+        );
+
+        // Code inside of a list item starts at the code indent too
+        let parsed = parse("- a\n\n    b", 4, 2);
+        let code = parsed
+            .ast()
+            .descendants_with_depth()
+            .find_map(|(_, node)| match node {
+                Node::CodeBlock(code) => Some(
+                    code.children()
+                        .iter()
+                        .flat_map(Node::text_leaves)
+                        .collect::<String>(),
+                ),
+                _ => None,
+            });
+        assert_eq!(code.as_deref(), Some("  b"));
+
+        // While lists indented by less still nest
+        let parsed = parse("- a\n  - b\n\nAfter", 4, 2);
+        assert_eq!(
+            types(parsed.ast()),
+            [
+                "Document",
+                "List",
+                "ListItem",
+                "Text",
+                "List",
+                "ListItem",
+                "Text",
+                "Paragraph",
+                "Text"
+            ]
+        );
+    }
+
+    #[test]
+    pub fn block_quote_indent_test() {
+        let parsed = parse("\t> Quoted\n\t> text", 2, 4);
+        assert_eq!(
+            types(parsed.ast()),
+            ["Document", "BlockQuote", "Text", "SoftLinebreak", "Text"]
+        );
+
+        // A quote marker indented by the code indent is code
+        let parsed = parse("Text\n\n  > a", 4, 2);
+        let blocks = parsed.ast().children().unwrap();
+        assert!(matches!(blocks[1], Node::CodeBlock(_)));
+        let code: String = blocks[1].text_leaves().collect();
+        assert_eq!(code, "> a");
+    }
+
+    #[test]
+    pub fn fenced_indent_test() {
+        // Code in a fence keeps its indentation, tabs and all, even past the code indent
+        let input = "```\n\tx\n  y\n```\n\n  z";
+        let parsed = parse(input, 2, 2);
+        let blocks = parsed.ast().children().unwrap();
+        let code: String = blocks[0].text_leaves().collect();
+        assert_eq!(code, "\tx\n  y");
+        // While the indent after the fence is closed still starts code
+        let code: String = blocks[1].text_leaves().collect();
+        assert_eq!(code, "z");
+    }
+}
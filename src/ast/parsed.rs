@@ -0,0 +1,96 @@
+use crate::error::ParseError;
+
+use super::model::Node;
+use super::parse_document;
+
+/// A markdown source that's owned together with the AST parsed from it, for when the AST needs to
+/// outlive the function that has the source, like returning it or storing it in a struct.
+///
+/// The AST borrows from the source just like it does with [`parse_document`], so it can only be
+/// accessed through a reference to the `ParsedDocument` and with its lifetime.
+#[derive(Debug)]
+pub struct ParsedDocument {
+    // Declared before the source so that it's dropped first, since it points into the source
+    ast: Node<'static>,
+    source: String,
+}
+
+impl ParsedDocument {
+    /// Take ownership of the markdown source and generate its AST.
+    ///
+    /// ### Parameters
+    /// - `source` - The markdown source.
+    ///
+    /// ### Returns
+    /// A result that on success contains the source and its AST, and on failure a [`ParseError`].
+    pub fn parse(source: impl Into<String>) -> Result<Self, ParseError> {
+        let source = source.into();
+        // SAFETY: The AST borrows from the source's heap buffer, which stays at the same address
+        // when the `String` is moved into the struct. The source is never mutated or handed out
+        // mutably, and the AST is never handed out with a lifetime longer than `&self`.
+        let input: &'static str = unsafe { &*(source.as_str() as *const str) };
+        let ast = parse_document(input)?;
+        Ok(Self { ast, source })
+    }
+
+    /// The root of the AST, borrowing from the owned source.
+    pub fn ast(&self) -> &Node<'_> {
+        &self.ast
+    }
+
+    /// The markdown source that the AST was parsed from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Drop the AST, returning the markdown source.
+    pub fn into_source(self) -> String {
+        self.source
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::traversal::{Direction, NextAction, TraversalContext, Visitor};
+    use std::cell::Cell;
+
+    #[derive(Default)]
+    struct HeadingCounter(Cell<usize>);
+
+    impl<'a> Visitor<'a> for HeadingCounter {
+        fn visit(
+            &self,
+            node: &'a Node<'a>,
+            direction: Direction,
+            _: &TraversalContext,
+        ) -> NextAction {
+            if let (Node::Heading(_), Direction::Entering) = (node, direction) {
+                self.0.set(self.0.get() + 1);
+            }
+            NextAction::GotoNext
+        }
+    }
+
+    fn parse_owned() -> ParsedDocument {
+        let source = String::from("# Title\n\nSome *owned* text");
+        ParsedDocument::parse(source).unwrap_or_else(|e| panic!("Failed to parse document: {e}"))
+    }
+
+    #[test]
+    pub fn parsed_document_test() {
+        let document = parse_owned();
+        let text: Vec<&str> = document.ast().text_leaves().collect();
+        assert_eq!(text, vec!["Title", "Some ", "owned", " text"]);
+        assert_eq!(document.ast().as_span(), document.source());
+
+        let counter = HeadingCounter::default();
+        document.ast().traverse(&counter);
+        assert_eq!(counter.0.get(), 1);
+
+        // Moving the document doesn't move the source that the AST points into
+        let moved = Box::new(document);
+        assert_eq!(moved.ast().children().map(|c| c.len()), Some(2));
+        assert_eq!(moved.into_source(), "# Title\n\nSome *owned* text");
+    }
+}
@@ -0,0 +1,101 @@
+use serde_json::Value;
+
+use super::model::Node;
+
+/// Describe the JSON that the AST serializes to as a [JSON Schema](https://json-schema.org/),
+/// for consumers of the serialized AST written in other languages. The schema is derived from the
+/// same types and serde attributes that the serialization is, so the two can't drift apart. The
+/// root of the schema describes a [`Node`], and each node type is defined under `$defs`.
+///
+/// Nodes are serialized as externally tagged enums, so every node is an object with a single key
+/// naming its type. With the `serde_tagged` feature, they're internally tagged instead, so every
+/// node is an object of its fields along with a `type` naming its type.
+pub fn json_schema() -> Value {
+    schemars::schema_for!(Node).to_value()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::parse_document;
+    use crate::test_utils::read_file_to_string;
+    use serde_json::Map;
+
+    /// The name of each type of node in the schema, from the variants of `Node`.
+    fn variant_names(schema: &Value) -> Vec<&str> {
+        schema["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(
+                |variant| match variant["properties"]["type"]["const"].as_str() {
+                    Some(name) => name,
+                    None => variant["required"][0].as_str().unwrap(),
+                },
+            )
+            .collect()
+    }
+
+    #[test]
+    pub fn schema_variants_test() {
+        let schema = json_schema();
+        let names = variant_names(&schema);
+        for name in [
+            "Document",
+            "Paragraph",
+            "List",
+            "ListItem",
+            "Text",
+            "Image",
+            "ErrorBlock",
+        ] {
+            assert!(names.contains(&name), "Missing variant {name}");
+        }
+        let definitions = schema["$defs"].as_object().unwrap();
+        for name in &names {
+            assert!(
+                definitions.contains_key(*name),
+                "Missing definition for {name}"
+            );
+        }
+
+        // Every node and field in the serialized fixtures needs to be described by the schema
+        fn check(value: &Value, names: &[&str], definitions: &Map<String, Value>) {
+            let (name, fields) = match value {
+                Value::Object(node) if cfg!(feature = "serde_tagged") => {
+                    (node["type"].as_str().unwrap(), node)
+                }
+                Value::Object(node) if node.len() == 1 => {
+                    let (name, fields) = node.iter().next().unwrap();
+                    (name.as_str(), fields.as_object().unwrap())
+                }
+                other => panic!("Unexpected node {other}"),
+            };
+            assert!(names.contains(&name), "{name} isn't a variant");
+            let properties = definitions[name]["properties"].as_object().unwrap();
+            for (field, value) in fields {
+                if field == "type" && cfg!(feature = "serde_tagged") {
+                    continue;
+                }
+                assert!(properties.contains_key(field), "{name} has no {field}");
+                if let Value::Array(children) = value {
+                    children
+                        .iter()
+                        .for_each(|child| check(child, names, definitions));
+                }
+            }
+        }
+        for fixture in [
+            "markdown/markup.md",
+            "markdown/lists.md",
+            "markdown/blocks.md",
+        ] {
+            let input = read_file_to_string(fixture);
+            let root =
+                parse_document(&input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            let value = serde_json::to_value(&root)
+                .unwrap_or_else(|e| panic!("Failed to serialize AST: {e}"));
+            check(&value, &names, definitions);
+        }
+    }
+}
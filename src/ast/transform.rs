@@ -0,0 +1,207 @@
+use super::model::{Node, Text};
+
+/// A post-parse hook for custom inline syntax, like `@mentions` or `:emoji:`, that the grammar
+/// doesn't know about. Transforms are run over the text of a tree by [`transform_inline`].
+pub trait InlineTransform<'input> {
+    /// Split a run of text into the nodes to replace it with, or return `None` to leave it as is.
+    /// Text that's still borrowed from the input can be split into slices with
+    /// [`Text::as_borrowed`], so that the new nodes keep pointing into the input.
+    fn transform(&self, text: &Text<'input>) -> Option<Vec<Node<'input>>>;
+}
+
+/// Run each transform over every [`Node::Text`] in the tree, in order, splicing in the nodes that
+/// it splits the text into. Later transforms only see the text that the earlier ones left, and the
+/// text in code spans and code blocks is never transformed.
+///
+/// ### Parameters
+/// - `root` - The tree to transform, usually a document.
+/// - `transforms` - The transforms to run, in the order to run them.
+///
+/// ### Returns
+/// The tree with its text transformed.
+pub fn transform_inline<'input>(
+    mut root: Node<'input>,
+    transforms: &[&dyn InlineTransform<'input>],
+) -> Node<'input> {
+    transform_children(&mut root, transforms);
+    root
+}
+
+fn transform_children<'input>(
+    node: &mut Node<'input>,
+    transforms: &[&dyn InlineTransform<'input>],
+) {
+    if matches!(node, Node::Code(_) | Node::CodeBlock(_)) {
+        return;
+    }
+    let Some(children) = node.children_mut() else {
+        return;
+    };
+    let mut transformed = Vec::with_capacity(children.len());
+    for mut child in std::mem::take(&mut **children) {
+        match child {
+            Node::Text(text) => transformed.extend(transform_text(text, transforms)),
+            _ => {
+                transform_children(&mut child, transforms);
+                transformed.push(child);
+            }
+        }
+    }
+    **children = transformed;
+}
+
+fn transform_text<'input>(
+    text: Text<'input>,
+    transforms: &[&dyn InlineTransform<'input>],
+) -> Vec<Node<'input>> {
+    let mut nodes = vec![Node::Text(text)];
+    for transform in transforms {
+        nodes = nodes
+            .into_iter()
+            .flat_map(|node| match node {
+                Node::Text(text) => transform
+                    .transform(&text)
+                    .unwrap_or_else(|| vec![Node::Text(text)]),
+                node => vec![node],
+            })
+            .collect();
+    }
+    nodes
+}
+
+// A small set of the most common GitHub style shortcodes, sorted so they can be binary searched
+const EMOJI: &[(&str, &str)] = &[
+    ("+1", "\u{1F44D}"),
+    ("-1", "\u{1F44E}"),
+    ("cry", "\u{1F622}"),
+    ("eyes", "\u{1F440}"),
+    ("fire", "\u{1F525}"),
+    ("grin", "\u{1F601}"),
+    ("heart", "\u{2764}\u{FE0F}"),
+    ("joy", "\u{1F602}"),
+    ("laughing", "\u{1F606}"),
+    ("rocket", "\u{1F680}"),
+    ("smile", "\u{1F604}"),
+    ("smiley", "\u{1F603}"),
+    ("sparkles", "\u{2728}"),
+    ("tada", "\u{1F389}"),
+    ("thinking", "\u{1F914}"),
+    ("thumbsdown", "\u{1F44E}"),
+    ("thumbsup", "\u{1F44D}"),
+    ("warning", "\u{26A0}\u{FE0F}"),
+    ("white_check_mark", "\u{2705}"),
+    ("wink", "\u{1F609}"),
+    ("x", "\u{274C}"),
+];
+
+/// An [`InlineTransform`] that replaces shortcodes like `:smile:` with the emoji they name.
+/// Shortcodes that it doesn't know are left as they were written.
+///
+/// ```
+/// use pegmd::ast::model::Node;
+/// use pegmd::ast::{parse_document, transform_inline, EmojiShortcodes};
+///
+/// let root = parse_document("Ship it :rocket:").unwrap();
+/// let root = transform_inline(root, &[&EmojiShortcodes]);
+/// let text: String = root
+///     .descendants_with_depth()
+///     .filter_map(|(_, node)| match node {
+///         Node::Text(text) => Some(text.as_span()),
+///         _ => None,
+///     })
+///     .collect();
+/// assert_eq!(text, "Ship it \u{1F680}");
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmojiShortcodes;
+
+impl EmojiShortcodes {
+    fn emoji(name: &str) -> Option<&'static str> {
+        EMOJI
+            .binary_search_by_key(&name, |(shortcode, _)| shortcode)
+            .ok()
+            .map(|i| EMOJI[i].1)
+    }
+}
+
+impl<'input> InlineTransform<'input> for EmojiShortcodes {
+    fn transform(&self, text: &Text<'input>) -> Option<Vec<Node<'input>>> {
+        let literal = text.as_span();
+        let mut nodes = Vec::new();
+        let mut written = 0;
+        let mut search = 0;
+        while let Some(open) = literal[search..].find(':').map(|i| search + i) {
+            let Some(close) = literal[open + 1..].find(':').map(|i| open + 1 + i) else {
+                break;
+            };
+            // The closing colon can start the next shortcode if this one isn't known
+            search = close;
+            let Some(emoji) = Self::emoji(&literal[open + 1..close]) else {
+                continue;
+            };
+            if written < open {
+                nodes.push(Node::Text(slice(text, written..open)));
+            }
+            nodes.push(Node::Text(Text::new(emoji)));
+            written = close + 1;
+            search = written;
+        }
+
+        if nodes.is_empty() {
+            return None;
+        }
+        if written < literal.len() {
+            nodes.push(Node::Text(slice(text, written..literal.len())));
+        }
+        Some(nodes)
+    }
+}
+
+fn slice<'input>(text: &Text<'input>, range: std::ops::Range<usize>) -> Text<'input> {
+    match text.as_borrowed() {
+        Some(literal) => Text::new(&literal[range]),
+        None => Text::new(text.as_span()[range].to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::parse_document;
+
+    #[test]
+    pub fn emoji_shortcode_test() {
+        let input = "Hello :smile: and :not_an_emoji: or 10:30:smile:, but `:smile:`";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let root = transform_inline(root, &[&EmojiShortcodes]);
+
+        let paragraph = root.children().unwrap()[0].children().unwrap();
+        let text: Vec<_> = paragraph
+            .iter()
+            .filter_map(|node| match node {
+                Node::Text(text) => Some(text.as_span()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            text,
+            [
+                "Hello ",
+                "\u{1F604}",
+                " and :not_an_emoji: or 10:30",
+                "\u{1F604}",
+                ", but "
+            ]
+        );
+        // The text around the emoji is still borrowed from the input
+        match &paragraph[0] {
+            Node::Text(text) => assert_eq!(text.as_borrowed(), Some("Hello ")),
+            other => panic!("Expected text, got {other:?}"),
+        }
+        match paragraph.last() {
+            Some(Node::Code(code)) => assert_eq!(code.as_span(), "`:smile:`"),
+            other => panic!("Expected a code span, got {other:?}"),
+        }
+    }
+}
@@ -1,20 +1,111 @@
-use super::model::Node;
+use super::model::{Children, Node};
 
 pub enum Direction {
     Entering,
     Exiting,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NextAction {
     GotoNext,
     SkipChildren,
     End,
 }
 
+impl NextAction {
+    /// Combine the decisions of two visitors, keeping whichever is the most restrictive. [`NextAction::End`]
+    /// takes precedence over [`NextAction::SkipChildren`], which takes precedence over [`NextAction::GotoNext`],
+    /// so the traversal only continues into a node's children if both sides agree to it.
+    pub fn and(self, other: NextAction) -> NextAction {
+        match (self, other) {
+            (Self::End, _) | (_, Self::End) => Self::End,
+            (Self::SkipChildren, _) | (_, Self::SkipChildren) => Self::SkipChildren,
+            (Self::GotoNext, Self::GotoNext) => Self::GotoNext,
+        }
+    }
+}
+
 /// Visitors can traverse the AST and perform action on each node. See [traverse](Node::traverse)
 /// for more information on how the visitor interacts with the tree.
 pub trait Visitor<'input> {
-    fn visit(&self, node: &'input Node<'input>, direction: Direction) -> NextAction;
+    fn visit(
+        &self,
+        node: &'input Node<'input>,
+        direction: Direction,
+        context: &TraversalContext<'input>,
+    ) -> NextAction;
+}
+
+/// Where the node being visited is in the tree, so that visitors don't need to track it
+/// themselves.
+#[derive(Debug, Default)]
+pub struct TraversalContext<'input> {
+    ancestors: Vec<&'input Node<'input>>,
+    list_ordinals: Vec<u32>,
+}
+
+impl<'input> TraversalContext<'input> {
+    /// How deeply nested the node is below the node that the traversal started from, which has a
+    /// depth of 0.
+    pub fn depth(&self) -> usize {
+        self.ancestors.len()
+    }
+
+    /// The containers that the node is in, starting with the node that the traversal started from
+    /// and ending with the node's parent.
+    pub fn ancestors(&self) -> &[&'input Node<'input>] {
+        &self.ancestors
+    }
+
+    pub fn parent(&self) -> Option<&'input Node<'input>> {
+        self.ancestors.last().copied()
+    }
+
+    /// The number of each list item that the node is in, outermost first, including the node
+    /// itself if it's a list item. Items are numbered from their list's start, so an item's
+    /// number is where it is in the list rather than what was written in its marker, like in the
+    /// rendered list. Numbers past [`u32::MAX`] stay at [`u32::MAX`].
+    pub fn list_ordinals(&self) -> &[u32] {
+        &self.list_ordinals
+    }
+}
+
+/// Wraps a visitor so that the children of any node matching the predicate are skipped. The wrapped
+/// visitor still visits the matching nodes themselves, and its own decisions are combined with the
+/// skip using [`NextAction::and`].
+pub struct SkipChildrenWhere<V, F> {
+    visitor: V,
+    predicate: F,
+}
+
+impl<V, F> SkipChildrenWhere<V, F> {
+    pub fn new(visitor: V, predicate: F) -> Self {
+        Self { visitor, predicate }
+    }
+
+    /// Consume the wrapper, returning the wrapped visitor.
+    pub fn into_inner(self) -> V {
+        self.visitor
+    }
+}
+
+impl<'input, V, F> Visitor<'input> for SkipChildrenWhere<V, F>
+where
+    V: Visitor<'input>,
+    F: Fn(&Node<'input>) -> bool,
+{
+    fn visit(
+        &self,
+        node: &'input Node<'input>,
+        direction: Direction,
+        context: &TraversalContext<'input>,
+    ) -> NextAction {
+        let skip = match direction {
+            Direction::Entering if (self.predicate)(node) => NextAction::SkipChildren,
+            _ => NextAction::GotoNext,
+        };
+        self.visitor.visit(node, direction, context).and(skip)
+    }
 }
 
 impl<'a> Node<'a> {
@@ -36,30 +127,377 @@ impl<'a> Node<'a> {
     /// Pipes through the visitor's NextAction value to be used in the recursive call. Any expected output from the
     /// recursion should be generated by side effects in the visitor as it visits each node.
     pub fn traverse(&'a self, visitor: &impl Visitor<'a>) -> NextAction {
+        self.traverse_with(visitor, &mut TraversalContext::default())
+    }
+
+    fn traverse_with(
+        &'a self,
+        visitor: &impl Visitor<'a>,
+        context: &mut TraversalContext<'a>,
+    ) -> NextAction {
         let children = match self.children() {
             Some(c) => c,
-            None => return visitor.visit(self, Direction::Entering),
+            None => return visitor.visit(self, Direction::Entering, context),
         };
 
-        match visitor.visit(self, Direction::Entering) {
+        match visitor.visit(self, Direction::Entering, context) {
             NextAction::GotoNext => {
+                let start = match self {
+                    Node::List(list) => Some(list.start()),
+                    _ => None,
+                };
+                context.ancestors.push(self);
                 // Visit the children, stopping early if one of them says to end the traversal
-                for child in children.iter() {
-                    if let NextAction::End = child.traverse(visitor) {
-                        return NextAction::End;
+                let mut next_action = NextAction::GotoNext;
+                for (position, child) in (0..).zip(children.iter()) {
+                    match (start, child) {
+                        (Some(start), Node::ListItem(_)) => {
+                            context.list_ordinals.push(start.saturating_add(position));
+                            next_action = child.traverse_with(visitor, context);
+                            context.list_ordinals.pop();
+                        }
+                        _ => next_action = child.traverse_with(visitor, context),
                     }
+                    if let NextAction::End = next_action {
+                        break;
+                    }
+                }
+                context.ancestors.pop();
+                match (
+                    next_action,
+                    visitor.visit(self, Direction::Exiting, context),
+                ) {
+                    // Still owe this container its exit visit so that visitors can close it out
+                    (NextAction::End, _) => NextAction::End,
+                    (_, exit_action) => exit_action,
                 }
-                visitor.visit(self, Direction::Exiting)
             }
             NextAction::SkipChildren => {
                 // Give the container its exit visit since we're not visiting any children
-                visitor.visit(self, Direction::Exiting)
+                visitor.visit(self, Direction::Exiting, context)
             }
             NextAction::End => {
                 // Give the container its exit visit before stopping the traversal
-                let _ = visitor.visit(self, Direction::Exiting);
+                let _ = visitor.visit(self, Direction::Exiting, context);
                 NextAction::End
             }
         }
     }
+
+    /// Iterate over the literal contents of every [`Node::Text`] leaf beneath this node, in document
+    /// order. Text nested inside of other containers, like the contents of [`Node::Code`] or
+    /// [`Node::Emphasis`], is included. Handy for things like spell checking that only care about
+    /// the text and not how it's marked up.
+    pub fn text_leaves(&self) -> TextLeaves<'_, 'a> {
+        TextLeaves { stack: vec![self] }
+    }
+
+    /// Iterate over this node and all of the nodes beneath it in document order, along with how
+    /// deeply nested each one is. This node has a depth of 0, its children a depth of 1, and so on.
+    pub fn descendants_with_depth(&self) -> Descendants<'_, 'a> {
+        Descendants {
+            stack: vec![(0, self)],
+        }
+    }
+
+    /// Copy the tree, dropping or replacing nodes along the way. The closure is called on a copy of
+    /// each node after its children have been filtered, and returns the node to use in its place,
+    /// or `None` to drop the node along with everything beneath it. The copy borrows from the same
+    /// input, so the spans of the kept nodes still point into the original source.
+    ///
+    /// ### Returns
+    /// The root of the filtered tree, or `None` if the closure dropped this node.
+    pub fn filter_map<F>(&self, mut f: F) -> Option<Node<'a>>
+    where
+        F: FnMut(Node<'a>) -> Option<Node<'a>>,
+    {
+        self.filter_map_with(&mut f)
+    }
+
+    fn filter_map_with<F>(&self, f: &mut F) -> Option<Node<'a>>
+    where
+        F: FnMut(Node<'a>) -> Option<Node<'a>>,
+    {
+        let node = match self.children() {
+            Some(children) => {
+                let filtered: Vec<Node<'a>> = children
+                    .iter()
+                    .filter_map(|child| child.filter_map_with(f))
+                    .collect();
+                self.clone_with_children(filtered.into())
+            }
+            None => self.clone(),
+        };
+        f(node)
+    }
+
+    /// Copy a container without its children, or the whole node if it's a leaf.
+    fn clone_with_children(&self, children: Children<'a>) -> Node<'a> {
+        match self {
+            Self::Document(c) => Self::Document(c.clone_with_children(children)),
+            Self::Paragraph(p) => Self::Paragraph(p.clone_with_children(children)),
+            Self::BlockQuote(bq) => Self::BlockQuote(bq.clone_with_children(children)),
+            Self::Heading(h) => Self::Heading(h.clone_with_children(children)),
+            Self::List(l) => Self::List(l.clone_with_children(children)),
+            Self::ListItem(li) => Self::ListItem(li.clone_with_children(children)),
+            Self::LineBlock(lb) => Self::LineBlock(lb.clone_with_children(children)),
+            Self::Line(l) => Self::Line(l.clone_with_children(children)),
+            Self::CodeBlock(cb) => Self::CodeBlock(cb.clone_with_children(children)),
+            Self::Emphasis(emp) => Self::Emphasis(emp.clone_with_children(children)),
+            Self::Strong(strong) => Self::Strong(strong.clone_with_children(children)),
+            Self::Subscript(sub) => Self::Subscript(sub.clone_with_children(children)),
+            Self::Superscript(sup) => Self::Superscript(sup.clone_with_children(children)),
+            Self::Highlight(highlight) => Self::Highlight(highlight.clone_with_children(children)),
+            Self::Label(l) => Self::Label(l.clone_with_children(children)),
+            Self::Link(l) => Self::Link(l.clone_with_children(children)),
+            Self::Code(c) => Self::Code(c.clone_with_children(children)),
+            Self::FootnoteDefinition(f) => {
+                Self::FootnoteDefinition(f.clone_with_children(children))
+            }
+            leaf => leaf.clone(),
+        }
+    }
+
+    /// Build an indented outline of the tree for debugging, with one node per line. Leaves show
+    /// their literal text, so a small document looks something like
+    ///
+    /// ```text
+    /// Document
+    ///   Paragraph
+    ///     Text("hi")
+    /// ```
+    pub fn debug_tree(&self) -> String {
+        self.debug_truncated(usize::MAX)
+    }
+
+    /// Build the same outline as [`Node::debug_tree`], but without going deeper than `max_depth`
+    /// so that it stays readable for huge documents. The children of nodes at `max_depth` are
+    /// replaced with a single `...` line.
+    pub fn debug_truncated(&self, max_depth: usize) -> String {
+        let mut outline = String::new();
+        self.write_outline(0, max_depth, &mut outline);
+        outline
+    }
+
+    fn write_outline(&self, depth: usize, max_depth: usize, outline: &mut String) {
+        outline.push_str(&"  ".repeat(depth));
+        outline.push_str(self.type_name());
+        let children = match self.children() {
+            Some(children) => children,
+            None => {
+                outline.push_str(&format!("({:?})\n", self.as_span()));
+                return;
+            }
+        };
+        outline.push('\n');
+        if depth >= max_depth {
+            if !children.is_empty() {
+                outline.push_str(&"  ".repeat(depth + 1));
+                outline.push_str("...\n");
+            }
+            return;
+        }
+        for child in children.iter() {
+            child.write_outline(depth + 1, max_depth, outline);
+        }
+    }
+
+    pub(crate) fn type_name(&self) -> &'static str {
+        self.kind().name()
+    }
+}
+
+/// Iterator over the text leaves of a subtree, created by [`Node::text_leaves`].
+pub struct TextLeaves<'node, 'input> {
+    stack: Vec<&'node Node<'input>>,
+}
+
+impl<'node, 'input> Iterator for TextLeaves<'node, 'input> {
+    type Item = &'node str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            if let Node::Text(text) = node {
+                return Some(text.as_span());
+            }
+            // Children are pushed in reverse so that the first child is the next one popped.
+            if let Some(children) = node.children() {
+                self.stack.extend(children.iter().rev());
+            }
+        }
+        None
+    }
+}
+
+/// Iterator over a subtree and the depth of each node in it, created by [`Node::descendants_with_depth`].
+pub struct Descendants<'node, 'input> {
+    stack: Vec<(usize, &'node Node<'input>)>,
+}
+
+impl<'node, 'input> Iterator for Descendants<'node, 'input> {
+    type Item = (usize, &'node Node<'input>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (depth, node) = self.stack.pop()?;
+        if let Some(children) = node.children() {
+            self.stack
+                .extend(children.iter().rev().map(|child| (depth + 1, child)));
+        }
+        Some((depth, node))
+    }
+}
+
+/// Iterate over the direct children of a node, so that `for child in &node` works. Leaves have no
+/// children to iterate over.
+impl<'node, 'input> IntoIterator for &'node Node<'input> {
+    type Item = &'node Node<'input>;
+    type IntoIter = std::slice::Iter<'node, Node<'input>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self.children() {
+            Some(children) => children.iter(),
+            None => [].iter(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::parse_document;
+    use std::cell::RefCell;
+
+    #[test]
+    pub fn text_leaves_test() {
+        let root = parse_document("Some *mixed* text with `code` and a [link](https://x.y)")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let actual: Vec<&str> = root.text_leaves().collect();
+        let expected = vec!["Some ", "mixed", " text with ", "code", " and a ", "link"];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    pub fn next_action_and_test() {
+        use NextAction::*;
+        assert_eq!(GotoNext.and(GotoNext), GotoNext);
+        assert_eq!(GotoNext.and(SkipChildren), SkipChildren);
+        assert_eq!(SkipChildren.and(GotoNext), SkipChildren);
+        assert_eq!(SkipChildren.and(End), End);
+        assert_eq!(End.and(GotoNext), End);
+    }
+
+    #[test]
+    pub fn skip_children_where_test() {
+        struct TextCollector<'input>(RefCell<Vec<&'input str>>);
+
+        impl<'input> Visitor<'input> for TextCollector<'input> {
+            fn visit(
+                &self,
+                node: &'input Node<'input>,
+                _: Direction,
+                _: &TraversalContext<'input>,
+            ) -> NextAction {
+                if let Node::Text(text) = node {
+                    self.0.borrow_mut().push(text.as_span());
+                }
+                NextAction::GotoNext
+            }
+        }
+
+        let root = parse_document("Some text\n\n```\nskipped code\n```\n\nMore text\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let visitor = SkipChildrenWhere::new(TextCollector(RefCell::default()), |node: &Node| {
+            matches!(node, Node::CodeBlock(_))
+        });
+        root.traverse(&visitor);
+        let actual = visitor.into_inner().0.into_inner();
+        assert_eq!(actual, vec!["Some text", "More text"]);
+    }
+
+    #[test]
+    pub fn debug_tree_test() {
+        let root = parse_document("# Title\n\nSome *text*")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let outline = root.debug_tree();
+        let lines: Vec<&str> = outline.lines().collect();
+        assert_eq!(lines[0], "Document");
+        assert_eq!(lines[1], "  Heading");
+        assert_eq!(lines[2], "    Text(\"Title\")");
+        assert!(lines.contains(&"  Paragraph"));
+        assert!(lines.contains(&"    Emphasis"));
+        assert!(lines.contains(&"      Text(\"text\")"));
+    }
+
+    #[test]
+    pub fn debug_truncated_test() {
+        let root = parse_document("> Some *deeply **nested***\n\nTop")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert_eq!(
+            root.debug_truncated(2),
+            "Document\n  BlockQuote\n    Text(\"Some \")\n    Emphasis\n      ...\n  Paragraph\n    Text(\"Top\")\n"
+        );
+        assert_eq!(root.debug_truncated(0), "Document\n  ...\n");
+        assert_eq!(root.debug_truncated(usize::MAX), root.debug_tree());
+    }
+
+    #[test]
+    pub fn traversal_context_test() {
+        /// Writes the number of each list item along with the numbers of the items it's in
+        struct OrdinalPaths(RefCell<Vec<String>>);
+
+        impl<'input> Visitor<'input> for OrdinalPaths {
+            fn visit(
+                &self,
+                node: &'input Node<'input>,
+                direction: Direction,
+                context: &TraversalContext<'input>,
+            ) -> NextAction {
+                if let (Node::ListItem(_), Direction::Entering) = (node, direction) {
+                    assert!(matches!(context.parent(), Some(Node::List(_))));
+                    let path: Vec<String> = context
+                        .list_ordinals()
+                        .iter()
+                        .map(|ordinal| ordinal.to_string())
+                        .collect();
+                    let path = format!("{}{}", "  ".repeat(context.depth()), path.join("."));
+                    self.0.borrow_mut().push(path);
+                }
+                NextAction::GotoNext
+            }
+        }
+
+        let root = parse_document("3. a\n7. b\n   - c\n   - d\n1. e")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let visitor = OrdinalPaths(RefCell::default());
+        root.traverse(&visitor);
+        assert_eq!(
+            visitor.0.into_inner(),
+            vec!["    3", "    4", "        4.1", "        4.2", "    5"]
+        );
+
+        // Items numbered past the largest start don't overflow
+        let root = parse_document("4294967295. a\n1. b\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let visitor = OrdinalPaths(RefCell::default());
+        root.traverse(&visitor);
+        assert_eq!(
+            visitor.0.into_inner(),
+            vec!["    4294967295", "    4294967295"]
+        );
+    }
+
+    #[test]
+    pub fn into_iterator_test() {
+        let root = parse_document("Some *mixed* text")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let paragraph = root.children().map(|c| &c[0]).unwrap();
+        let mut kinds = Vec::new();
+        for child in paragraph {
+            kinds.push(child.type_name());
+        }
+        assert_eq!(kinds, vec!["Text", "Emphasis", "Text"]);
+
+        let text = paragraph.children().map(|c| &c[0]).unwrap();
+        assert_eq!(text.into_iter().count(), 0);
+    }
 }
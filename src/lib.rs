@@ -15,11 +15,145 @@ mod parser {
                 Self::str | Self::symbol | Self::source | Self::space | Self::non_space
             )
         }
+
+        /// A user friendly name for the rule, used in error messages so that they don't
+        /// expose the names of the grammar's internal rules.
+        pub fn human_name(&self) -> &'static str {
+            match self {
+                Self::EOI => "end of input",
+                Self::document => "document",
+                Self::space => "space",
+                Self::non_space => "non-space character",
+                Self::control_character => "escapable character",
+                Self::str | Self::symbol => "text",
+                Self::normal_endline | Self::list_endline | Self::blockquote_endline => {
+                    "line ending"
+                }
+                Self::linebreak
+                | Self::list_linebreak
+                | Self::blockquote_linebreak
+                | Self::linebreak_literal => "line break",
+                Self::label => "link label",
+                Self::source | Self::reference_source => "link destination",
+                Self::link_title => "link title",
+                Self::thematic_break => "thematic break",
+                Self::paragraph => "paragraph",
+                Self::verbatim => "block quote",
+                Self::atx_hash => "heading marker",
+                Self::header => "heading",
+                Self::attribute_list
+                | Self::attribute_id
+                | Self::attribute_class
+                | Self::attribute_pair => "attribute list",
+                Self::attribute_key => "attribute name",
+                Self::attribute_value => "attribute value",
+                Self::end_list => "end of list",
+                Self::star_bullet | Self::dash_bullet | Self::plus_bullet | Self::bullet => {
+                    "list bullet"
+                }
+                Self::list_index => "list index",
+                Self::task_marker => "task checkbox",
+                Self::star_bullet_item_tight
+                | Self::dash_bullet_item_tight
+                | Self::plus_bullet_item_tight
+                | Self::star_bullet_item
+                | Self::dash_bullet_item
+                | Self::plus_bullet_item => "bullet list item",
+                Self::period_ordered_list_item_tight
+                | Self::parenthesis_ordered_list_item_tight
+                | Self::period_ordered_list_item
+                | Self::parenthesis_ordered_list_item => "ordered list item",
+                Self::list_item_contents | Self::list_item_contents_tight => "list item contents",
+                Self::list_tight | Self::list_loose => "list",
+                Self::bullet_list => "bullet list",
+                Self::ordered_list => "ordered list",
+                Self::strong => "strong emphasis",
+                Self::emphasis => "emphasis",
+                Self::subscript => "subscript",
+                Self::superscript => "superscript",
+                Self::highlight => "highlight",
+                Self::inline_math => "inline math",
+                Self::display_math => "display math",
+                Self::math_expression | Self::display_math_expression => "math expression",
+                Self::info_string_language => "code block language",
+                Self::code => "code span",
+                Self::tilde_fenced_codeblock | Self::fenced_codeblock => "fenced code block",
+                Self::indented_codeblock => "indented code block",
+                Self::reference => "link reference definition",
+                Self::line_block => "line block",
+                Self::line_block_line => "line",
+                Self::line_block_indent => "line indentation",
+                Self::footnote_label => "footnote label",
+                Self::footnote_reference => "footnote reference",
+                Self::footnote_definition => "footnote definition",
+                Self::include => "include directive",
+                Self::include_name => "include name",
+                Self::shortcut_reference_link | Self::full_reference_link => "reference link",
+                Self::directed_link => "inline link",
+                Self::autolink => "autolink",
+                Self::autolink_uri | Self::autolink_email => "autolink destination",
+                Self::link => "link",
+                Self::image => "image",
+                // Silent rules never produce tokens, so they won't show up in errors
+                _ => "markdown",
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use crate::error::ParseError;
+        use pest::Parser;
+
+        #[test]
+        pub fn human_name_error_test() {
+            let error = MarkdownParser::parse(Rule::period_ordered_list_item, "- Not ordered")
+                .map(|_| ())
+                .map_err(ParseError::from)
+                .expect_err("Expected the bullet item to fail to parse as an ordered item");
+            let message = error.to_string();
+            assert!(
+                message.contains("expected list index"),
+                "Missing friendly rule name in: {message}"
+            );
+            assert!(!message.contains("list_index"));
+        }
+
+        #[test]
+        pub fn unique_rule_names_test() {
+            let error = MarkdownParser::parse(Rule::document, "a\n\n  ")
+                .map(|_| ())
+                .map_err(ParseError::from)
+                .expect_err("Expected trailing spaces to fail to parse");
+            let message = error.to_string();
+            let expected = message
+                .split("expected ")
+                .nth(1)
+                .unwrap_or_else(|| panic!("No expected rules in: {message}"));
+            let names: Vec<_> = expected
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .split(", ")
+                .flat_map(|name| name.split(" or "))
+                .map(|name| name.trim_start_matches("or ").trim())
+                .filter(|name| !name.is_empty())
+                .collect();
+            assert!(names.len() > 1, "Expected several rules in: {message}");
+            for (i, name) in names.iter().enumerate() {
+                assert!(
+                    !names[..i].contains(name),
+                    "{name:?} is repeated in: {message}"
+                );
+            }
+        }
     }
 }
 
 pub mod error {
     use crate::parser::Rule;
+    use pest::error::ErrorVariant;
 
     #[derive(Debug)]
     pub enum ParseError {
@@ -28,11 +162,43 @@ pub mod error {
     }
 
     impl From<pest::error::Error<Rule>> for ParseError {
-        fn from(value: pest::error::Error<Rule>) -> Self {
+        fn from(mut value: pest::error::Error<Rule>) -> Self {
+            if let ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            } = &value.variant
+            {
+                let message = match (friendly_names(negatives), friendly_names(positives)) {
+                    (Some(negatives), Some(positives)) => {
+                        format!("unexpected {negatives}; expected {positives}")
+                    }
+                    (Some(negatives), None) => format!("unexpected {negatives}"),
+                    (None, Some(positives)) => format!("expected {positives}"),
+                    (None, None) => "unknown parsing error".to_owned(),
+                };
+                value.variant = ErrorVariant::CustomError { message };
+            }
             ParseError::TokenizationError(value.to_string())
         }
     }
 
+    /// List the friendly names of `rules` like pest does, as in `a, b, or c`. Many rules share a
+    /// name, like the kinds of line break, so each name is only listed the first time it comes up.
+    fn friendly_names(rules: &[Rule]) -> Option<String> {
+        let mut names: Vec<&str> = Vec::with_capacity(rules.len());
+        for name in rules.iter().map(Rule::human_name) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        match names.as_slice() {
+            [] => None,
+            [name] => Some((*name).to_owned()),
+            [first, second] => Some(format!("{first} or {second}")),
+            [rest @ .., last] => Some(format!("{}, or {last}", rest.join(", "))),
+        }
+    }
+
     impl From<core::num::ParseIntError> for ParseError {
         fn from(value: core::num::ParseIntError) -> Self {
             ParseError::SyntaxError(value.to_string())
@@ -49,18 +215,98 @@ pub mod error {
             }
         }
     }
+
+    impl std::error::Error for ParseError {}
+
+    /// An error from writing a tree out, either from the sink or because the tree's structure
+    /// didn't make sense to the renderer, like a list item outside of a list.
+    #[derive(Debug)]
+    pub enum RenderError {
+        IOError(String),
+        StateError(String),
+    }
+
+    impl From<std::io::Error> for RenderError {
+        fn from(value: std::io::Error) -> Self {
+            Self::IOError(value.to_string())
+        }
+    }
+
+    impl std::fmt::Display for RenderError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Self::IOError(e) => write!(f, "IOError ({e})"),
+                Self::StateError(e) => write!(f, "StateError ({e})"),
+            }
+        }
+    }
+
+    impl std::error::Error for RenderError {}
+
+    /// The error from functions that both parse and render a document, so that callers only have
+    /// one type of error to match on.
+    #[derive(Debug)]
+    pub enum MarkdownError {
+        Parse(ParseError),
+        Render(RenderError),
+    }
+
+    impl From<ParseError> for MarkdownError {
+        fn from(value: ParseError) -> Self {
+            Self::Parse(value)
+        }
+    }
+
+    impl From<RenderError> for MarkdownError {
+        fn from(value: RenderError) -> Self {
+            Self::Render(value)
+        }
+    }
+
+    impl std::fmt::Display for MarkdownError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Self::Parse(e) => write!(f, "{e}"),
+                Self::Render(e) => write!(f, "Failed to render document: {e}"),
+            }
+        }
+    }
+
+    impl std::error::Error for MarkdownError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                Self::Parse(e) => Some(e),
+                Self::Render(e) => Some(e),
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod test_utils {
     use std::{fs::read_to_string, path::PathBuf};
 
+    /// Read a fixture from `test_data`, with `file_name` always using `/` between directories.
+    /// Line endings are normalized to `\n`, so that checkouts that convert fixtures to CRLF, like
+    /// on Windows, still match the output of the tests.
+    #[allow(dead_code)]
     pub fn read_file_to_string(file_name: &str) -> String {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        path.push("test_data/");
-        path.push(file_name);
+        path.push("test_data");
+        path.extend(file_name.split('/'));
 
         read_to_string(&path)
+            .map(|contents| contents.replace("\r\n", "\n"))
             .unwrap_or_else(|e| panic!("Failed to read file {path:?} to string: {e}"))
     }
+
+    mod test {
+        use super::*;
+
+        #[test]
+        pub fn crlf_fixture_test() {
+            let contents = read_file_to_string("markdown/crlf.md");
+            assert_eq!(contents, "# Title\n\nSome text\nover two lines\n");
+        }
+    }
 }
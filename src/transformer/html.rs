@@ -1,31 +1,128 @@
-use crate::ast::model::{CodeBlock, Heading, Image, Link, List, Node, Reference};
-use crate::ast::traversal::{Direction, NextAction, Visitor};
-use std::cell::RefCell;
-use std::collections::HashMap;
+use crate::ast::model::{
+    normalize_label, BlockQuote, Code, CodeBlock, DisplayMath, Document, Emphasis, ErrorBlock,
+    FootnoteDefinition, FootnoteReference, Heading, Highlight, Image, InlineMath, Label, Line,
+    LineBlock, Linebreak, Link, List, ListItem, Node, Paragraph, Reference, SoftLinebreak, Strong,
+    Subscript, Superscript, Text, ThematicBreak,
+};
+use crate::ast::traversal::{Direction, NextAction, TraversalContext, Visitor};
+use crate::error::{MarkdownError, RenderError};
+use crate::transformer::is_collapsible_whitespace;
+use crate::transformer::render::Renderer;
+use crate::transformer::sink::Sink;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
-use std::io::Write;
 
+/// Finds the definitions of references for links and images. By default it's a [`Visitor`] that
+/// collects definitions into a hash map, which needs its own traversal before rendering. For
+/// documents known to have only a few references, [`LinkResolver::from_document`] is cheaper.
 #[derive(Default, Debug)]
 pub struct LinkResolver<'a> {
-    name_to_reference_table: RefCell<HashMap<&'a str, &'a Reference<'a>>>,
+    name_to_reference_table: RefCell<ReferenceTable<'a>>,
+}
+
+#[derive(Debug)]
+enum ReferenceTable<'a> {
+    Map(HashMap<String, &'a Reference<'a>>),
+    // A linear scan beats hashing the name when there are only a handful of entries
+    List(Vec<(String, &'a Reference<'a>)>),
+}
+
+impl<'a> Default for ReferenceTable<'a> {
+    fn default() -> Self {
+        Self::Map(HashMap::new())
+    }
 }
 
 impl<'a> LinkResolver<'a> {
+    /// Collect the references defined in `root` into a small list in a single pass over its
+    /// blocks, without a separate traversal. Names are looked up by scanning the list, so this
+    /// is best for documents with few references.
+    pub fn from_document(root: &'a Node<'a>) -> Self {
+        let resolver = Self {
+            name_to_reference_table: RefCell::new(ReferenceTable::List(Vec::new())),
+        };
+        for child in root {
+            if let Node::Reference(reference) = child {
+                resolver.insert(reference);
+            }
+        }
+        resolver
+    }
+
+    /// Find the definition for a reference. Like in CommonMark, names are matched case
+    /// insensitively and any runs of whitespace in them are treated as a single space.
     pub fn resolve(&self, name: &str) -> Option<&'a Reference<'a>> {
-        self.name_to_reference_table
-            .borrow()
-            .get(name)
-            .map(|reference| *reference)
+        let name = normalize_label(name);
+        match &*self.name_to_reference_table.borrow() {
+            ReferenceTable::Map(map) => map.get(&name).copied(),
+            ReferenceTable::List(list) => list
+                .iter()
+                .find_map(|(key, reference)| (*key == name).then_some(*reference)),
+        }
+    }
+
+    /// Find the reference links and images in `root` that have no definition in this resolver,
+    /// like `[x]` without an `[x]: /url` anywhere. These are rendered as the text they were
+    /// written as, so they usually mean a definition is missing or misspelled.
+    ///
+    /// ### Returns
+    /// The unresolved references in document order, which is empty if everything resolved.
+    pub fn unresolved<'b>(&self, root: &'b Node<'b>) -> Vec<UnresolvedReference<'b>> {
+        root.descendants_with_depth()
+            .filter_map(|(_, node)| match node {
+                Node::Link(link) if link.is_reference() => Some((link.source(), link.as_span())),
+                Node::Image(image) if image.is_reference() => {
+                    Some((image.source(), image.as_span()))
+                }
+                _ => None,
+            })
+            .filter(|(label, _)| self.resolve(label).is_none())
+            .map(|(label, span)| UnresolvedReference { label, span })
+            .collect()
+    }
+
+    /// Add a definition. Only the first definition of a name is kept.
+    fn insert(&self, reference: &'a Reference<'a>) {
+        let name = reference.normalized_name();
+        match &mut *self.name_to_reference_table.borrow_mut() {
+            ReferenceTable::Map(map) => {
+                map.entry(name).or_insert(reference);
+            }
+            ReferenceTable::List(list) => {
+                if list.iter().all(|(key, _)| *key != name) {
+                    list.push((name, reference));
+                }
+            }
+        }
+    }
+}
+
+/// A reference link or image whose name has no definition, found by [`LinkResolver::unresolved`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnresolvedReference<'a> {
+    label: &'a str,
+    span: &'a str,
+}
+
+impl<'a> UnresolvedReference<'a> {
+    /// The name that was looked up, as it was written.
+    pub fn label(&self) -> &'a str {
+        self.label
+    }
+
+    /// The slice of the input for the link, or the description of the image.
+    pub fn span(&self) -> &'a str {
+        self.span
     }
 }
 
 impl<'a> Visitor<'a> for LinkResolver<'a> {
-    fn visit(&self, node: &'a Node<'a>, _direction: Direction) -> NextAction {
+    fn visit(&self, node: &'a Node<'a>, _: Direction, _: &TraversalContext<'a>) -> NextAction {
         match node {
             Node::Reference(reference) => {
-                self.name_to_reference_table
-                    .borrow_mut()
-                    .insert(reference.name(), reference);
+                self.insert(reference);
                 NextAction::GotoNext
             }
             Node::Document(_) => NextAction::GotoNext,
@@ -37,6 +134,7 @@ impl<'a> Visitor<'a> for LinkResolver<'a> {
 #[derive(Debug)]
 struct ListContext {
     tight: bool,
+    ordered: bool,
     _start: u32,
 }
 
@@ -44,6 +142,7 @@ impl<'a> From<&List<'a>> for ListContext {
     fn from(value: &List<'a>) -> Self {
         Self {
             tight: value.tight(),
+            ordered: value.ordered(),
             _start: value.start(),
         }
     }
@@ -52,6 +151,33 @@ impl<'a> From<&List<'a>> for ListContext {
 #[derive(Debug, Default)]
 struct GenerationContext {
     list_context: Vec<ListContext>,
+    in_heading: bool,
+    // Every id that headings have been given or that the author gave one
+    ids: HashSet<String>,
+    // How many lines of the current line block have been started
+    lines: usize,
+    defined_footnotes: HashSet<String>,
+    // The footnotes in the order they were first used, with how many times each was used
+    used_footnotes: Vec<(String, usize)>,
+    // Where the document being rendered starts in memory and the offsets of the lines in it, for
+    // finding which line of the source a node is on
+    source_start: usize,
+    source_len: usize,
+    line_starts: Vec<usize>,
+    // The headings that were given an id, for a table of contents
+    toc: Vec<TocEntry>,
+}
+
+/// A heading in a document's table of contents, from [`render_to_html_with_toc`] or
+/// [`HTMLRenderer::take_toc`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TocEntry {
+    /// The heading's level, from 1 for `#` to 6 for `######`.
+    pub level: u8,
+    /// The plain text of the heading, without any markup.
+    pub text: String,
+    /// The `id` that the heading was given in the HTML, for linking to it with `#anchor`.
+    pub anchor: String,
 }
 
 impl GenerationContext {
@@ -68,48 +194,433 @@ impl GenerationContext {
         self.list_context.pop();
         self
     }
+
+    pub fn set_in_heading(&mut self, in_heading: bool) -> &Self {
+        self.in_heading = in_heading;
+        self
+    }
+
+    pub fn in_heading(&self) -> bool {
+        self.in_heading
+    }
+
+    pub fn start_line_block(&mut self) -> &Self {
+        self.lines = 0;
+        self
+    }
+
+    /// Start the next line of the current line block, returning whether it's the first one.
+    pub fn start_line(&mut self) -> bool {
+        self.lines += 1;
+        self.lines == 1
+    }
+
+    pub fn define_footnote(&mut self, label: &str) -> &Self {
+        self.defined_footnotes.insert(normalize_label(label));
+        self
+    }
+
+    /// Record a use of a footnote, returning the footnote's number and how many times it's been
+    /// used including this one, or `None` if the footnote was never defined.
+    pub fn use_footnote(&mut self, label: &str) -> Option<(usize, usize)> {
+        let label = normalize_label(label);
+        if !self.defined_footnotes.contains(&label) {
+            return None;
+        }
+        let index = match self
+            .used_footnotes
+            .iter()
+            .position(|(used, _)| *used == label)
+        {
+            Some(index) => index,
+            None => {
+                self.used_footnotes.push((label, 0));
+                self.used_footnotes.len() - 1
+            }
+        };
+        self.used_footnotes[index].1 += 1;
+        Some((index + 1, self.used_footnotes[index].1))
+    }
+
+    /// The label of the `number`th footnote to be used and how many times it was used.
+    pub fn used_footnote(&self, number: usize) -> Option<(String, usize)> {
+        self.used_footnotes.get(number.checked_sub(1)?).cloned()
+    }
+
+    pub fn set_source(&mut self, source: &str) -> &Self {
+        self.source_start = source.as_ptr() as usize;
+        self.source_len = source.len();
+        self.line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        self
+    }
+
+    /// The line of the source that `span` starts on, counting from 1, or `None` if the span isn't
+    /// part of the source.
+    pub fn line_of(&self, span: &str) -> Option<usize> {
+        let offset = (span.as_ptr() as usize).checked_sub(self.source_start)?;
+        (offset <= self.source_len)
+            .then(|| self.line_starts.partition_point(|&start| start <= offset))
+    }
+
+    /// Keep an id that the author gave a heading, so that no generated id repeats it.
+    pub fn reserve_id(&mut self, id: &str) -> &Self {
+        self.ids.insert(id.to_owned());
+        self
+    }
+
+    /// Make an `id` unique within the document by numbering it, like `intro-1`, until it doesn't
+    /// repeat any id that was already used or reserved.
+    pub fn unique_id(&mut self, id: String) -> String {
+        let mut unique = id.clone();
+        let mut n = 0;
+        while self.ids.contains(&unique) {
+            n += 1;
+            unique = format!("{id}-{n}");
+        }
+        self.ids.insert(unique.clone());
+        unique
+    }
+}
+
+/// A set of inline constructs for [`HTMLRenderer::with_inline_features`] to render, like only
+/// allowing emphasis and links in comments. Constructs that aren't in the set are written as the
+/// markdown they were parsed from instead. Sets are combined with `|` and removed with `-`:
+///
+/// ```
+/// use pegmd::transformer::InlineFeatures;
+///
+/// let features = InlineFeatures::ALL - InlineFeatures::IMAGES;
+/// assert!(features.contains(InlineFeatures::LINKS | InlineFeatures::EMPHASIS));
+/// assert!(!features.contains(InlineFeatures::IMAGES));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InlineFeatures(u16);
+
+impl InlineFeatures {
+    pub const EMPHASIS: Self = Self(1);
+    pub const STRONG: Self = Self(1 << 1);
+    pub const CODE: Self = Self(1 << 2);
+    /// Links of every kind, including references and autolinks.
+    pub const LINKS: Self = Self(1 << 3);
+    pub const IMAGES: Self = Self(1 << 4);
+    pub const SUBSCRIPT: Self = Self(1 << 5);
+    pub const SUPERSCRIPT: Self = Self(1 << 6);
+    pub const HIGHLIGHT: Self = Self(1 << 7);
+    pub const MATH: Self = Self(1 << 8);
+    pub const FOOTNOTES: Self = Self(1 << 9);
+    pub const NONE: Self = Self(0);
+    pub const ALL: Self = Self((1 << 10) - 1);
+
+    /// Whether every feature in `other` is also in this set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The feature that controls how `node` is rendered, if it's an inline construct that can be
+    /// turned off.
+    fn of(node: &Node) -> Option<Self> {
+        match node {
+            Node::Emphasis(_) => Some(Self::EMPHASIS),
+            Node::Strong(_) => Some(Self::STRONG),
+            Node::Code(_) => Some(Self::CODE),
+            Node::Link(_) => Some(Self::LINKS),
+            Node::Image(_) => Some(Self::IMAGES),
+            Node::Subscript(_) => Some(Self::SUBSCRIPT),
+            Node::Superscript(_) => Some(Self::SUPERSCRIPT),
+            Node::Highlight(_) => Some(Self::HIGHLIGHT),
+            Node::InlineMath(_) => Some(Self::MATH),
+            Node::FootnoteReference(_) => Some(Self::FOOTNOTES),
+            _ => None,
+        }
+    }
 }
 
-pub enum RenderError {
-    IOError(String),
-    StateError(String),
+impl Default for InlineFeatures {
+    fn default() -> Self {
+        Self::ALL
+    }
 }
 
-impl From<std::io::Error> for RenderError {
-    fn from(value: std::io::Error) -> Self {
-        Self::IOError(value.to_string())
+impl std::ops::BitOr for InlineFeatures {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
     }
 }
 
-impl std::fmt::Display for RenderError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::IOError(e) => write!(f, "IOError ({e})"),
-            Self::StateError(e) => write!(f, "StateError ({e})"),
+impl std::ops::Sub for InlineFeatures {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(self.0 & !rhs.0)
+    }
+}
+
+/// The options of an [`HTMLRenderer`], which can be kept around to render many documents the same
+/// way, like the comments on a page. Each field is documented on the `with_*` method that sets it.
+///
+/// ```
+/// use pegmd::transformer::RendererConfig;
+///
+/// let config = RendererConfig {
+///     presentational_emphasis: true,
+///     ..Default::default()
+/// };
+/// let html = config.render("Some *text*").unwrap();
+/// assert_eq!(html, "<!DOCTYPE html><html><p>Some <i>text</i></p></html>");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RendererConfig {
+    /// See [`HTMLRenderer::with_text_limit`].
+    pub text_limit: Option<usize>,
+    /// See [`HTMLRenderer::with_explicit_li_values`].
+    pub explicit_li_values: bool,
+    /// See [`HTMLRenderer::with_xhtml`].
+    pub xhtml: bool,
+    /// See [`HTMLRenderer::with_commonmark_whitespace`].
+    pub commonmark_whitespace: bool,
+    /// See [`HTMLRenderer::with_external_link_attrs`].
+    pub site_host: Option<String>,
+    /// See [`HTMLRenderer::with_external_links_in_new_tab`].
+    pub external_links_in_new_tab: bool,
+    /// See [`HTMLRenderer::with_code_line_numbers`].
+    pub code_line_numbers: bool,
+    /// See [`HTMLRenderer::with_default_code_language`].
+    pub default_code_language: Option<String>,
+    /// See [`HTMLRenderer::with_force_tight_lists`].
+    pub force_tight_lists: bool,
+    /// See [`HTMLRenderer::with_presentational_emphasis`].
+    pub presentational_emphasis: bool,
+    /// See [`HTMLRenderer::with_math_delimiters`].
+    pub math_delimiters: bool,
+    /// See [`HTMLRenderer::with_heading_ids`].
+    pub heading_ids: bool,
+    /// See [`HTMLRenderer::with_id_prefix`].
+    pub id_prefix: String,
+    /// See [`HTMLRenderer::with_flush_per_block`].
+    pub flush_per_block: bool,
+    /// See [`HTMLRenderer::with_empty_paragraphs`].
+    pub empty_paragraphs: bool,
+    /// See [`HTMLRenderer::with_interactive_tasks`].
+    pub interactive_tasks: bool,
+    /// See [`HTMLRenderer::with_quote_citations`].
+    pub quote_citations: bool,
+    /// See [`HTMLRenderer::with_galleries`].
+    pub galleries: bool,
+    /// See [`HTMLRenderer::with_inline_features`].
+    pub inline_features: InlineFeatures,
+    /// See [`HTMLRenderer::with_email_styles`].
+    pub email_styles: Option<HashMap<String, String>>,
+}
+
+impl RendererConfig {
+    /// Parse a document and render it to HTML with these options, resolving any references that
+    /// it defines like [`render_to_html`]. Every call gets a renderer and buffer of its own, so
+    /// nothing carries over from one document to the next.
+    ///
+    /// ### Parameters
+    /// - `input` - The markdown source.
+    ///
+    /// ### Returns
+    /// A result that on success contains the HTML, and on failure a [`MarkdownError`] for the
+    /// step that failed.
+    pub fn render(&self, input: &str) -> Result<String, MarkdownError> {
+        let root = crate::ast::parse_document(input)?;
+        let renderer = HTMLRenderer::with_resolver(resolver_for(&root)).with_config(self.clone());
+        finish(&root, renderer)
+    }
+}
+
+// Builder methods for the options that are just switches, documented by the renderer's method
+macro_rules! builder_flags {
+    ($(($name: ident, $with: ident)),+ $(,)?) => {
+        $(
+            #[doc = concat!("See [`HTMLRenderer::", stringify!($with), "`].")]
+            pub fn $name(mut self, $name: bool) -> Self {
+                self.config.$name = $name;
+                self
+            }
+        )+
+    };
+}
+
+/// Builds an [`HTMLRenderer`] with all of its options, its link resolver, and its sink set in one
+/// place, as an alternative to chaining `with_*` calls onto a renderer. Each method is named
+/// after the renderer method that it stands in for, without the `with_`.
+///
+/// ```
+/// use pegmd::ast::parse_document;
+/// use pegmd::transformer::{HTMLRendererBuilder, LinkResolver};
+///
+/// let root = parse_document("# Title\n\n[Home][home]\n\n[home]: /").unwrap();
+/// let renderer = HTMLRendererBuilder::new()
+///     .resolver(LinkResolver::from_document(&root))
+///     .heading_ids(true)
+///     .commonmark_whitespace(true)
+///     .build();
+/// root.traverse(&renderer);
+/// assert_eq!(
+///     renderer.to_string(),
+///     "<h1 id=\"title\">Title</h1>\n<p><a href=\"/\">Home</a></p>\n"
+/// );
+/// ```
+#[derive(Debug)]
+pub struct HTMLRendererBuilder<'a, S: Sink = Vec<u8>> {
+    sink: S,
+    resolver: LinkResolver<'a>,
+    config: RendererConfig,
+}
+
+impl<'a> Default for HTMLRendererBuilder<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> HTMLRendererBuilder<'a> {
+    /// Start building a renderer with the default options, no references, and an in-memory
+    /// buffer for its output.
+    pub fn new() -> Self {
+        Self {
+            sink: Vec::new(),
+            resolver: LinkResolver::default(),
+            config: RendererConfig::default(),
+        }
+    }
+}
+
+impl<'a, S: Sink> HTMLRendererBuilder<'a, S> {
+    /// Create the renderer with everything that's been set.
+    pub fn build(self) -> HTMLRenderer<'a, S> {
+        HTMLRenderer {
+            link_table: self.resolver,
+            config: self.config,
+            ..HTMLRenderer::with_sink(self.sink)
+        }
+    }
+
+    /// Write the output to `sink` instead. See [`HTMLRenderer::with_sink`].
+    pub fn sink<T: Sink>(self, sink: T) -> HTMLRendererBuilder<'a, T> {
+        HTMLRendererBuilder {
+            sink,
+            resolver: self.resolver,
+            config: self.config,
         }
     }
+
+    /// See [`HTMLRenderer::with_resolver`].
+    pub fn resolver(mut self, resolver: LinkResolver<'a>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Replace all of the options with `config`. See [`HTMLRenderer::with_config`].
+    pub fn config(mut self, config: RendererConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// See [`HTMLRenderer::with_text_limit`].
+    pub fn text_limit(mut self, limit: usize) -> Self {
+        self.config.text_limit = Some(limit);
+        self
+    }
+
+    /// See [`HTMLRenderer::with_external_link_attrs`].
+    pub fn external_link_attrs(mut self, site_host: &str) -> Self {
+        self.config.site_host = Some(site_host.to_owned());
+        self
+    }
+
+    /// See [`HTMLRenderer::with_default_code_language`].
+    pub fn default_code_language(mut self, language: Option<&str>) -> Self {
+        self.config.default_code_language = language.map(str::to_owned);
+        self
+    }
+
+    /// See [`HTMLRenderer::with_id_prefix`].
+    pub fn id_prefix(mut self, prefix: &str) -> Self {
+        self.config.id_prefix = prefix.to_owned();
+        self
+    }
+
+    /// See [`HTMLRenderer::with_email_styles`].
+    pub fn email_styles<'s>(
+        mut self,
+        styles: impl IntoIterator<Item = (&'s str, &'s str)>,
+    ) -> Self {
+        let styles = styles
+            .into_iter()
+            .map(|(tag, style)| (tag.to_owned(), style.to_owned()))
+            .collect();
+        self.config.email_styles = Some(styles);
+        self
+    }
+
+    /// See [`HTMLRenderer::with_inline_features`].
+    pub fn inline_features(mut self, features: InlineFeatures) -> Self {
+        self.config.inline_features = features;
+        self
+    }
+
+    builder_flags!(
+        (explicit_li_values, with_explicit_li_values),
+        (xhtml, with_xhtml),
+        (commonmark_whitespace, with_commonmark_whitespace),
+        (external_links_in_new_tab, with_external_links_in_new_tab),
+        (code_line_numbers, with_code_line_numbers),
+        (force_tight_lists, with_force_tight_lists),
+        (presentational_emphasis, with_presentational_emphasis),
+        (math_delimiters, with_math_delimiters),
+        (heading_ids, with_heading_ids),
+        (flush_per_block, with_flush_per_block),
+        (empty_paragraphs, with_empty_paragraphs),
+        (interactive_tasks, with_interactive_tasks),
+        (quote_citations, with_quote_citations),
+        (galleries, with_galleries),
+    );
 }
 
-/// An implementation of [`Visitor`] that generates HTML from AST.
-#[derive(Default)]
-pub struct HTMLRenderer<'a> {
-    output: RefCell<Vec<u8>>,
+/// An implementation of [`Visitor`] that generates HTML from AST. The output is written to a
+/// [`Sink`], which defaults to an in-memory byte buffer.
+///
+/// Since visitors only get a shared reference, the renderer keeps its state in [`RefCell`]s and
+/// isn't [`Sync`]. It is [`Send`] as long as its sink is, so a renderer can be moved to another
+/// thread or task to do its work there, but each renderer should only be used by one at a time.
+pub struct HTMLRenderer<'a, S: Sink = Vec<u8>> {
+    output: RefCell<S>,
     context: RefCell<GenerationContext>,
     link_table: LinkResolver<'a>,
+    config: RendererConfig,
+    emitted_chars: Cell<usize>,
+    // The first error that stopped rendering
+    error: RefCell<Option<RenderError>>,
+    // How many blocks are open, counting the document, so that top-level blocks can be found
+    block_depth: Cell<usize>,
+}
+
+impl<'a> Default for HTMLRenderer<'a> {
+    fn default() -> Self {
+        Self::with_sink(Vec::new())
+    }
 }
 
 /// A slightly nicer debug implementation that converts the output to a string rather than
 /// writing the raw hex bytes.
-impl<'a> std::fmt::Debug for HTMLRenderer<'a> {
+impl<'a, S: Sink + AsRef<[u8]>> std::fmt::Debug for HTMLRenderer<'a, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Ok(s) = std::str::from_utf8(self.output.borrow().as_slice()) {
+        let output = self.output.borrow();
+        if let Ok(s) = std::str::from_utf8(output.as_ref()) {
             f.debug_struct("HTMLRenderer")
                 .field("output", &s)
                 .field("context", &self.context)
                 .finish()
         } else {
             f.debug_struct("HTMLRenderer")
-                .field("output", &self.output)
+                .field("output", &output.as_ref())
                 .field("context", &self.context)
                 .finish()
         }
@@ -127,10 +638,288 @@ impl<'a> HTMLRenderer<'a> {
     /// Create an HTML renderer with a pre-allocated buffer, ensuring that it will
     /// be able to hold at least `capacity` bytes without reallocating.
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_sink(Vec::with_capacity(capacity))
+    }
+
+    /// Consume the renderer, moving the rendered HTML out of it as bytes. The output is always
+    /// valid UTF-8, so this skips the validation and copy that going through [`Display`] costs.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.into_sink()
+    }
+}
+
+impl<'a, S: Sink> HTMLRenderer<'a, S> {
+    /// Create an HTML renderer that writes its output to the provided sink.
+    pub fn with_sink(sink: S) -> Self {
         Self {
-            output: RefCell::new(Vec::with_capacity(capacity)),
-            ..Default::default()
+            output: RefCell::new(sink),
+            context: RefCell::default(),
+            link_table: LinkResolver::default(),
+            config: RendererConfig::default(),
+            emitted_chars: Cell::new(0),
+            error: RefCell::new(None),
+            block_depth: Cell::new(0),
+        }
+    }
+
+    /// Consume the renderer, returning the sink that it wrote to.
+    pub fn into_sink(self) -> S {
+        self.output.into_inner()
+    }
+
+    /// Take the error that stopped the renderer, if writing the tree failed. The output only has
+    /// what was written before the error.
+    pub fn take_error(&self) -> Option<RenderError> {
+        self.error.borrow_mut().take()
+    }
+
+    /// Take the headings that were given an `id`, in the order they were written, for building a
+    /// table of contents whose links match the HTML. Headings only have ids when
+    /// [`HTMLRenderer::with_heading_ids`] is on or when the author gave them one.
+    pub fn take_toc(&self) -> Vec<TocEntry> {
+        std::mem::take(&mut self.context.borrow_mut().toc)
+    }
+
+    /// Replace all of the renderer's options with `config`.
+    pub fn with_config(mut self, config: RendererConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// The options that the renderer was configured with.
+    pub fn config(&self) -> &RendererConfig {
+        &self.config
+    }
+
+    /// Stop rendering once `limit` characters of visible text have been emitted, appending an
+    /// ellipsis if any text was cut off. Any tags that are open at that point are still closed,
+    /// so the output remains well formed. Useful for generating previews of longer documents.
+    pub fn with_text_limit(mut self, limit: usize) -> Self {
+        self.config.text_limit = Some(limit);
+        self
+    }
+
+    /// Give each item of an ordered list a `value` attribute with the index that was written in
+    /// the source, rather than relying on the list's `start` attribute and sequential numbering.
+    pub fn with_explicit_li_values(mut self, explicit_li_values: bool) -> Self {
+        self.config.explicit_li_values = explicit_li_values;
+        self
+    }
+
+    /// Write void elements like `<br>` in their XHTML self-closing form, `<br />`. By default,
+    /// void elements are written in HTML5 style without the trailing slash.
+    pub fn with_xhtml(mut self, xhtml: bool) -> Self {
+        self.config.xhtml = xhtml;
+        self
+    }
+
+    /// Emit output formatted like the CommonMark reference implementation, `cmark`. Block elements
+    /// are followed by a newline, soft breaks are kept as newlines, and the doctype and `<html>`
+    /// wrapper are omitted so that the output can be compared against the CommonMark spec tests.
+    /// Combine this with [`HTMLRenderer::with_xhtml`] to match the reference output exactly.
+    pub fn with_commonmark_whitespace(mut self, commonmark_whitespace: bool) -> Self {
+        self.config.commonmark_whitespace = commonmark_whitespace;
+        self
+    }
+
+    /// Mark links to hosts other than `site_host` with `rel="noopener noreferrer"`, so that pages
+    /// linked from user content can't access the page that linked to them. Relative links and links
+    /// to `site_host` are left untouched.
+    pub fn with_external_link_attrs(mut self, site_host: &str) -> Self {
+        self.config.site_host = Some(site_host.to_owned());
+        self
+    }
+
+    /// Also give external links `target="_blank"` so that they open in a new tab. This only has an
+    /// effect once the site's host is set with [`HTMLRenderer::with_external_link_attrs`].
+    pub fn with_external_links_in_new_tab(mut self, new_tab: bool) -> Self {
+        self.config.external_links_in_new_tab = new_tab;
+        self
+    }
+
+    /// Wrap each line of a code block in a `<span class="line">`, so that stylesheets can number
+    /// the lines with a CSS counter.
+    pub fn with_code_line_numbers(mut self, code_line_numbers: bool) -> Self {
+        self.config.code_line_numbers = code_line_numbers;
+        self
+    }
+
+    /// Treat code blocks without an info string as if they were written in `language`, so that
+    /// they get the same `language-` class as labeled blocks. Blocks with their own language keep
+    /// it.
+    pub fn with_default_code_language(mut self, language: Option<&str>) -> Self {
+        self.config.default_code_language = language.map(str::to_owned);
+        self
+    }
+
+    /// Render every list as if it were tight, without wrapping the contents of its items in `<p>`
+    /// tags, even if the items are separated by blank lines in the source. Useful for compact
+    /// output like UI previews.
+    pub fn with_force_tight_lists(mut self, force_tight_lists: bool) -> Self {
+        self.config.force_tight_lists = force_tight_lists;
+        self
+    }
+
+    /// Write emphasis and strong emphasis with the presentational `<i>` and `<b>` tags instead of
+    /// the semantic `<em>` and `<strong>` tags, for themes that style those instead.
+    pub fn with_presentational_emphasis(mut self, presentational_emphasis: bool) -> Self {
+        self.config.presentational_emphasis = presentational_emphasis;
+        self
+    }
+
+    /// Wrap math expressions in `\(…\)` and `\[…\]` delimiters inside of their elements, so that
+    /// they're picked up by the auto-render extensions of libraries like MathJax and KaTeX. By
+    /// default, the expression is written into the element as is.
+    pub fn with_math_delimiters(mut self, math_delimiters: bool) -> Self {
+        self.config.math_delimiters = math_delimiters;
+        self
+    }
+
+    /// Give each heading an `id` derived from its text, like `getting-started` for a heading of
+    /// "Getting Started", so that it can be linked to. Headings with the same text are numbered to
+    /// keep their ids unique.
+    pub fn with_heading_ids(mut self, heading_ids: bool) -> Self {
+        self.config.heading_ids = heading_ids;
+        self
+    }
+
+    /// Start every generated `id` with `prefix`, so that the ids of multiple documents rendered
+    /// onto the same page don't collide.
+    pub fn with_id_prefix(mut self, prefix: &str) -> Self {
+        self.config.id_prefix = prefix.to_owned();
+        self
+    }
+
+    /// Flush the sink after each top-level block is written rather than only when the caller is
+    /// done, so that a long document streamed to a client like a socket starts showing up sooner.
+    /// Only sinks that buffer, like an [`IoSink`](crate::transformer::sink::IoSink) around a
+    /// buffered writer, do anything when flushed.
+    pub fn with_flush_per_block(mut self, flush_per_block: bool) -> Self {
+        self.config.flush_per_block = flush_per_block;
+        self
+    }
+
+    /// Write paragraphs that have nothing in them besides whitespace as `<p></p>`, like earlier
+    /// versions did, instead of leaving them out.
+    pub fn with_empty_paragraphs(mut self, empty_paragraphs: bool) -> Self {
+        self.config.empty_paragraphs = empty_paragraphs;
+        self
+    }
+
+    /// Leave the checkboxes of task items like `- [ ] todo` enabled, and give each of them a
+    /// `data-task-line` attribute with the line of the source that the item starts on, counting
+    /// from 1. Pages can use it to write a click on a checkbox back to the source. By default,
+    /// checkboxes are `disabled`.
+    pub fn with_interactive_tasks(mut self, interactive_tasks: bool) -> Self {
+        self.config.interactive_tasks = interactive_tasks;
+        self
+    }
+
+    /// Write a last line like `— Author` in a block quote as a `<cite>` after the rest of the
+    /// quote, which is wrapped in a `<p>`. The line has to start with an em dash and be plain
+    /// text. By default, the line is written as part of the quote.
+    pub fn with_quote_citations(mut self, quote_citations: bool) -> Self {
+        self.config.quote_citations = quote_citations;
+        self
+    }
+
+    /// Write a paragraph that's only images, with nothing but whitespace between them, as a
+    /// `<div class="gallery">` of the images instead of a `<p>`, like for a photo post. There have
+    /// to be at least two images, so a paragraph with a single image is written as usual.
+    pub fn with_galleries(mut self, galleries: bool) -> Self {
+        self.config.galleries = galleries;
+        self
+    }
+
+    /// Render for HTML email, where the output is put inside of another page and stylesheets are
+    /// often stripped. The doctype and `<html>` wrapper are left out, and each element named in
+    /// `styles` gets its style as a `style` attribute, like `("blockquote", "margin: 0")`.
+    pub fn with_email_styles<'s>(
+        mut self,
+        styles: impl IntoIterator<Item = (&'s str, &'s str)>,
+    ) -> Self {
+        let styles = styles
+            .into_iter()
+            .map(|(tag, style)| (tag.to_owned(), style.to_owned()))
+            .collect();
+        self.config.email_styles = Some(styles);
+        self
+    }
+
+    /// Only render the inline constructs in `features`, writing the rest as the markdown they
+    /// were parsed from, like `![x](y)` for an image when images aren't allowed. Everything is
+    /// rendered by default.
+    pub fn with_inline_features(mut self, features: InlineFeatures) -> Self {
+        self.config.inline_features = features;
+        self
+    }
+
+    /// Write a construct that isn't in the renderer's inline features as literal text, skipping
+    /// its children on the way in and writing nothing on the way out. Constructs that are allowed
+    /// get `None`.
+    fn disabled_feature<'s>(
+        &self,
+        feature: InlineFeatures,
+        action: &Direction,
+        source: impl FnOnce() -> Cow<'s, str>,
+    ) -> Option<NextAction> {
+        if self.config.inline_features.contains(feature) {
+            return None;
+        }
+        match action {
+            Direction::Entering => Some(
+                self.next_action(
+                    self.text(&source())
+                        .map(|next_action| next_action.and(NextAction::SkipChildren)),
+                ),
+            ),
+            Direction::Exiting => Some(NextAction::GotoNext),
+        }
+    }
+
+    /// Render an inline construct with `render` if it's in the renderer's inline features, or as
+    /// literal text if it isn't.
+    fn gated<'s>(
+        &self,
+        feature: InlineFeatures,
+        action: &Direction,
+        source: impl FnOnce() -> Cow<'s, str>,
+        render: impl FnOnce() -> NextAction,
+    ) -> NextAction {
+        self.disabled_feature(feature, action, source)
+            .unwrap_or_else(render)
+    }
+
+    fn is_external(&self, source: &str) -> bool {
+        match (&self.config.site_host, link_host(source)) {
+            (Some(site_host), Some(host)) => !host.eq_ignore_ascii_case(site_host),
+            _ => false,
+        }
+    }
+
+    /// Writes a newline if the output should be formatted like the CommonMark reference output.
+    fn block_separator(&self) -> Result<(), RenderError> {
+        if self.config.commonmark_whitespace {
+            writeln!(self.output.borrow_mut())?;
+        }
+        Ok(())
+    }
+
+    fn open_block(&self) {
+        self.block_depth.set(self.block_depth.get() + 1);
+    }
+
+    /// Finish writing a block, flushing the sink if it's a top-level block and flushing per block
+    /// is on. Blocks with children must have been started with `open_block`.
+    fn close_block(&self, container: bool) -> Result<(), RenderError> {
+        if container {
+            self.block_depth
+                .set(self.block_depth.get().saturating_sub(1));
+        }
+        if self.config.flush_per_block && self.block_depth.get() <= 1 {
+            self.output.borrow_mut().flush()?;
         }
+        Ok(())
     }
 
     fn tag_with_attrs(
@@ -144,8 +933,17 @@ impl<'a> HTMLRenderer<'a> {
         for (name, value) in attrs {
             write!(self.output.borrow_mut(), r#" {name}="{value}""#)?;
         }
-        if close {
-            write!(self.output.borrow_mut(), "/>")?;
+        // A style from the author, like in a heading's attributes, wins over the configured one
+        let style = self
+            .config
+            .email_styles
+            .as_ref()
+            .and_then(|styles| styles.get(tag));
+        if let Some(style) = style.filter(|_| attrs.iter().all(|(name, _)| *name != "style")) {
+            write!(self.output.borrow_mut(), r#" style="{style}""#)?;
+        }
+        if close && self.config.xhtml {
+            write!(self.output.borrow_mut(), " />")?;
         } else {
             write!(self.output.borrow_mut(), ">")?;
         }
@@ -153,20 +951,50 @@ impl<'a> HTMLRenderer<'a> {
     }
 
     fn linebreak(&self) -> Result<(), RenderError> {
-        write!(self.output.borrow_mut(), "</br>")?;
+        // Headings are a single logical line, so breaks inside of them collapse to a space
+        if self.context.borrow().in_heading() {
+            write!(self.output.borrow_mut(), " ")?;
+        } else {
+            self.tag_with_attrs("br", &[], true)?;
+            self.block_separator()?;
+        }
         Ok(())
     }
 
     fn link(&self, link: &Link, action: Direction) -> Result<(), RenderError> {
+        let reference = self.link_table.resolve(link.source());
+        // References that were never defined aren't links, so show them as they were written
+        if reference.is_none() && link.is_reference() {
+            match action {
+                Direction::Entering => write!(self.output.borrow_mut(), "[")?,
+                Direction::Exiting => {
+                    write!(self.output.borrow_mut(), "]{}", reference_suffix(link))?
+                }
+            }
+            return Ok(());
+        }
+
         if let Direction::Entering = action {
-            let (source, title) = match self.link_table.resolve(link.source()) {
+            let (source, title) = match reference {
                 Some(reference) => (reference.source(), reference.title()),
                 None => (link.source(), link.title()),
             };
-            match title {
-                Some(t) => self.tag_with_attrs("a", &[("href", source), ("title", t)], false)?,
-                None => self.tag_with_attrs("a", &[("href", source)], false)?,
+            let href = if reference.is_none() && link.is_email_autolink() {
+                Cow::Owned(format!("mailto:{}", encode_destination(source)))
+            } else {
+                encode_destination(source)
             };
+            let mut attrs = vec![("href", href.as_ref())];
+            if let Some(t) = title {
+                attrs.push(("title", t));
+            }
+            if self.is_external(source) {
+                attrs.push(("rel", "noopener noreferrer"));
+                if self.config.external_links_in_new_tab {
+                    attrs.push(("target", "_blank"));
+                }
+            }
+            self.tag_with_attrs("a", &attrs, false)?;
         } else {
             write!(self.output.borrow_mut(), "</a>")?;
         }
@@ -174,48 +1002,254 @@ impl<'a> HTMLRenderer<'a> {
     }
 
     fn image(&self, image: &Image) -> Result<(), RenderError> {
-        let source = image.source();
-        let alt = image.as_span();
-        self.tag_with_attrs("img", &[("src", source), ("alt", alt)], true)
+        let alt_text = image.alt_text();
+        let alt = alt_text.as_ref();
+        let (source, title) = match self.link_table.resolve(image.source()) {
+            Some(reference) if image.is_reference() => (reference.source(), reference.title()),
+            // References that were never defined aren't images, so show them as they were written
+            None if image.is_reference() => {
+                return self.text(image.markdown()).map(|_| ());
+            }
+            _ => (image.source(), image.title()),
+        };
+        let source = encode_destination(source);
+        let source = source.as_ref();
+        match title {
+            Some(t) => {
+                self.tag_with_attrs("img", &[("src", source), ("alt", alt), ("title", t)], true)
+            }
+            None => self.tag_with_attrs("img", &[("src", source), ("alt", alt)], true),
+        }
     }
 
-    fn document(&self, action: Direction) -> Result<(), RenderError> {
+    fn document(&self, document: &Document, action: Direction) -> Result<(), RenderError> {
+        let wrap = !self.config.commonmark_whitespace && self.config.email_styles.is_none();
         if let Direction::Entering = action {
-            write!(self.output.borrow_mut(), "<!DOCTYPE html><html>")?;
+            self.open_block();
+            if self.config.interactive_tasks {
+                self.context.borrow_mut().set_source(document.as_span());
+            }
+            // Footnotes can be used before they're defined, so find them all up front
+            for child in document.children().iter() {
+                if let Node::FootnoteDefinition(definition) = child {
+                    self.context
+                        .borrow_mut()
+                        .define_footnote(definition.label());
+                }
+            }
+            // Same for the ids from the author, which generated ids can't take even if they come
+            // first
+            for (_, node) in document
+                .children()
+                .iter()
+                .flat_map(Node::descendants_with_depth)
+            {
+                if let Some(id) = match node {
+                    Node::Heading(heading) => heading.attributes().id(),
+                    _ => None,
+                } {
+                    self.context.borrow_mut().reserve_id(id);
+                }
+            }
+            if wrap {
+                write!(self.output.borrow_mut(), "<!DOCTYPE html><html>")?;
+            }
+            Ok(())
         } else {
-            write!(self.output.borrow_mut(), "</html>")?;
+            self.footnotes(document)?;
+            if wrap {
+                write!(self.output.borrow_mut(), "</html>")?;
+            }
+            self.close_block(true)
         }
-        Ok(())
     }
 
-    fn paragraph(&self, action: Direction) -> Result<(), RenderError> {
-        if let Direction::Entering = action {
-            write!(self.output.borrow_mut(), "<p>")?;
-        } else {
-            write!(self.output.borrow_mut(), "</p>")?;
+    fn footnote_reference(&self, reference: &FootnoteReference) -> Result<(), RenderError> {
+        let prefix = &self.config.id_prefix;
+        let number = self.context.borrow_mut().use_footnote(reference.label());
+        match number {
+            Some((number, 1)) => write!(
+                self.output.borrow_mut(),
+                r##"<sup class="footnote-ref"><a href="#{prefix}fn-{number}" id="{prefix}fnref-{number}">{number}</a></sup>"##
+            )?,
+            Some((number, nth)) => write!(
+                self.output.borrow_mut(),
+                r##"<sup class="footnote-ref"><a href="#{prefix}fn-{number}" id="{prefix}fnref-{number}-{nth}">{number}</a></sup>"##
+            )?,
+            // Footnotes that were never defined show up as they were written
+            None => write!(self.output.borrow_mut(), "{}", reference.as_span())?,
         }
         Ok(())
     }
 
-    fn heading(&self, heading: &Heading, action: Direction) -> Result<(), RenderError> {
-        if let Direction::Entering = action {
-            write!(self.output.borrow_mut(), "<h{}>", heading.level())?;
-        } else {
-            write!(self.output.borrow_mut(), "</h{}>", heading.level())?;
+    /// Write the footnotes that were used, in the order they were first used, with a link back to
+    /// each place that they were used.
+    fn footnotes(&self, document: &Document) -> Result<(), RenderError> {
+        let prefix = &self.config.id_prefix;
+        let mut number = 1;
+        // Footnotes can use other footnotes, so the list can grow while it's being written
+        loop {
+            let next = self.context.borrow().used_footnote(number);
+            let Some((label, _)) = next else {
+                break;
+            };
+            if number == 1 {
+                self.tag_with_attrs("section", &[("class", "footnotes")], false)?;
+                self.tag_with_attrs("ol", &[], false)?;
+                self.block_separator()?;
+            }
+            self.tag_with_attrs("li", &[("id", &format!("{prefix}fn-{number}"))], false)?;
+            let definition = document.children().iter().find_map(|child| match child {
+                Node::FootnoteDefinition(definition)
+                    if normalize_label(definition.label()) == label =>
+                {
+                    Some(definition)
+                }
+                _ => None,
+            });
+            for child in definition
+                .iter()
+                .flat_map(|definition| definition.children().iter())
+            {
+                if let NextAction::End = child.traverse(self) {
+                    break;
+                }
+            }
+            let uses = self
+                .context
+                .borrow()
+                .used_footnote(number)
+                .map_or(0, |(_, uses)| uses);
+            for nth in 1..=uses {
+                let (id, marker) = match nth {
+                    1 => (format!("{prefix}fnref-{number}"), String::new()),
+                    nth => (
+                        format!("{prefix}fnref-{number}-{nth}"),
+                        format!("<sup>{nth}</sup>"),
+                    ),
+                };
+                write!(
+                    self.output.borrow_mut(),
+                    r##" <a href="#{id}" class="footnote-backref">↩{marker}</a>"##
+                )?;
+            }
+            write!(self.output.borrow_mut(), "</li>")?;
+            self.block_separator()?;
+            number += 1;
+        }
+        if number > 1 {
+            write!(self.output.borrow_mut(), "</ol></section>")?;
+            self.block_separator()?;
         }
         Ok(())
     }
 
-    fn list(&self, list: &List, action: Direction) -> Result<(), RenderError> {
+    fn paragraph(
+        &self,
+        paragraph: &Paragraph,
+        action: Direction,
+    ) -> Result<NextAction, RenderError> {
+        // Paragraphs with nothing to show, like ones left behind by stray markup or by filtering
+        // a tree, are left out rather than written as `<p></p>`
+        if !self.config.empty_paragraphs && paragraph.children().iter().all(is_blank) {
+            return Ok(match action {
+                Direction::Entering => NextAction::SkipChildren,
+                Direction::Exiting => NextAction::GotoNext,
+            });
+        }
+        let gallery = self.config.galleries && is_gallery(paragraph);
+        if let Direction::Exiting = action {
+            write!(
+                self.output.borrow_mut(),
+                "{}",
+                if gallery { "</div>" } else { "</p>" }
+            )?;
+            self.block_separator()?;
+            self.close_block(true)?;
+            return Ok(NextAction::GotoNext);
+        }
+
+        self.open_block();
+        if !gallery {
+            self.tag_with_attrs("p", &[], false)?;
+            return Ok(NextAction::GotoNext);
+        }
+        // Only the images are written, since the whitespace between them would otherwise end up
+        // as text between the images in the gallery
+        self.tag_with_attrs("div", &[("class", "gallery")], false)?;
+        for child in paragraph.children().iter() {
+            if matches!(child, Node::Image(_)) && child.traverse(self) == NextAction::End {
+                return Ok(NextAction::End);
+            }
+        }
+        Ok(NextAction::SkipChildren)
+    }
+
+    fn heading(&self, heading: &Heading, action: Direction) -> Result<(), RenderError> {
+        if let Direction::Entering = action {
+            self.open_block();
+            self.context.borrow_mut().set_in_heading(true);
+            let tag = format!("h{}", heading.level());
+            let attributes = heading.attributes();
+            let text: String = heading
+                .children()
+                .iter()
+                .flat_map(Node::text_leaves)
+                .collect();
+            // An id from the author takes the place of a generated one
+            let id = match attributes.id() {
+                Some(id) => {
+                    self.context.borrow_mut().reserve_id(id);
+                    Some(id.to_owned())
+                }
+                None if self.config.heading_ids => {
+                    let id = format!("{}{}", self.config.id_prefix, heading_slug(&text));
+                    Some(self.context.borrow_mut().unique_id(id))
+                }
+                None => None,
+            };
+            if let Some(id) = &id {
+                self.context.borrow_mut().toc.push(TocEntry {
+                    level: heading.level(),
+                    text: text.trim().to_owned(),
+                    anchor: id.clone(),
+                });
+            }
+            let classes = attributes.classes().collect::<Vec<_>>().join(" ");
+
+            let mut attrs = Vec::new();
+            if let Some(id) = &id {
+                attrs.push(("id", id.as_str()));
+            }
+            if !classes.is_empty() {
+                attrs.push(("class", classes.as_str()));
+            }
+            attrs.extend(attributes.pairs());
+            self.tag_with_attrs(&tag, &attrs, false)?;
+        } else {
+            self.context.borrow_mut().set_in_heading(false);
+            write!(self.output.borrow_mut(), "</h{}>", heading.level())?;
+            self.block_separator()?;
+            self.close_block(true)?;
+        }
+        Ok(())
+    }
+
+    fn list(&self, list: &List, action: Direction) -> Result<(), RenderError> {
         let start = list.start();
 
         if let Direction::Entering = action {
+            self.open_block();
             self.context.borrow_mut().push_list_context(list);
-            if list.ordered() {
+            if list.ordered() && self.config.commonmark_whitespace && start == 1 {
+                // The reference output only includes the start when it isn't the default
+                self.tag_with_attrs("ol", &[], false)?;
+            } else if list.ordered() {
                 self.tag_with_attrs("ol", &[("start", &start.to_string())], false)?;
             } else {
                 self.tag_with_attrs("ul", &[], false)?;
             }
+            self.block_separator()?;
         } else {
             self.context.borrow_mut().drop_list_context();
             if list.ordered() {
@@ -223,158 +1257,2315 @@ impl<'a> HTMLRenderer<'a> {
             } else {
                 write!(self.output.borrow_mut(), "</ul>")?;
             }
+            self.block_separator()?;
+            self.close_block(true)?;
         }
         Ok(())
     }
 
-    fn list_item(&self, action: Direction) -> Result<(), RenderError> {
+    fn list_item(&self, list_item: &ListItem, action: Direction) -> Result<(), RenderError> {
         let context = self.context.borrow();
         let list_context = context.list_context().ok_or(RenderError::StateError(
             "No list context found when creating a list item".to_owned(),
         ))?;
+        let tight = list_context.tight || self.config.force_tight_lists;
         if let Direction::Entering = action {
-            if list_context.tight {
-                write!(self.output.borrow_mut(), "<li>")?;
+            self.open_block();
+            if self.config.explicit_li_values && list_context.ordered {
+                self.tag_with_attrs("li", &[("value", &list_item.index().to_string())], false)?;
             } else {
-                write!(self.output.borrow_mut(), "<li><p>")?;
+                self.tag_with_attrs("li", &[], false)?;
+            }
+            if !tight {
+                self.block_separator()?;
+                self.tag_with_attrs("p", &[], false)?;
+            }
+            if let Some(checked) = list_item.checked() {
+                let line = match self.config.interactive_tasks {
+                    true => context.line_of(list_item.as_span()),
+                    false => None,
+                };
+                self.task_checkbox(checked, line)?;
+                if !list_item.children().is_empty() {
+                    write!(self.output.borrow_mut(), " ")?;
+                }
             }
         } else {
-            if list_context.tight {
-                write!(self.output.borrow_mut(), "</li>")?;
-            } else {
-                write!(self.output.borrow_mut(), "</p></li>")?;
+            if !tight {
+                write!(self.output.borrow_mut(), "</p>")?;
+                self.block_separator()?;
             }
+            write!(self.output.borrow_mut(), "</li>")?;
+            self.block_separator()?;
+            self.close_block(true)?;
         }
 
         Ok(())
     }
 
-    fn blockquote(&self, action: Direction) -> Result<(), RenderError> {
+    fn task_checkbox(&self, checked: bool, line: Option<usize>) -> Result<(), RenderError> {
+        let mut attrs = vec![("type", "checkbox")];
+        if checked {
+            attrs.push(("checked", ""));
+        }
+        let line = line.map(|line| line.to_string());
+        if !self.config.interactive_tasks {
+            attrs.push(("disabled", ""));
+        } else if let Some(line) = &line {
+            attrs.push(("data-task-line", line));
+        }
+        self.tag_with_attrs("input", &attrs, true)
+    }
+
+    fn blockquote(
+        &self,
+        block_quote: &BlockQuote,
+        action: Direction,
+    ) -> Result<NextAction, RenderError> {
+        if let Direction::Exiting = action {
+            write!(self.output.borrow_mut(), "</blockquote>")?;
+            self.block_separator()?;
+            self.close_block(true)?;
+            return Ok(NextAction::GotoNext);
+        }
+
+        self.open_block();
+        self.tag_with_attrs("blockquote", &[], false)?;
+        self.block_separator()?;
+        let citation = block_quote
+            .citation()
+            .filter(|_| self.config.quote_citations);
+        let Some((quote, author)) = citation else {
+            return Ok(NextAction::GotoNext);
+        };
+        // The children are written here instead of by the traversal, which would also write the
+        // attribution as part of the quote
+        self.tag_with_attrs("p", &[], false)?;
+        for child in quote {
+            if let NextAction::End = child.traverse(self) {
+                return Ok(NextAction::End);
+            }
+        }
+        write!(self.output.borrow_mut(), "</p>")?;
+        self.block_separator()?;
+        self.tag_with_attrs("cite", &[], false)?;
+        self.text(author)?;
+        write!(self.output.borrow_mut(), "</cite>")?;
+        self.block_separator()?;
+        Ok(NextAction::SkipChildren)
+    }
+
+    fn line_block(&self, action: Direction) -> Result<(), RenderError> {
         if let Direction::Entering = action {
-            write!(self.output.borrow_mut(), "<blockquote>")?;
+            self.open_block();
+            self.context.borrow_mut().start_line_block();
+            self.tag_with_attrs("div", &[("class", "line-block")], false)?;
         } else {
-            write!(self.output.borrow_mut(), "</blockquote>")?;
+            write!(self.output.borrow_mut(), "</div>")?;
+            self.block_separator()?;
+            self.close_block(true)?;
+        }
+        Ok(())
+    }
+
+    /// Lines are separated by breaks, and the spaces at the start of a line are written as
+    /// non-breaking spaces so that browsers don't collapse them.
+    fn line(&self, line: &Line, action: Direction) -> Result<(), RenderError> {
+        if let Direction::Entering = action {
+            if !self.context.borrow_mut().start_line() {
+                self.tag_with_attrs("br", &[], true)?;
+                self.block_separator()?;
+            }
+            write!(
+                self.output.borrow_mut(),
+                "{}",
+                "&nbsp;".repeat(line.indent() as usize)
+            )?;
         }
         Ok(())
     }
 
-    fn codeblock(&self, codeblock: &CodeBlock, action: Direction) -> Result<(), RenderError> {
+    fn codeblock(
+        &self,
+        codeblock: &CodeBlock,
+        action: Direction,
+    ) -> Result<NextAction, RenderError> {
         if let Direction::Entering = action {
-            write!(self.output.borrow_mut(), "<pre>")?;
-            if let Some(language) = codeblock.language() {
+            self.open_block();
+            self.tag_with_attrs("pre", &[], false)?;
+            let language = codeblock
+                .language()
+                .or(self.config.default_code_language.as_deref());
+            if let Some(language) = language {
                 self.tag_with_attrs("code", &[("class", &format!("language-{language}"))], false)?;
             } else {
-                write!(self.output.borrow_mut(), "<code>")?;
+                self.tag_with_attrs("code", &[], false)?;
+            }
+            let diff = language == Some("diff");
+            if self.config.code_line_numbers || diff {
+                return self.code_lines(codeblock, diff);
             }
         } else {
-            write!(self.output.borrow_mut(), "")?;
+            // The reference output keeps the newline that ends the last line of code
+            self.block_separator()?;
             write!(self.output.borrow_mut(), "</code></pre>")?;
+            self.block_separator()?;
+            self.close_block(true)?;
         }
 
-        Ok(())
+        Ok(NextAction::GotoNext)
+    }
+
+    /// Write the contents of a code block line by line, skipping the code block's children since
+    /// they've already been written. Each line is wrapped in a `<span class="line">` when lines are
+    /// numbered, and in diffs, lines that start with `+` or `-` get an `addition` or `deletion`
+    /// class.
+    fn code_lines(&self, codeblock: &CodeBlock, diff: bool) -> Result<NextAction, RenderError> {
+        let code: String = codeblock
+            .children()
+            .iter()
+            .flat_map(Node::text_leaves)
+            .collect();
+        let code = code.strip_suffix('\n').unwrap_or(&code);
+        for (i, line) in code.split('\n').enumerate() {
+            if i > 0 {
+                writeln!(self.output.borrow_mut())?;
+            }
+            let change = match line.chars().next() {
+                Some('+') if diff => Some("addition"),
+                Some('-') if diff => Some("deletion"),
+                _ => None,
+            };
+            let class = match (self.config.code_line_numbers, change) {
+                (true, Some(change)) => Some(Cow::Owned(format!("line {change}"))),
+                (true, None) => Some(Cow::Borrowed("line")),
+                (false, change) => change.map(Cow::Borrowed),
+            };
+            let next_action = match class {
+                Some(class) => {
+                    self.tag_with_attrs("span", &[("class", &class)], false)?;
+                    let next_action = self.text(line)?;
+                    write!(self.output.borrow_mut(), "</span>")?;
+                    next_action
+                }
+                None => self.text(line)?,
+            };
+            if let NextAction::End = next_action {
+                return Ok(NextAction::End);
+            }
+        }
+        Ok(NextAction::SkipChildren)
+    }
+
+    /// Writes the text, unless doing so would exceed the text limit. In that case, only the
+    /// portion of the text that fits is written followed by an ellipsis, and the returned
+    /// action signals that the traversal should stop.
+    fn text(&self, text: &str) -> Result<NextAction, RenderError> {
+        let limit = match self.config.text_limit {
+            Some(limit) => limit,
+            None => {
+                write!(self.output.borrow_mut(), "{text}")?;
+                return Ok(NextAction::GotoNext);
+            }
+        };
+
+        let remaining = limit - self.emitted_chars.get();
+        match visible_chars(text).nth(remaining) {
+            Some(cutoff) => {
+                write!(self.output.borrow_mut(), "{}…", &text[..cutoff])?;
+                self.emitted_chars.set(limit);
+                Ok(NextAction::End)
+            }
+            None => {
+                write!(self.output.borrow_mut(), "{text}")?;
+                self.emitted_chars
+                    .set(self.emitted_chars.get() + visible_chars(text).count());
+                Ok(NextAction::GotoNext)
+            }
+        }
     }
 
-    fn inline_style(&self, open: &str, close: &str, action: Direction) -> Result<(), RenderError> {
+    fn inline_style(&self, tag: &str, action: Direction) -> Result<(), RenderError> {
         match action {
-            Direction::Entering => write!(self.output.borrow_mut(), "{}", open)?,
-            Direction::Exiting => write!(self.output.borrow_mut(), "{}", close)?,
+            Direction::Entering => self.tag_with_attrs(tag, &[], false)?,
+            Direction::Exiting => write!(self.output.borrow_mut(), "</{tag}>")?,
         };
 
         Ok(())
     }
+
+    fn emphasis(&self, action: Direction) -> Result<(), RenderError> {
+        if self.config.presentational_emphasis {
+            self.inline_style("i", action)
+        } else {
+            self.inline_style("em", action)
+        }
+    }
+
+    fn strong(&self, action: Direction) -> Result<(), RenderError> {
+        if self.config.presentational_emphasis {
+            self.inline_style("b", action)
+        } else {
+            self.inline_style("strong", action)
+        }
+    }
+
+    fn inline_math(&self, inline_math: &InlineMath) -> Result<(), RenderError> {
+        let expression = inline_math.expression();
+        self.tag_with_attrs("span", &[("class", "math inline")], false)?;
+        if self.config.math_delimiters {
+            write!(self.output.borrow_mut(), r"\({expression}\)")?;
+        } else {
+            write!(self.output.borrow_mut(), "{expression}")?;
+        }
+        write!(self.output.borrow_mut(), "</span>")?;
+        Ok(())
+    }
+
+    fn display_math(&self, display_math: &DisplayMath) -> Result<(), RenderError> {
+        let expression = display_math.expression();
+        self.tag_with_attrs("div", &[("class", "math display")], false)?;
+        if self.config.math_delimiters {
+            write!(self.output.borrow_mut(), r"\[{expression}\]")?;
+        } else {
+            write!(self.output.borrow_mut(), "{expression}")?;
+        }
+        write!(self.output.borrow_mut(), "</div>")?;
+        self.block_separator()?;
+        self.close_block(false)
+    }
+
+    /// Blocks that couldn't be parsed are shown as they were written, so that previews of a
+    /// document that's being edited still show everything in it.
+    fn error_block(&self, error_block: &ErrorBlock) -> Result<(), RenderError> {
+        self.tag_with_attrs("pre", &[("class", "parse-error")], false)?;
+        write!(self.output.borrow_mut(), "{}</pre>", error_block.as_span())?;
+        self.block_separator()?;
+        self.close_block(false)
+    }
+
+    fn soft_linebreak(&self) -> Result<(), RenderError> {
+        if self.config.commonmark_whitespace {
+            writeln!(self.output.borrow_mut())?;
+        } else {
+            write!(self.output.borrow_mut(), " ")?;
+        }
+        Ok(())
+    }
+
+    fn label(&self, action: Direction) -> Result<(), RenderError> {
+        match action {
+            Direction::Entering => write!(self.output.borrow_mut(), "[")?,
+            Direction::Exiting => write!(self.output.borrow_mut(), "]")?,
+        };
+        Ok(())
+    }
+
+    fn thematic_break(&self) -> Result<(), RenderError> {
+        self.tag_with_attrs("hr", &[], true)?;
+        self.block_separator()?;
+        self.close_block(false)
+    }
+
+    /// Turn the result of writing a node into the next step of the traversal, stopping it if the
+    /// node couldn't be written.
+    fn next_action(&self, result: Result<NextAction, RenderError>) -> NextAction {
+        match result {
+            Ok(next_action) => next_action,
+            Err(e) => {
+                let mut error = self.error.borrow_mut();
+                if error.is_none() {
+                    *error = Some(e);
+                }
+                NextAction::End
+            }
+        }
+    }
+
+    fn emit(&self, result: Result<(), RenderError>) -> NextAction {
+        self.next_action(result.map(|_| NextAction::GotoNext))
+    }
 }
 
-impl<'a> Visitor<'_> for HTMLRenderer<'a> {
-    fn visit(&self, node: &Node, action: Direction) -> NextAction {
+impl<'a, S: Sink> Visitor<'_> for HTMLRenderer<'a, S> {
+    fn visit(&self, node: &Node, action: Direction, _: &TraversalContext) -> NextAction {
+        if let Some(feature) = InlineFeatures::of(node) {
+            let source = || match node {
+                Node::Image(image) => Cow::Borrowed(image.markdown()),
+                node => Cow::Borrowed(node.as_span()),
+            };
+            if let Some(next_action) = self.disabled_feature(feature, &action, source) {
+                return next_action;
+            }
+        }
         let emit_result = match node {
-            Node::Document(_) => self.document(action),
-            Node::Paragraph(_) => self.paragraph(action),
-            Node::BlockQuote(_) => self.blockquote(action),
+            Node::Document(document) => self.document(document, action),
+            Node::Paragraph(paragraph) => {
+                return self.next_action(self.paragraph(paragraph, action))
+            }
+            Node::BlockQuote(block_quote) => {
+                return self.next_action(self.blockquote(block_quote, action))
+            }
+            Node::LineBlock(_) => self.line_block(action),
+            Node::Line(line) => self.line(line, action),
             Node::Heading(heading) => self.heading(heading, action),
             Node::List(list) => self.list(list, action),
-            Node::ListItem(_) => self.list_item(action),
-            Node::CodeBlock(cb) => self.codeblock(cb, action),
-            Node::Emphasis(_) => self.inline_style("<em>", "</em>", action),
-            Node::Strong(_) => self.inline_style("<strong>", "</strong>", action),
-            Node::Code(_) => self.inline_style("<pre><code>", "</code></pre>", action),
+            Node::ListItem(list_item) => self.list_item(list_item, action),
+            Node::CodeBlock(cb) => return self.next_action(self.codeblock(cb, action)),
+            Node::Emphasis(_) => self.emphasis(action),
+            Node::Strong(_) => self.strong(action),
+            Node::Subscript(_) => self.inline_style("sub", action),
+            Node::Superscript(_) => self.inline_style("sup", action),
+            Node::Highlight(_) => self.inline_style("mark", action),
+            Node::Code(_) => self.inline_style("code", action),
             Node::Link(link) => self.link(link, action),
             Node::Image(img) => self.image(img),
-            Node::Text(text) => {
-                write!(self.output.borrow_mut(), "{}", text.as_span()).map_err(RenderError::from)
-            }
+            Node::Text(text) => return self.next_action(self.text(text.as_span())),
             Node::Linebreak(_) => self.linebreak(),
-            Node::SoftLinebreak(_) => {
-                write!(self.output.borrow_mut(), " ").map_err(RenderError::from)
-            }
-            Node::Label(_) => return NextAction::GotoNext,
-            Node::ThematicBreak(_) => {
-                write!(self.output.borrow_mut(), "<hr/>").map_err(RenderError::from)
-            }
+            Node::SoftLinebreak(_) => self.soft_linebreak(),
+            Node::Label(_) => self.label(action),
+            Node::ThematicBreak(_) => self.thematic_break(),
             Node::Reference(_) => return NextAction::GotoNext,
-            Node::EOI => Ok(()),
+            // Includes that were never resolved have nothing to show
+            Node::Include(_) => return NextAction::GotoNext,
+            Node::InlineMath(math) => self.inline_math(math),
+            Node::DisplayMath(math) => self.display_math(math),
+            Node::ErrorBlock(error_block) => self.error_block(error_block),
+            Node::FootnoteReference(reference) => self.footnote_reference(reference),
+            // Definitions are written at the end of the document by `footnotes`
+            Node::FootnoteDefinition(_) => return NextAction::SkipChildren,
         };
 
-        match emit_result {
-            Ok(_) => NextAction::GotoNext,
-            Err(e) => {
-                println!("Encountered an error while generating HTML, stopping. Error was: {e}");
-                NextAction::End
-            }
-        }
+        self.emit(emit_result)
+    }
+}
+
+/// Rendering through [`render`](crate::transformer::render::render) produces the same output as
+/// traversing the tree with the renderer as a [`Visitor`].
+impl<'a, 'input, S: Sink> Renderer<'input> for HTMLRenderer<'a, S> {
+    fn enter_document(&mut self, document: &'input Document<'input>) -> NextAction {
+        self.emit(self.document(document, Direction::Entering))
+    }
+
+    fn exit_document(&mut self, document: &'input Document<'input>) -> NextAction {
+        self.emit(self.document(document, Direction::Exiting))
+    }
+
+    fn enter_paragraph(&mut self, paragraph: &'input Paragraph<'input>) -> NextAction {
+        self.next_action(self.paragraph(paragraph, Direction::Entering))
+    }
+
+    fn exit_paragraph(&mut self, paragraph: &'input Paragraph<'input>) -> NextAction {
+        self.next_action(self.paragraph(paragraph, Direction::Exiting))
+    }
+
+    fn enter_line_block(&mut self, _: &'input LineBlock<'input>) -> NextAction {
+        self.emit(self.line_block(Direction::Entering))
+    }
+
+    fn exit_line_block(&mut self, _: &'input LineBlock<'input>) -> NextAction {
+        self.emit(self.line_block(Direction::Exiting))
+    }
+
+    fn enter_line(&mut self, line: &'input Line<'input>) -> NextAction {
+        self.emit(self.line(line, Direction::Entering))
+    }
+
+    fn enter_block_quote(&mut self, block_quote: &'input BlockQuote<'input>) -> NextAction {
+        self.next_action(self.blockquote(block_quote, Direction::Entering))
+    }
+
+    fn exit_block_quote(&mut self, block_quote: &'input BlockQuote<'input>) -> NextAction {
+        self.next_action(self.blockquote(block_quote, Direction::Exiting))
+    }
+
+    fn enter_heading(&mut self, heading: &'input Heading<'input>) -> NextAction {
+        self.emit(self.heading(heading, Direction::Entering))
+    }
+
+    fn exit_heading(&mut self, heading: &'input Heading<'input>) -> NextAction {
+        self.emit(self.heading(heading, Direction::Exiting))
+    }
+
+    fn enter_list(&mut self, list: &'input List<'input>) -> NextAction {
+        self.emit(self.list(list, Direction::Entering))
+    }
+
+    fn exit_list(&mut self, list: &'input List<'input>) -> NextAction {
+        self.emit(self.list(list, Direction::Exiting))
+    }
+
+    fn enter_list_item(&mut self, list_item: &'input ListItem<'input>) -> NextAction {
+        self.emit(self.list_item(list_item, Direction::Entering))
+    }
+
+    fn exit_list_item(&mut self, list_item: &'input ListItem<'input>) -> NextAction {
+        self.emit(self.list_item(list_item, Direction::Exiting))
+    }
+
+    fn enter_code_block(&mut self, code_block: &'input CodeBlock<'input>) -> NextAction {
+        self.next_action(self.codeblock(code_block, Direction::Entering))
+    }
+
+    fn exit_code_block(&mut self, code_block: &'input CodeBlock<'input>) -> NextAction {
+        self.next_action(self.codeblock(code_block, Direction::Exiting))
+    }
+
+    fn enter_emphasis(&mut self, emphasis: &'input Emphasis<'input>) -> NextAction {
+        let source = || Cow::Borrowed(emphasis.as_span());
+        self.gated(
+            InlineFeatures::EMPHASIS,
+            &Direction::Entering,
+            source,
+            || self.emit(self.emphasis(Direction::Entering)),
+        )
+    }
+
+    fn exit_emphasis(&mut self, emphasis: &'input Emphasis<'input>) -> NextAction {
+        let source = || Cow::Borrowed(emphasis.as_span());
+        self.gated(
+            InlineFeatures::EMPHASIS,
+            &Direction::Exiting,
+            source,
+            || self.emit(self.emphasis(Direction::Exiting)),
+        )
+    }
+
+    fn enter_strong(&mut self, strong: &'input Strong<'input>) -> NextAction {
+        let source = || Cow::Borrowed(strong.as_span());
+        self.gated(InlineFeatures::STRONG, &Direction::Entering, source, || {
+            self.emit(self.strong(Direction::Entering))
+        })
+    }
+
+    fn exit_strong(&mut self, strong: &'input Strong<'input>) -> NextAction {
+        let source = || Cow::Borrowed(strong.as_span());
+        self.gated(InlineFeatures::STRONG, &Direction::Exiting, source, || {
+            self.emit(self.strong(Direction::Exiting))
+        })
+    }
+
+    fn enter_subscript(&mut self, subscript: &'input Subscript<'input>) -> NextAction {
+        let source = || Cow::Borrowed(subscript.as_span());
+        self.gated(
+            InlineFeatures::SUBSCRIPT,
+            &Direction::Entering,
+            source,
+            || self.emit(self.inline_style("sub", Direction::Entering)),
+        )
+    }
+
+    fn exit_subscript(&mut self, subscript: &'input Subscript<'input>) -> NextAction {
+        let source = || Cow::Borrowed(subscript.as_span());
+        self.gated(
+            InlineFeatures::SUBSCRIPT,
+            &Direction::Exiting,
+            source,
+            || self.emit(self.inline_style("sub", Direction::Exiting)),
+        )
+    }
+
+    fn enter_superscript(&mut self, superscript: &'input Superscript<'input>) -> NextAction {
+        let source = || Cow::Borrowed(superscript.as_span());
+        self.gated(
+            InlineFeatures::SUPERSCRIPT,
+            &Direction::Entering,
+            source,
+            || self.emit(self.inline_style("sup", Direction::Entering)),
+        )
+    }
+
+    fn exit_superscript(&mut self, superscript: &'input Superscript<'input>) -> NextAction {
+        let source = || Cow::Borrowed(superscript.as_span());
+        self.gated(
+            InlineFeatures::SUPERSCRIPT,
+            &Direction::Exiting,
+            source,
+            || self.emit(self.inline_style("sup", Direction::Exiting)),
+        )
+    }
+
+    fn enter_highlight(&mut self, highlight: &'input Highlight<'input>) -> NextAction {
+        let source = || Cow::Borrowed(highlight.as_span());
+        self.gated(
+            InlineFeatures::HIGHLIGHT,
+            &Direction::Entering,
+            source,
+            || self.emit(self.inline_style("mark", Direction::Entering)),
+        )
+    }
+
+    fn exit_highlight(&mut self, highlight: &'input Highlight<'input>) -> NextAction {
+        let source = || Cow::Borrowed(highlight.as_span());
+        self.gated(
+            InlineFeatures::HIGHLIGHT,
+            &Direction::Exiting,
+            source,
+            || self.emit(self.inline_style("mark", Direction::Exiting)),
+        )
+    }
+
+    fn enter_label(&mut self, _: &'input Label<'input>) -> NextAction {
+        self.emit(self.label(Direction::Entering))
+    }
+
+    fn exit_label(&mut self, _: &'input Label<'input>) -> NextAction {
+        self.emit(self.label(Direction::Exiting))
+    }
+
+    fn enter_link(&mut self, link: &'input Link<'input>) -> NextAction {
+        let source = || Cow::Borrowed(link.as_span());
+        self.gated(InlineFeatures::LINKS, &Direction::Entering, source, || {
+            self.emit(self.link(link, Direction::Entering))
+        })
+    }
+
+    fn exit_link(&mut self, link: &'input Link<'input>) -> NextAction {
+        let source = || Cow::Borrowed(link.as_span());
+        self.gated(InlineFeatures::LINKS, &Direction::Exiting, source, || {
+            self.emit(self.link(link, Direction::Exiting))
+        })
+    }
+
+    fn enter_code(&mut self, code: &'input Code<'input>) -> NextAction {
+        let source = || Cow::Borrowed(code.as_span());
+        self.gated(InlineFeatures::CODE, &Direction::Entering, source, || {
+            self.emit(self.inline_style("code", Direction::Entering))
+        })
+    }
+
+    fn exit_code(&mut self, code: &'input Code<'input>) -> NextAction {
+        let source = || Cow::Borrowed(code.as_span());
+        self.gated(InlineFeatures::CODE, &Direction::Exiting, source, || {
+            self.emit(self.inline_style("code", Direction::Exiting))
+        })
+    }
+
+    fn text(&mut self, text: &'input Text<'input>) -> NextAction {
+        self.next_action(HTMLRenderer::text(self, text.as_span()))
+    }
+
+    fn linebreak(&mut self, _: &'input Linebreak<'input>) -> NextAction {
+        self.emit(HTMLRenderer::linebreak(self))
+    }
+
+    fn soft_linebreak(&mut self, _: &'input SoftLinebreak<'input>) -> NextAction {
+        self.emit(HTMLRenderer::soft_linebreak(self))
+    }
+
+    fn image(&mut self, image: &'input Image<'input>) -> NextAction {
+        let source = || Cow::Borrowed(image.markdown());
+        self.gated(InlineFeatures::IMAGES, &Direction::Entering, source, || {
+            self.emit(HTMLRenderer::image(self, image))
+        })
+    }
+
+    fn thematic_break(&mut self, _: &'input ThematicBreak<'input>) -> NextAction {
+        self.emit(HTMLRenderer::thematic_break(self))
+    }
+
+    fn inline_math(&mut self, inline_math: &'input InlineMath<'input>) -> NextAction {
+        let source = || Cow::Borrowed(inline_math.as_span());
+        self.gated(InlineFeatures::MATH, &Direction::Entering, source, || {
+            self.emit(HTMLRenderer::inline_math(self, inline_math))
+        })
+    }
+
+    fn display_math(&mut self, display_math: &'input DisplayMath<'input>) -> NextAction {
+        self.emit(HTMLRenderer::display_math(self, display_math))
+    }
+
+    fn error_block(&mut self, error_block: &'input ErrorBlock<'input>) -> NextAction {
+        self.emit(HTMLRenderer::error_block(self, error_block))
+    }
+
+    fn footnote_reference(
+        &mut self,
+        footnote_reference: &'input FootnoteReference<'input>,
+    ) -> NextAction {
+        let source = || Cow::Borrowed(footnote_reference.as_span());
+        self.gated(
+            InlineFeatures::FOOTNOTES,
+            &Direction::Entering,
+            source,
+            || self.emit(HTMLRenderer::footnote_reference(self, footnote_reference)),
+        )
+    }
+
+    fn enter_footnote_definition(&mut self, _: &'input FootnoteDefinition<'input>) -> NextAction {
+        NextAction::SkipChildren
     }
 }
 
-impl<'a> Display for HTMLRenderer<'a> {
+impl<'a, S: Sink + AsRef<[u8]>> Display for HTMLRenderer<'a, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match std::str::from_utf8(self.output.borrow().as_slice()) {
+        match std::str::from_utf8(self.output.borrow().as_ref()) {
             Ok(s) => write!(f, "{}", s),
             Err(e) => write!(f, "Invalid UTF-8 contents in buffer: {e:?}"),
         }
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::ast::parse_document;
-    use crate::test_utils::read_file_to_string;
-    use pretty_assertions::assert_eq;
+/// Render a tree to HTML with the default options, resolving any references that it defines.
+/// Documents without any references skip collecting them, and the rest are resolved with
+/// [`LinkResolver::from_document`].
+///
+/// ```
+/// use pegmd::ast::parse_document;
+/// use pegmd::transformer::render_to_html;
+///
+/// let root = parse_document("[Home]\n\n[home]: /index.html").unwrap();
+/// assert!(render_to_html(&root).contains(r#"<a href="/index.html">Home</a>"#));
+/// ```
+pub fn render_to_html<'a>(root: &'a Node<'a>) -> String {
+    let renderer = HTMLRenderer::with_resolver(resolver_for(root));
+    root.traverse(&renderer);
+    renderer.to_string()
+}
 
-    #[test]
-    pub fn markup_test() {
-        let input = read_file_to_string("markdown/markup.md");
-        let root =
-            parse_document(&input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
-        let link_resolver = LinkResolver::default();
-        root.traverse(&link_resolver);
-        let html_renderer = HTMLRenderer::with_resolver(link_resolver);
-        root.traverse(&html_renderer);
-        let actual = html_renderer.to_string();
-        let expected = read_file_to_string("html/markup.html");
-        assert_eq!(&actual, &expected);
+/// Render a tree to HTML like [`render_to_html`] with [`HTMLRenderer::with_heading_ids`] on,
+/// along with a table of contents of its headings. The anchors in the table of contents are the
+/// same ids that the headings were given in the HTML, including the numbers added to repeats.
+///
+/// ```
+/// use pegmd::ast::parse_document;
+/// use pegmd::transformer::render_to_html_with_toc;
+///
+/// let root = parse_document("# Intro\n\n## Usage").unwrap();
+/// let (html, toc) = render_to_html_with_toc(&root);
+/// assert_eq!(toc[1].anchor, "usage");
+/// assert!(html.contains(r#"<h2 id="usage">Usage</h2>"#));
+/// ```
+pub fn render_to_html_with_toc<'a>(root: &'a Node<'a>) -> (String, Vec<TocEntry>) {
+    let renderer = HTMLRenderer::with_resolver(resolver_for(root)).with_heading_ids(true);
+    root.traverse(&renderer);
+    let toc = renderer.take_toc();
+    (renderer.to_string(), toc)
+}
+
+/// Render a tree to HTML like [`render_to_html`], but fail if the renderer runs into an error
+/// instead of returning what it wrote up to that point, like for a tree built by hand with list
+/// items outside of a list.
+///
+/// ```
+/// use pegmd::ast::parse_document;
+/// use pegmd::transformer::try_render_to_html;
+///
+/// let root = parse_document("Some *text*").unwrap();
+/// assert_eq!(
+///     try_render_to_html(&root).unwrap(),
+///     "<!DOCTYPE html><html><p>Some <em>text</em></p></html>"
+/// );
+/// ```
+pub fn try_render_to_html<'a>(root: &'a Node<'a>) -> Result<String, MarkdownError> {
+    finish(root, HTMLRenderer::with_resolver(resolver_for(root)))
+}
+
+fn finish<'a>(root: &'a Node<'a>, renderer: HTMLRenderer<'a>) -> Result<String, MarkdownError> {
+    root.traverse(&renderer);
+    match renderer.take_error() {
+        Some(e) => Err(MarkdownError::Render(e)),
+        None => Ok(renderer.to_string()),
     }
+}
 
-    #[test]
-    pub fn list_test() {
-        let input = read_file_to_string("markdown/lists.md");
-        let root =
-            parse_document(&input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
-        let html_renderer = HTMLRenderer::default();
-        root.traverse(&html_renderer);
-        let actual = html_renderer.to_string();
-        let expected = read_file_to_string("html/lists.html");
-        assert_eq!(&actual, &expected);
+/// A resolver for the references that `root` defines, which skips collecting them for documents
+/// that don't have any.
+fn resolver_for<'a>(root: &'a Node<'a>) -> LinkResolver<'a> {
+    let has_references = root
+        .children()
+        .is_some_and(|children| children.iter().any(|c| matches!(c, Node::Reference(_))));
+    if has_references {
+        LinkResolver::from_document(root)
+    } else {
+        LinkResolver::default()
     }
+}
 
-    #[test]
-    pub fn blocks_test() {
-        let input = read_file_to_string("markdown/blocks.md");
-        let root =
-            parse_document(&input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
-        let html_renderer = HTMLRenderer::default();
-        root.traverse(&html_renderer);
-        let actual = html_renderer.to_string();
-        let expected = read_file_to_string("html/blocks.html");
-        assert_eq!(&actual, &expected);
+/// Parse and render a markdown document to HTML in one call, for callers like bindings to other
+/// languages that only pass strings around. Parsing is lenient, so this never fails, and blocks
+/// that can't be parsed are shown as they were written.
+///
+/// ```
+/// use pegmd::transformer::render_markdown;
+///
+/// assert_eq!(
+///     render_markdown("Some *text*"),
+///     "<!DOCTYPE html><html><p>Some <em>text</em></p></html>"
+/// );
+/// ```
+pub fn render_markdown(input: &str) -> String {
+    render_to_html(&crate::ast::parse_document_lenient(input))
+}
+
+/// Rendered HTML, for converting a markdown document to HTML with `try_into`. The conversion
+/// parses the document strictly, failing with a [`MarkdownError`] if it can't be parsed, and then
+/// renders it like [`try_render_to_html`]. It derefs to the HTML as a `str`.
+///
+/// ```
+/// use pegmd::transformer::Html;
+///
+/// let html: Html = "Some *text*".try_into().unwrap();
+/// assert_eq!(&*html, "<!DOCTYPE html><html><p>Some <em>text</em></p></html>");
+/// assert!(Html::try_from("  ").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Html(String);
+
+impl TryFrom<&str> for Html {
+    type Error = MarkdownError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let root = crate::ast::parse_document(value)?;
+        try_render_to_html(&root).map(Self)
+    }
+}
+
+impl std::ops::Deref for Html {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for Html {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<Html> for String {
+    fn from(value: Html) -> Self {
+        value.0
+    }
+}
+
+/// The part of a reference link's source text that follows its label, like `[ref]` in `[text][ref]`.
+fn reference_suffix<'a>(link: &Link<'a>) -> &'a str {
+    let span = link.as_span();
+    let source = link.source();
+    if span.ends_with("[]") {
+        "[]"
+    } else if span.len() == source.len() + 2 {
+        // Shortcut references are just the label
+        ""
+    } else {
+        &span[span.len() - source.len() - 2..]
+    }
+}
+
+/// The byte offset of each character of text as it's shown, where an entity like `&nbsp;` is a
+/// single character, so that text is never cut off in the middle of one.
+fn visible_chars(text: &str) -> impl Iterator<Item = usize> + '_ {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let rest = &text[offset..];
+        let c = rest.chars().next()?;
+        let start = offset;
+        offset += match entity_len(rest) {
+            Some(len) => len,
+            None => c.len_utf8(),
+        };
+        Some(start)
+    })
+}
+
+/// The length of the entity, like `&shy;` or `&#160;`, that `text` starts with.
+fn entity_len(text: &str) -> Option<usize> {
+    let name = text.strip_prefix('&')?;
+    let name = name.strip_prefix('#').unwrap_or(name);
+    let len = name.find(';')?;
+    (len > 0 && len <= 32 && name[..len].bytes().all(|b| b.is_ascii_alphanumeric()))
+        .then(|| text.len() - name.len() + len + 1)
+}
+
+/// Turn the text of a heading into an `id`, keeping letters, numbers, dashes, and underscores.
+/// Whitespace, including `&nbsp;`, becomes a dash, soft hyphens are dropped, and so is everything
+/// else. Headings with none of those, like `## !!!`, are given `section` instead.
+fn heading_slug(text: &str) -> String {
+    let slug: String = text
+        .replace("&nbsp;", " ")
+        .replace("&shy;", "")
+        .trim()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_whitespace() => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect();
+    if slug.is_empty() {
+        "section".to_owned()
+    } else {
+        slug
+    }
+}
+
+/// Whether a paragraph is only images, at least two of them, with nothing but whitespace between.
+fn is_gallery(paragraph: &Paragraph) -> bool {
+    let mut images = 0;
+    for child in paragraph.children().iter() {
+        match child {
+            Node::Image(_) => images += 1,
+            child if is_blank(child) => {}
+            _ => return false,
+        }
+    }
+    images > 1
+}
+
+/// Whether a node inside of a paragraph doesn't show anything when it's rendered.
+fn is_blank(node: &Node) -> bool {
+    match node {
+        Node::Text(text) => text.as_span().chars().all(is_collapsible_whitespace),
+        Node::SoftLinebreak(_) | Node::Linebreak(_) => true,
+        Node::Emphasis(_)
+        | Node::Strong(_)
+        | Node::Subscript(_)
+        | Node::Superscript(_)
+        | Node::Highlight(_) => node.children().is_some_and(|c| c.iter().all(is_blank)),
+        _ => false,
+    }
+}
+
+/// Percent-encode the characters of a link destination that aren't allowed in URLs, like spaces
+/// and non-ASCII characters. Sequences that are already percent-encoded are left alone, so
+/// encoding a destination twice doesn't change it.
+fn encode_destination(source: &str) -> Cow<'_, str> {
+    fn is_safe(bytes: &[u8], i: usize) -> bool {
+        match bytes[i] {
+            b'%' => bytes
+                .get(i + 1..i + 3)
+                .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit)),
+            b => b.is_ascii_alphanumeric() || b";/?:@&=+$,-_.!~*'()#".contains(&b),
+        }
+    }
+
+    let bytes = source.as_bytes();
+    if (0..bytes.len()).all(|i| is_safe(bytes, i)) {
+        return Cow::Borrowed(source);
+    }
+    let mut encoded = String::with_capacity(source.len() + 8);
+    for (i, &b) in bytes.iter().enumerate() {
+        if is_safe(bytes, i) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    Cow::Owned(encoded)
+}
+
+/// Find the host of an absolute or scheme relative URL, like `example.com` in
+/// `https://user@example.com:8080/path`. Relative URLs, and URLs like `mailto:` that don't
+/// have a host, return `None`.
+fn link_host(source: &str) -> Option<&str> {
+    let after_scheme = match source.split_once("://") {
+        Some((scheme, rest))
+            if scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)) =>
+        {
+            rest
+        }
+        _ => source.strip_prefix("//")?,
+    };
+    let authority = after_scheme
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default();
+    let host_and_port = authority.rsplit('@').next().unwrap_or_default();
+    let host = host_and_port.split(':').next().unwrap_or_default();
+    (!host.is_empty()).then_some(host)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::parse_document;
+    use crate::test_utils::read_file_to_string;
+    use crate::transformer::render::render;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    pub fn markup_test() {
+        let input = read_file_to_string("markdown/markup.md");
+        let root =
+            parse_document(&input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let link_resolver = LinkResolver::default();
+        root.traverse(&link_resolver);
+        let html_renderer = HTMLRenderer::with_resolver(link_resolver);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = read_file_to_string("html/markup.html");
+        assert_eq!(&actual, &expected);
+    }
+
+    #[test]
+    pub fn list_test() {
+        let input = read_file_to_string("markdown/lists.md");
+        let root =
+            parse_document(&input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = read_file_to_string("html/lists.html");
+        assert_eq!(&actual, &expected);
+    }
+
+    #[test]
+    pub fn blocks_test() {
+        let input = read_file_to_string("markdown/blocks.md");
+        let root =
+            parse_document(&input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = read_file_to_string("html/blocks.html");
+        assert_eq!(&actual, &expected);
+    }
+
+    #[test]
+    pub fn text_limit_test() {
+        let input = "This paragraph is *much longer* than the preview should allow";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default().with_text_limit(20);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><p>This paragraph is <em>mu…</em></p></html>";
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn subscript_superscript_test() {
+        let root = parse_document("H~2~O and e = mc^2^, but not ~ or ^ alone")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><p>H<sub>2</sub>O and e = mc<sup>2</sup>, but not ~ or ^ alone</p></html>";
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn highlight_test() {
+        let root = parse_document("An ==important== note, but a = b and a == b stay")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><p>An <mark>important</mark> note, but a = b and a == b stay</p></html>";
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn line_block_test() {
+        let input = "| 123 Main St\n|    Suite 4\n| Springfield";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let expected = concat!(
+            r#"<!DOCTYPE html><html><div class="line-block">123 Main St<br>"#,
+            "&nbsp;&nbsp;&nbsp;Suite 4<br>Springfield</div></html>"
+        );
+        assert_eq!(html_renderer.to_string(), expected);
+
+        let mut html_renderer = HTMLRenderer::default();
+        render(&root, &mut html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+    }
+
+    #[test]
+    pub fn heading_linebreak_test() {
+        use crate::ast::model::{Attributes, Children, Document, Linebreak, SoftLinebreak, Text};
+
+        let input = "# A heading that\nwraps  \nlines";
+        let heading = Heading::new(
+            Children::from(vec![
+                Node::Text(Text::new("A heading that")),
+                Node::SoftLinebreak(SoftLinebreak::new("\n")),
+                Node::Text(Text::new("wraps")),
+                Node::Linebreak(Linebreak::new("  \n")),
+                Node::Text(Text::new("lines")),
+            ]),
+            input,
+            1,
+            Attributes::default(),
+        );
+        let root = Node::Document(Document::new(
+            Children::from(vec![Node::Heading(heading)]),
+            input,
+        ));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><h1>A heading that wraps lines</h1></html>";
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn break_consistency_test() {
+        let render = |input: &str| {
+            let root =
+                parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            let html_renderer = HTMLRenderer::default();
+            root.traverse(&html_renderer);
+            html_renderer.to_string()
+        };
+
+        assert_eq!(
+            render("A hard  \nbreak and a soft\nbreak"),
+            "<!DOCTYPE html><html><p>A hard<br>break and a soft break</p></html>"
+        );
+        assert_eq!(
+            render("> A hard  \n> break and a soft\n> break"),
+            "<!DOCTYPE html><html><blockquote>A hard<br>break and a soft break</blockquote></html>"
+        );
+        assert_eq!(
+            render("- A hard  \n  break and a soft\n  break"),
+            "<!DOCTYPE html><html><ul><li>A hard<br>break and a soft break</li></ul></html>"
+        );
+    }
+
+    #[test]
+    pub fn empty_list_item_test() {
+        let root = parse_document("-\n- Second\n- \n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let first_item = root
+            .children()
+            .and_then(|blocks| blocks[0].children())
+            .map(|list| &list[0]);
+        assert!(matches!(first_item, Some(Node::ListItem(item)) if item.children().is_empty()));
+
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><ul><li></li><li>Second</li><li></li></ul></html>";
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn tilde_fence_test() {
+        let root = parse_document("~~~ markdown\n```\nfenced\n```\n~~~~\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><pre><code class=\"language-markdown\">```\nfenced\n```</code></pre></html>";
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn into_bytes_test() {
+        let root = parse_document("# Rendered\n\nStraight to *bytes*")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let expected = html_renderer.to_string().into_bytes();
+        assert_eq!(html_renderer.into_bytes(), expected);
+    }
+
+    #[test]
+    pub fn mixed_nested_list_test() {
+        let root = parse_document("1. a\n   - b\n     1. c\n2. d\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><ol start=\"1\"><li>a<ul><li>b<ol start=\"1\"><li>c</li></ol></li></ul></li><li>d</li></ol></html>";
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn reference_image_test() {
+        let input = "![logo][l] and ![missing][nope]\n\n[l]: /logo.png \"Logo\"\n";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let link_resolver = LinkResolver::default();
+        root.traverse(&link_resolver);
+        let html_renderer = HTMLRenderer::with_resolver(link_resolver);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><p><img src=\"/logo.png\" alt=\"logo\" title=\"Logo\"> and ![missing][nope]</p></html>";
+        assert_eq!(&actual, expected);
+
+        // Undefined references are written back exactly, including an empty `[]`
+        let root = parse_document("![x][] and ![y]")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert_eq!(
+            render_to_html(&root),
+            "<!DOCTYPE html><html><p>![x][] and ![y]</p></html>"
+        );
+    }
+
+    #[test]
+    pub fn backslash_edge_cases_test() {
+        let render = |input: &str| {
+            let root =
+                parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            let html_renderer = HTMLRenderer::default();
+            root.traverse(&html_renderer);
+            html_renderer.to_string()
+        };
+
+        assert_eq!(render("abc\\"), "<!DOCTYPE html><html><p>abc\\</p></html>");
+        assert_eq!(render("\\"), "<!DOCTYPE html><html><p>\\</p></html>");
+        assert_eq!(
+            render("\\a \\*"),
+            "<!DOCTYPE html><html><p>\\a *</p></html>"
+        );
+        assert_eq!(render("H~2\\"), "<!DOCTYPE html><html><p>H~2\\</p></html>");
+    }
+
+    #[test]
+    pub fn external_link_attrs_test() {
+        let root = parse_document(
+            "[Out](https://other.org/page) [In](https://Example.com/about) [Relative](/docs)",
+        )
+        .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default()
+            .with_external_link_attrs("example.com")
+            .with_external_links_in_new_tab(true);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = concat!(
+            "<!DOCTYPE html><html><p>",
+            r#"<a href="https://other.org/page" rel="noopener noreferrer" target="_blank">Out</a> "#,
+            r#"<a href="https://Example.com/about">In</a> "#,
+            r#"<a href="/docs">Relative</a>"#,
+            "</p></html>"
+        );
+        assert_eq!(&actual, expected);
+
+        assert_eq!(link_host("//cdn.example.com/x.js"), Some("cdn.example.com"));
+        assert_eq!(link_host("http://user@host:8080?q"), Some("host"));
+        assert_eq!(link_host("mailto:someone@example.com"), None);
+        assert_eq!(link_host("docs/intro.md"), None);
+    }
+
+    #[test]
+    pub fn heading_closing_hashes_test() {
+        let root = parse_document("## Title ##\n# C#\n### Closed at the end ###   ")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected =
+            "<!DOCTYPE html><html><h2>Title</h2><h1>C#</h1><h3>Closed at the end</h3></html>";
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn code_line_numbers_test() {
+        let root = parse_document("```\nfirst\n\nthird\n```\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default().with_code_line_numbers(true);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = concat!(
+            "<!DOCTYPE html><html><pre><code>",
+            "<span class=\"line\">first</span>\n",
+            "<span class=\"line\"></span>\n",
+            "<span class=\"line\">third</span>",
+            "</code></pre></html>"
+        );
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn diff_code_block_test() {
+        let root = parse_document("```diff\n context\n-old\n+new\n```\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let expected = concat!(
+            "<!DOCTYPE html><html><pre><code class=\"language-diff\"> context\n",
+            "<span class=\"deletion\">-old</span>\n",
+            "<span class=\"addition\">+new</span>",
+            "</code></pre></html>"
+        );
+
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+
+        let mut html_renderer = HTMLRenderer::default();
+        render(&root, &mut html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+
+        // Numbered lines keep their line class along with the change
+        let html_renderer = HTMLRenderer::default().with_code_line_numbers(true);
+        root.traverse(&html_renderer);
+        assert!(html_renderer
+            .to_string()
+            .contains(r#"<span class="line addition">+new</span>"#));
+    }
+
+    #[test]
+    pub fn undefined_reference_test() {
+        use crate::ast::model::{Children, Document, Label, Text};
+
+        let root = parse_document("An undefined [shortcut] and a [*full*][reference]")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><p>An undefined [shortcut] and a [<em>full</em>][reference]</p></html>";
+        assert_eq!(&actual, expected);
+
+        // Labels only stand alone in hand built trees, and are rendered the same way
+        let label = Label::new(
+            Children::from(vec![Node::Text(Text::new("label"))]),
+            "[label]",
+        );
+        let root = Node::Document(Document::new(
+            Children::from(vec![Node::Label(label)]),
+            "[label]",
+        ));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        assert_eq!(
+            html_renderer.to_string(),
+            "<!DOCTYPE html><html>[label]</html>"
+        );
+    }
+
+    #[test]
+    pub fn image_alt_text_test() {
+        let root = parse_document("![**bold** alt](i.png) ![plain](p.png)")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = r#"<!DOCTYPE html><html><p><img src="i.png" alt="bold alt"> <img src="p.png" alt="plain"></p></html>"#;
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn reference_link_forms_test() {
+        let input = concat!(
+            "[Full text][Ref] [ref][] [REF] [Other  Ref] [missing][] [missing]\n",
+            "\n",
+            "[ref]: /target \"Title\"\n",
+            "[other ref]: /other\n",
+        );
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let link_resolver = LinkResolver::default();
+        root.traverse(&link_resolver);
+        let html_renderer = HTMLRenderer::with_resolver(link_resolver);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = concat!(
+            "<!DOCTYPE html><html><p>",
+            r#"<a href="/target" title="Title">Full text</a> "#,
+            r#"<a href="/target" title="Title">ref</a> "#,
+            r#"<a href="/target" title="Title">REF</a> "#,
+            r#"<a href="/other">Other  Ref</a> "#,
+            "[missing][] [missing]",
+            "</p></html>"
+        );
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn renderer_is_send_test() {
+        fn assert_send<T: Send>() {}
+        assert_send::<HTMLRenderer>();
+        assert_send::<HTMLRenderer<String>>();
+
+        let root = parse_document("Rendered on *another* thread")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::with_sink(String::new());
+        let actual = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    root.traverse(&html_renderer);
+                    html_renderer.into_sink()
+                })
+                .join()
+                .unwrap()
+        });
+        assert_eq!(
+            actual,
+            "<!DOCTYPE html><html><p>Rendered on <em>another</em> thread</p></html>"
+        );
+    }
+
+    #[test]
+    pub fn render_driver_test() {
+        let input = read_file_to_string("markdown/markup.md");
+        let root =
+            parse_document(&input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let link_resolver = LinkResolver::default();
+        root.traverse(&link_resolver);
+        let mut html_renderer = HTMLRenderer::with_resolver(link_resolver);
+        render(&root, &mut html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = read_file_to_string("html/markup.html");
+        assert_eq!(&actual, &expected);
+    }
+
+    #[test]
+    pub fn force_tight_lists_test() {
+        let root = parse_document("- First\n\n- Second\n")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        assert!(html_renderer.to_string().contains("<p>"));
+
+        let html_renderer = HTMLRenderer::default().with_force_tight_lists(true);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><ul><li>First</li><li>Second</li></ul></html>";
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn presentational_emphasis_test() {
+        let root =
+            parse_document("*a* **b**").unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><p><em>a</em> <strong>b</strong></p></html>";
+        assert_eq!(&actual, expected);
+
+        let html_renderer = HTMLRenderer::default().with_presentational_emphasis(true);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><p><i>a</i> <b>b</b></p></html>";
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn math_test() {
+        let input = "Inline $x^2$ math, \\$5, and `$a$`\n\n$$\n\\sum_i x_i\n$$\n";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = concat!(
+            "<!DOCTYPE html><html><p>Inline <span class=\"math inline\">x^2</span> math, $5, and ",
+            "<code>$a$</code></p>",
+            "<div class=\"math display\">\n\\sum_i x_i\n</div></html>"
+        );
+        assert_eq!(&actual, expected);
+
+        let html_renderer = HTMLRenderer::default().with_math_delimiters(true);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        assert!(actual.contains(r#"<span class="math inline">\(x^2\)</span>"#));
+        assert!(actual.contains("<div class=\"math display\">\\[\n\\sum_i x_i\n\\]</div>"));
+    }
+
+    #[test]
+    pub fn filter_map_test() {
+        let input = "A ![logo](logo.png) and a [link](https://x.y)\n\n![banner](banner.png)";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let filtered = root
+            .filter_map(|node| match node {
+                Node::Image(_) => None,
+                // Children are filtered first, so paragraphs that only held an image are empty
+                Node::Paragraph(paragraph) if paragraph.children().is_empty() => None,
+                node => Some(node),
+            })
+            .unwrap_or_else(|| panic!("Document was dropped"));
+        let html_renderer = HTMLRenderer::default();
+        filtered.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected =
+            r#"<!DOCTYPE html><html><p>A  and a <a href="https://x.y">link</a></p></html>"#;
+        assert_eq!(&actual, expected);
+        assert!(root
+            .descendants_with_depth()
+            .any(|(_, node)| matches!(node, Node::Image(_))));
+    }
+
+    #[test]
+    pub fn code_block_tabs_test() {
+        let input = "```\nfn main() {\n\tlet x = 1;\n}\n```\n\n    a\tb\n    \tc";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = concat!(
+            "<!DOCTYPE html><html><pre><code>fn main() {\n\tlet x = 1;\n}</code></pre>",
+            "<pre><code>a\tb\n\tc</code></pre></html>"
+        );
+        assert_eq!(&actual, expected);
+
+        let html_renderer = HTMLRenderer::default().with_code_line_numbers(true);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        assert!(actual.contains("<span class=\"line\">\tlet x = 1;</span>"));
+    }
+
+    #[test]
+    pub fn autolink_test() {
+        let input = "<https://x.com/a?b=1>, <a.b+c@example.com>, <not a link>, and <nope>";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = concat!(
+            r#"<!DOCTYPE html><html><p><a href="https://x.com/a?b=1">https://x.com/a?b=1</a>, "#,
+            r#"<a href="mailto:a.b+c@example.com">a.b+c@example.com</a>, "#,
+            "<not a link>, and <nope></p></html>"
+        );
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn builder_test() {
+        use crate::ast::builder::*;
+
+        let root = document(vec![
+            heading(2, vec![text("Hi")]),
+            paragraph(vec![
+                strong(vec![text("Built")]),
+                text(" by hand"),
+                soft_linebreak(),
+                code("x"),
+            ]),
+            list(false, vec![vec![text("One")], vec![text("Two")]]),
+            code_block(Some("rust"), "let x = 1;"),
+        ]);
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = concat!(
+            "<!DOCTYPE html><html><h2>Hi</h2>",
+            "<p><strong>Built</strong> by hand <code>x</code></p>",
+            "<ul><li>One</li><li>Two</li></ul>",
+            r#"<pre><code class="language-rust">let x = 1;</code></pre></html>"#
+        );
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn encode_destination_test() {
+        let root = parse_document("[x](/a b) [y](/caf\u{e9}?q=%20 \"Title\") ![z](/\u{1F600}.png)")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = concat!(
+            r#"<!DOCTYPE html><html><p><a href="/a%20b">x</a> "#,
+            r#"<a href="/caf%C3%A9?q=%20" title="Title">y</a> "#,
+            r#"<img src="/%F0%9F%98%80.png" alt="z"></p></html>"#
+        );
+        assert_eq!(&actual, expected);
+        assert_eq!(encode_destination("/100%"), "/100%25");
+    }
+
+    #[test]
+    pub fn id_prefix_test() {
+        let render = |input: &str, prefix: &str| {
+            let root =
+                parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            let html_renderer = HTMLRenderer::default()
+                .with_heading_ids(true)
+                .with_id_prefix(prefix);
+            root.traverse(&html_renderer);
+            html_renderer.to_string()
+        };
+        let first = render("# Getting Started!\n\n## Setup\n\n## Setup\n", "first-");
+        let second = render("# Getting Started\n\n## Usage\n", "second-");
+        assert_eq!(
+            first,
+            concat!(
+                r#"<!DOCTYPE html><html><h1 id="first-getting-started">Getting Started!</h1>"#,
+                r#"<h2 id="first-setup">Setup</h2><h2 id="first-setup-1">Setup</h2></html>"#
+            )
+        );
+        assert_eq!(
+            second,
+            concat!(
+                r#"<!DOCTYPE html><html><h1 id="second-getting-started">Getting Started</h1>"#,
+                r#"<h2 id="second-usage">Usage</h2></html>"#
+            )
+        );
+    }
+
+    #[test]
+    pub fn unique_id_test() {
+        let render = |input: &str| {
+            let root =
+                parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            let html_renderer = HTMLRenderer::default().with_heading_ids(true);
+            root.traverse(&html_renderer);
+            html_renderer.to_string()
+        };
+        // A numbered id can't take the id of a heading whose text ends in the same number
+        assert_eq!(
+            render("## Setup\n\n## Setup\n\n## Setup 1"),
+            concat!(
+                r#"<!DOCTYPE html><html><h2 id="setup">Setup</h2><h2 id="setup-1">Setup</h2>"#,
+                r#"<h2 id="setup-1-1">Setup 1</h2></html>"#
+            )
+        );
+        assert_eq!(
+            render("## !!!\n\n## Section"),
+            concat!(
+                r#"<!DOCTYPE html><html><h2 id="section">!!!</h2>"#,
+                r#"<h2 id="section-1">Section</h2></html>"#
+            )
+        );
+    }
+
+    #[test]
+    pub fn linear_resolver_test() {
+        let inputs = [
+            read_file_to_string("markdown/markup.md"),
+            read_file_to_string("markdown/lists.md"),
+            String::from(
+                "[First] and [SECOND][second]\n\n[first]: /1\n[second]: /2 \"Two\"\n[first]: /dup",
+            ),
+        ];
+        for input in inputs.iter() {
+            let root =
+                parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            let link_resolver = LinkResolver::default();
+            root.traverse(&link_resolver);
+            let html_renderer = HTMLRenderer::with_resolver(link_resolver);
+            root.traverse(&html_renderer);
+            assert_eq!(render_to_html(&root), html_renderer.to_string());
+        }
+    }
+
+    #[test]
+    pub fn heading_attributes_test() {
+        let input = "## Custom {#custom-id .note .wide lang=en}\n\n# Plain {.only-class}";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default().with_heading_ids(true);
+        root.traverse(&html_renderer);
+        assert_eq!(
+            html_renderer.to_string(),
+            r#"<!DOCTYPE html><html><h2 id="custom-id" class="note wide" lang="en">Custom</h2><h1 id="plain" class="only-class">Plain</h1></html>"#
+        );
+
+        // Generated ids steer clear of the author's, even ones further down the document
+        let root = parse_document("# Intro {#intro}\n\n# Intro\n\n# Setup\n\n# Other {#setup}")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default().with_heading_ids(true);
+        root.traverse(&html_renderer);
+        assert_eq!(
+            html_renderer.to_string(),
+            concat!(
+                r#"<!DOCTYPE html><html><h1 id="intro">Intro</h1><h1 id="intro-1">Intro</h1>"#,
+                r#"<h1 id="setup-1">Setup</h1><h1 id="setup">Other</h1></html>"#
+            )
+        );
+    }
+
+    #[test]
+    pub fn error_block_test() {
+        let root = crate::ast::parse_document_lenient("# Title\n\nSome *text*\n\n  ");
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        assert_eq!(
+            html_renderer.to_string(),
+            r#"<!DOCTYPE html><html><h1>Title</h1><p>Some <em>text</em></p><pre class="parse-error">  </pre></html>"#
+        );
+    }
+
+    #[test]
+    pub fn inline_code_test() {
+        let root = parse_document("a `b` c\n\n```\nblock\n```")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        assert_eq!(
+            html_renderer.to_string(),
+            "<!DOCTYPE html><html><p>a <code>b</code> c</p><pre><code>block</code></pre></html>"
+        );
+    }
+
+    #[test]
+    pub fn trimmed_content_test() {
+        let input = "#   Spaced   \n\n-   item   \n-  last  ";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        assert_eq!(
+            html_renderer.to_string(),
+            "<!DOCTYPE html><html><h1>Spaced</h1><ul><li>item</li><li>last</li></ul></html>"
+        );
+
+        // The spans still cover the original source, whitespace and all
+        let blocks = root.children().unwrap();
+        assert_eq!(blocks[0].as_span(), "#   Spaced   \n\n");
+        assert_eq!(blocks[0].inner_source(), Some("Spaced"));
+        let last_item = &blocks[1].children().unwrap()[1];
+        assert_eq!(last_item.as_span(), "-  last  ");
+        assert_eq!(last_item.inner_source(), Some("last"));
+    }
+
+    #[test]
+    pub fn render_markdown_test() {
+        let input = "# Title\n\n[Home]\n\n[home]: /index.html";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert_eq!(render_markdown(input), render_to_html(&root));
+        assert_eq!(
+            render_markdown("Trailing\n\n  "),
+            r#"<!DOCTYPE html><html><p>Trailing</p><pre class="parse-error">  </pre></html>"#
+        );
+    }
+
+    #[test]
+    pub fn duplicate_reference_test() {
+        let input = "[x]\n\n[x]: /first\n[x]: /second";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let expected = r#"<!DOCTYPE html><html><p><a href="/first">x</a></p></html>"#;
+        let link_resolver = LinkResolver::default();
+        root.traverse(&link_resolver);
+        let html_renderer = HTMLRenderer::with_resolver(link_resolver);
+        root.traverse(&html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+        assert_eq!(render_to_html(&root), expected);
+    }
+
+    #[test]
+    pub fn flush_per_block_test() {
+        use crate::transformer::sink::IoSink;
+
+        /// Records where in the output each flush happened.
+        #[derive(Default)]
+        struct FlushRecorder {
+            written: Vec<u8>,
+            flushes: Vec<usize>,
+        }
+
+        impl std::io::Write for FlushRecorder {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.written.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.flushes.push(self.written.len());
+                Ok(())
+            }
+        }
+
+        let root = parse_document("# Title\n\n> Some *text*\n\n---\n\n- a\n- b")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer =
+            HTMLRenderer::with_sink(IoSink(FlushRecorder::default())).with_flush_per_block(true);
+        root.traverse(&html_renderer);
+        let recorder = html_renderer.into_sink().into_inner();
+
+        let output = String::from_utf8(recorder.written).unwrap();
+        let mut start = 0;
+        let chunks: Vec<&str> = recorder
+            .flushes
+            .iter()
+            .map(|&end| {
+                let chunk = &output[start..end];
+                start = end;
+                chunk
+            })
+            .collect();
+        assert_eq!(
+            chunks,
+            vec![
+                "<!DOCTYPE html><html><h1>Title</h1>",
+                "<blockquote>Some <em>text</em></blockquote>",
+                "<hr>",
+                "<ul><li>a</li><li>b</li></ul>",
+                "</html>",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn footnote_test() {
+        let input = "Uses[^note] twice[^note] and once[^b] and [^missing].\n\n[^note]: The *note*.\n\n[^b]: Other\n\n[^unused]: Never shown";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let expected = concat!(
+            r##"<!DOCTYPE html><html><p>Uses<sup class="footnote-ref"><a href="#fn-1" id="fnref-1">1</a></sup>"##,
+            r##" twice<sup class="footnote-ref"><a href="#fn-1" id="fnref-1-2">1</a></sup>"##,
+            r##" and once<sup class="footnote-ref"><a href="#fn-2" id="fnref-2">2</a></sup> and [^missing].</p>"##,
+            r##"<section class="footnotes"><ol><li id="fn-1">The <em>note</em>."##,
+            r##" <a href="#fnref-1" class="footnote-backref">↩</a>"##,
+            r##" <a href="#fnref-1-2" class="footnote-backref">↩<sup>2</sup></a></li>"##,
+            r##"<li id="fn-2">Other <a href="#fnref-2" class="footnote-backref">↩</a></li></ol></section></html>"##,
+        );
+        assert_eq!(html_renderer.to_string(), expected);
+
+        let mut html_renderer = HTMLRenderer::default();
+        render(&root, &mut html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+    }
+
+    #[test]
+    pub fn nested_footnote_test() {
+        // A footnote that uses another footnote adds it to the list while it's being written
+        let input = "x[^1]\n\n[^1]: see [^2]\n\n[^2]: two";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html = render_to_html(&root);
+        let expected = concat!(
+            r##"<!DOCTYPE html><html><p>x<sup class="footnote-ref"><a href="#fn-1" id="fnref-1">1</a></sup></p>"##,
+            r##"<section class="footnotes"><ol><li id="fn-1">see <sup class="footnote-ref"><a href="#fn-2" id="fnref-2">2</a></sup>"##,
+            r##" <a href="#fnref-1" class="footnote-backref">↩</a></li>"##,
+            r##"<li id="fn-2">two <a href="#fnref-2" class="footnote-backref">↩</a></li></ol></section></html>"##,
+        );
+        assert_eq!(html, expected);
+    }
+
+    #[test]
+    pub fn default_code_language_test() {
+        let input = "```\nls -a\n```\n\n```rust\nlet x = 1;\n```";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default().with_default_code_language(Some("sh"));
+        root.traverse(&html_renderer);
+        assert_eq!(
+            html_renderer.to_string(),
+            concat!(
+                r#"<!DOCTYPE html><html><pre><code class="language-sh">ls -a</code></pre>"#,
+                r#"<pre><code class="language-rust">let x = 1;</code></pre></html>"#
+            )
+        );
+
+        let html_renderer = HTMLRenderer::default().with_default_code_language(None);
+        root.traverse(&html_renderer);
+        assert!(html_renderer
+            .to_string()
+            .starts_with("<!DOCTYPE html><html><pre><code>ls -a"));
+    }
+
+    #[test]
+    pub fn empty_paragraph_test() {
+        // Emphasis around nothing but a hard break parses as a paragraph with nothing to show
+        let root =
+            parse_document("*\\\n*").unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        assert_eq!(html_renderer.to_string(), "<!DOCTYPE html><html></html>");
+        let html_renderer = HTMLRenderer::default().with_empty_paragraphs(true);
+        root.traverse(&html_renderer);
+        assert_eq!(
+            html_renderer.to_string(),
+            "<!DOCTYPE html><html><p><em><br></em></p></html>"
+        );
+
+        // Dropping the image leaves a paragraph with only whitespace and an empty emphasis
+        let input = "A paragraph\n\n![logo](logo.png) *\n![logo](logo.png)*";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let filtered = root
+            .filter_map(|node| match node {
+                Node::Image(_) => None,
+                node => Some(node),
+            })
+            .unwrap_or_else(|| panic!("Document was dropped"));
+        let html_renderer = HTMLRenderer::default();
+        filtered.traverse(&html_renderer);
+        let expected = "<!DOCTYPE html><html><p>A paragraph</p></html>";
+        assert_eq!(html_renderer.to_string(), expected);
+
+        let mut html_renderer = HTMLRenderer::default();
+        render(&filtered, &mut html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+
+        let html_renderer = HTMLRenderer::default().with_empty_paragraphs(true);
+        filtered.traverse(&html_renderer);
+        assert!(html_renderer.to_string().contains("<p>A paragraph</p><p>"));
+    }
+
+    #[test]
+    pub fn string_sink_test() {
+        let root = parse_document("Rendered *straight* into a `String`")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::with_sink(String::new());
+        root.traverse(&html_renderer);
+        let actual = html_renderer.into_sink();
+        let expected = "<!DOCTYPE html><html><p>Rendered <em>straight</em> into a <code>String</code></p></html>";
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn explicit_li_values_test() {
+        let root = parse_document("1. One\n5. Five\n3. Three")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default().with_explicit_li_values(true);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = r#"<!DOCTYPE html><html><ol start="1"><li value="1">One</li><li value="5">Five</li><li value="3">Three</li></ol></html>"#;
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn built_document_test() {
+        use crate::ast::model::{Children, Document};
+
+        let mut first = parse_document("The first paragraph")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let mut second = parse_document("The *second* paragraph")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let take_paragraph = |document: &mut Node<'static>| {
+            document
+                .children_mut()
+                .unwrap_or_else(|| panic!("Document has no children"))
+                .remove(0)
+        };
+
+        let root = Node::Document(
+            Document::new(Children::default(), "")
+                .with_child(take_paragraph(&mut first))
+                .with_child(take_paragraph(&mut second)),
+        );
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = "<!DOCTYPE html><html><p>The first paragraph</p><p>The <em>second</em> paragraph</p></html>";
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn code_escapes_test() {
+        let root = parse_document(
+            "Escapes like `\\*` stay literal in code\n\n```\n\\*not\\_escaped\n```\n",
+        )
+        .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = r#"<!DOCTYPE html><html><p>Escapes like <code>\*</code> stay literal in code</p><pre><code>\*not\_escaped</code></pre></html>"#;
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn block_marker_escapes_test() {
+        let root = parse_document("# \\# not a bigger heading\n\n- \\- literal dash")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = r#"<!DOCTYPE html><html><h1># not a bigger heading</h1><ul><li>- literal dash</li></ul></html>"#;
+        assert_eq!(&actual, expected);
+
+        let mut html_renderer = HTMLRenderer::default();
+        render(&root, &mut html_renderer);
+        assert_eq!(&html_renderer.to_string(), expected);
+    }
+
+    #[test]
+    pub fn task_list_test() {
+        let root = parse_document("Tasks\n\n- [ ] todo\n- [x] done\n- plain\n- [X]")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = r#"<!DOCTYPE html><html><p>Tasks</p><ul><li><input type="checkbox" disabled=""> todo</li><li><input type="checkbox" checked="" disabled=""> done</li><li>plain</li><li><input type="checkbox" checked="" disabled=""></li></ul></html>"#;
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn interactive_tasks_test() {
+        let root = parse_document("Tasks\n\n- [ ] todo\n- [x] done")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let expected = r#"<!DOCTYPE html><html><p>Tasks</p><ul><li><input type="checkbox" data-task-line="3"> todo</li><li><input type="checkbox" checked="" data-task-line="4"> done</li></ul></html>"#;
+
+        let html_renderer = HTMLRenderer::default().with_interactive_tasks(true);
+        root.traverse(&html_renderer);
+        assert_eq!(&html_renderer.to_string(), expected);
+
+        let mut html_renderer = HTMLRenderer::default().with_interactive_tasks(true);
+        render(&root, &mut html_renderer);
+        assert_eq!(&html_renderer.to_string(), expected);
+    }
+
+    #[test]
+    pub fn inline_features_test() {
+        let root = parse_document("![x](y) and [x](y) with ![t](u \"v\")")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let features = InlineFeatures::ALL - InlineFeatures::IMAGES;
+        let expected =
+            r#"<!DOCTYPE html><html><p>![x](y) and <a href="y">x</a> with ![t](u "v")</p></html>"#;
+
+        let html_renderer = HTMLRenderer::default().with_inline_features(features);
+        root.traverse(&html_renderer);
+        assert_eq!(&html_renderer.to_string(), expected);
+
+        let mut html_renderer = HTMLRenderer::default().with_inline_features(features);
+        render(&root, &mut html_renderer);
+        assert_eq!(&html_renderer.to_string(), expected);
+
+        // Disabled containers are written as their source without rendering their children
+        let root = parse_document("*a **b*** and **c**")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default()
+            .with_inline_features(InlineFeatures::ALL - InlineFeatures::EMPHASIS);
+        root.traverse(&html_renderer);
+        assert_eq!(
+            html_renderer.to_string(),
+            "<!DOCTYPE html><html><p>*a **b*** and <strong>c</strong></p></html>"
+        );
+    }
+
+    #[test]
+    pub fn renderer_config_test() {
+        let config = RendererConfig {
+            heading_ids: true,
+            xhtml: true,
+            ..Default::default()
+        };
+        let inputs = ["# Intro", "# Intro\n\nA  \nbreak", "[x]\n\n[x]: /linked"];
+        let outputs = inputs
+            .iter()
+            .map(|input| config.render(input))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|e| panic!("Failed to render documents: {e}"));
+        // Ids start over for each document, since each one gets a fresh renderer
+        assert_eq!(
+            outputs,
+            [
+                r#"<!DOCTYPE html><html><h1 id="intro">Intro</h1></html>"#,
+                r#"<!DOCTYPE html><html><h1 id="intro">Intro</h1><p>A<br />break</p></html>"#,
+                r#"<!DOCTYPE html><html><p><a href="/linked">x</a></p></html>"#,
+            ]
+        );
+        assert!(config.render("  ").is_err());
+
+        let html_renderer = HTMLRenderer::default().with_config(config.clone());
+        assert_eq!(html_renderer.config(), &config);
+    }
+
+    #[test]
+    pub fn markdown_error_test() {
+        match Html::try_from("  ") {
+            Err(MarkdownError::Parse(_)) => {}
+            other => panic!("Expected a parse error, got {other:?}"),
+        }
+
+        // List items can't be rendered outside of a list
+        let item = ListItem::new(crate::ast::model::Children::default(), "- x", 1, None);
+        let root = Node::ListItem(item);
+        match try_render_to_html(&root) {
+            Err(MarkdownError::Render(RenderError::StateError(_))) => {}
+            other => panic!("Expected a render error, got {other:?}"),
+        }
+
+        let root =
+            parse_document("Fine").unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert_eq!(
+            try_render_to_html(&root).unwrap_or_else(|e| panic!("Failed to render document: {e}")),
+            render_to_html(&root)
+        );
+    }
+
+    #[test]
+    pub fn nested_emphasis_test() {
+        let cases = [
+            ("*a **b** c*", "<em>a <strong>b</strong> c</em>"),
+            ("**a *b* c**", "<strong>a <em>b</em> c</strong>"),
+            ("*a *b* c*", "<em>a <em>b</em> c</em>"),
+            ("*a _b_ c*", "<em>a <em>b</em> c</em>"),
+            // The first delimiter that can close a run of emphasis does, like in CommonMark
+            ("*a*b*", "<em>a</em>b*"),
+            ("*foo _bar* baz_", "<em>foo _bar</em> baz_"),
+            // Overlapping runs never produce crossed tags. Delimiter runs aren't split like in
+            // CommonMark, which gives `<em>a <em><em>b</em> c</em></em>`, so the run that can't
+            // be closed is text instead
+            ("*a **b* c**", "*a <strong>b* c</strong>"),
+            // Which for the opposite overlap happens to match CommonMark
+            ("**a *b** c*", "*<em>a <em>b</em></em> c*"),
+        ];
+        for (input, expected) in cases {
+            let root =
+                parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            let expected = format!("<!DOCTYPE html><html><p>{expected}</p></html>");
+
+            let html_renderer = HTMLRenderer::default();
+            root.traverse(&html_renderer);
+            assert_eq!(html_renderer.to_string(), expected, "{input:?}");
+
+            let mut html_renderer = HTMLRenderer::default();
+            render(&root, &mut html_renderer);
+            assert_eq!(html_renderer.to_string(), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    pub fn unresolved_references_test() {
+        let input = "See [docs] and ![logo][missing].\n\n[docs]: /docs";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let unresolved = LinkResolver::from_document(&root).unresolved(&root);
+        assert_eq!(unresolved.len(), 1);
+        assert_eq!(unresolved[0].label(), "missing");
+        assert_eq!(unresolved[0].span(), "logo");
+
+        let link_resolver = LinkResolver::default();
+        root.traverse(&link_resolver);
+        assert_eq!(link_resolver.unresolved(&root), unresolved);
+    }
+
+    #[test]
+    pub fn quote_citation_test() {
+        let input = "> Stay *hungry*\n> \u{2014} Steve Jobs\n\n> A \u{2014} in the middle";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let expected = "<!DOCTYPE html><html><blockquote><p>Stay <em>hungry</em></p><cite>Steve Jobs</cite></blockquote><blockquote>A \u{2014} in the middle</blockquote></html>";
+
+        let html_renderer = HTMLRenderer::default().with_quote_citations(true);
+        root.traverse(&html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+
+        let mut html_renderer = HTMLRenderer::default().with_quote_citations(true);
+        render(&root, &mut html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+
+        // Citations are opt in
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        assert!(html_renderer
+            .to_string()
+            .starts_with("<!DOCTYPE html><html><blockquote>Stay <em>hungry</em> \u{2014} Steve Jobs</blockquote>"));
+    }
+
+    #[test]
+    pub fn flanking_test() {
+        let cases = [
+            ("*(a)*", "<em>(a)</em>"),
+            ("a*b*c", "a<em>b</em>c"),
+            ("**\"x\"**", "<strong>\"x\"</strong>"),
+            // Closing delimiters can't follow a space
+            ("*a *", "*a *"),
+            ("**foo **", "**foo **"),
+            ("*a * b*", "<em>a * b</em>"),
+            // Underscores inside of words are just text
+            ("snake_case_name", "snake_case_name"),
+            ("foo_bar_", "foo_bar_"),
+            ("_a_b", "_a_b"),
+            ("__foo__bar", "__foo__bar"),
+            ("_foo_bar_baz_", "<em>foo_bar_baz</em>"),
+        ];
+        for (input, expected) in cases {
+            let root =
+                parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+            let expected = format!("<!DOCTYPE html><html><p>{expected}</p></html>");
+
+            let html_renderer = HTMLRenderer::default();
+            root.traverse(&html_renderer);
+            assert_eq!(html_renderer.to_string(), expected, "{input:?}");
+
+            let mut html_renderer = HTMLRenderer::default();
+            render(&root, &mut html_renderer);
+            assert_eq!(html_renderer.to_string(), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    pub fn toc_test() {
+        let input = "# Intro\n\n## Setup *steps*\n\n## Setup steps\n\n### Custom {#mine}\n\nText";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let (html, toc) = render_to_html_with_toc(&root);
+        let entry = |level, text: &str, anchor: &str| TocEntry {
+            level,
+            text: text.to_owned(),
+            anchor: anchor.to_owned(),
+        };
+        assert_eq!(
+            toc,
+            [
+                entry(1, "Intro", "intro"),
+                entry(2, "Setup steps", "setup-steps"),
+                entry(2, "Setup steps", "setup-steps-1"),
+                entry(3, "Custom", "mine"),
+            ]
+        );
+        for entry in &toc {
+            assert!(
+                html.contains(&format!(r#" id="{}">"#, entry.anchor)),
+                "No heading with the id {} in {html}",
+                entry.anchor
+            );
+        }
+
+        // The generated ids are the same when rendering through `render`
+        let mut html_renderer = HTMLRenderer::default().with_heading_ids(true);
+        render(&root, &mut html_renderer);
+        assert_eq!(html_renderer.take_toc(), toc);
+        assert_eq!(html_renderer.to_string(), html);
+
+        // No two entries share an anchor, whether the repeat comes from a heading's text or from
+        // an id the author gave
+        let input = "## Setup\n\n## Setup\n\n## Setup 1\n\n# Intro\n\n# Intro {#intro}";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let (html, toc) = render_to_html_with_toc(&root);
+        assert_eq!(
+            toc,
+            [
+                entry(2, "Setup", "setup"),
+                entry(2, "Setup", "setup-1"),
+                entry(2, "Setup 1", "setup-1-1"),
+                entry(1, "Intro", "intro-1"),
+                entry(1, "Intro", "intro"),
+            ]
+        );
+        for entry in &toc {
+            assert_eq!(
+                html.matches(&format!(r#" id="{}">"#, entry.anchor)).count(),
+                1
+            );
+        }
+    }
+
+    #[test]
+    pub fn email_styles_test() {
+        let root = parse_document("> Quoted *text*\n\nA paragraph")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let styles = [
+            ("blockquote", "margin: 0; color: #555"),
+            ("em", "font-style: italic"),
+        ];
+        let expected = concat!(
+            r#"<blockquote style="margin: 0; color: #555">Quoted "#,
+            r#"<em style="font-style: italic">text</em></blockquote><p>A paragraph</p>"#
+        );
+
+        let html_renderer = HTMLRenderer::default().with_email_styles(styles);
+        root.traverse(&html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+
+        let mut html_renderer = HTMLRenderer::default().with_email_styles(styles);
+        render(&root, &mut html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+    }
+
+    #[test]
+    pub fn gallery_test() {
+        let input = "![One](1.png) ![Two](2.png)\n![Three](3.png)\n\n![Alone](4.png)\n\n![Five](5.png) and text";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let expected = concat!(
+            "<!DOCTYPE html><html>",
+            r#"<div class="gallery"><img src="1.png" alt="One"><img src="2.png" alt="Two"><img src="3.png" alt="Three"></div>"#,
+            r#"<p><img src="4.png" alt="Alone"></p>"#,
+            r#"<p><img src="5.png" alt="Five"> and text</p>"#,
+            "</html>"
+        );
+
+        let html_renderer = HTMLRenderer::default().with_galleries(true);
+        root.traverse(&html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+
+        let mut html_renderer = HTMLRenderer::default().with_galleries(true);
+        render(&root, &mut html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+
+        // Galleries are opt in
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        assert!(!html_renderer.to_string().contains("gallery"));
+    }
+
+    #[test]
+    pub fn renderer_builder_test() {
+        let input =
+            "# Title\n\nSome *text* and a [link][home]\\\nbreak\n\n[home]: https://example.com";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let renderer = HTMLRendererBuilder::new()
+            .resolver(LinkResolver::from_document(&root))
+            .sink(String::new())
+            .xhtml(true)
+            .heading_ids(true)
+            .id_prefix("doc-")
+            .presentational_emphasis(true)
+            .external_link_attrs("mysite.com")
+            .build();
+        root.traverse(&renderer);
+        assert_eq!(
+            renderer.into_sink(),
+            r#"<!DOCTYPE html><html><h1 id="doc-title">Title</h1><p>Some <i>text</i> and a <a href="https://example.com" rel="noopener noreferrer">link</a><br />break</p></html>"#
+        );
+
+        // The same as chaining the options onto a renderer
+        let builder = HTMLRendererBuilder::new().xhtml(true).heading_ids(true);
+        let chained = HTMLRenderer::default()
+            .with_xhtml(true)
+            .with_heading_ids(true);
+        assert_eq!(builder.build().config(), chained.config());
+    }
+
+    #[test]
+    pub fn typographic_space_test() {
+        let input = "# Mr.&nbsp;Smith\n\nDr.&nbsp;Jones is extra&shy;ordinary\n\nMs.\u{a0}Lee is co\u{ad}operative\n\n\u{a0}";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let expected = "<!DOCTYPE html><html><h1 id=\"mr-smith\">Mr.&nbsp;Smith</h1><p>Dr.&nbsp;Jones is extra&shy;ordinary</p><p>Ms.\u{a0}Lee is co\u{ad}operative</p><p>\u{a0}</p></html>";
+
+        let html_renderer = HTMLRenderer::default().with_heading_ids(true);
+        root.traverse(&html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+
+        let mut html_renderer = HTMLRenderer::default().with_heading_ids(true);
+        render(&root, &mut html_renderer);
+        assert_eq!(html_renderer.to_string(), expected);
+
+        // Entities count as a single character and are never cut in half
+        let root = parse_document("Dr.&nbsp;Jones")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default().with_text_limit(4);
+        root.traverse(&html_renderer);
+        assert_eq!(
+            html_renderer.to_string(),
+            "<!DOCTYPE html><html><p>Dr.&nbsp;…</p></html>"
+        );
+    }
+
+    #[test]
+    pub fn void_element_style_test() {
+        let root = parse_document("A hard  \nbreak\n\n***\n![alt](img.png)")
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+
+        let html_renderer = HTMLRenderer::default();
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = r#"<!DOCTYPE html><html><p>A hard<br>break</p><hr><p><img src="img.png" alt="alt"></p></html>"#;
+        assert_eq!(&actual, expected);
+
+        let html_renderer = HTMLRenderer::default().with_xhtml(true);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        let expected = r#"<!DOCTYPE html><html><p>A hard<br />break</p><hr /><p><img src="img.png" alt="alt" /></p></html>"#;
+        assert_eq!(&actual, expected);
+    }
+
+    #[test]
+    pub fn commonmark_whitespace_test() {
+        let input = "# Title\n\nA paragraph\nthat wraps\n\n- One\n- Two\n\n***\n\n```rust\nlet x = 1;\n```\n";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let html_renderer = HTMLRenderer::default()
+            .with_xhtml(true)
+            .with_commonmark_whitespace(true);
+        root.traverse(&html_renderer);
+        let actual = html_renderer.to_string();
+        // Output of `cmark` for the same input
+        let expected = "<h1>Title</h1>\n<p>A paragraph\nthat wraps</p>\n<ul>\n<li>One</li>\n<li>Two</li>\n</ul>\n<hr />\n<pre><code class=\"language-rust\">let x = 1;\n</code></pre>\n";
+        assert_eq!(&actual, expected);
     }
 }
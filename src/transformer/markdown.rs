@@ -0,0 +1,654 @@
+use crate::ast::model::{
+    BlockQuote, Code, CodeBlock, DisplayMath, Document, Emphasis, ErrorBlock, FootnoteDefinition,
+    FootnoteReference, Heading, Highlight, Image, Include, InlineMath, Label, Line, LineBlock,
+    Linebreak, Link, List, ListItem, Node, Paragraph, Reference, SoftLinebreak, Strong, Subscript,
+    Superscript, Text, ThematicBreak,
+};
+use crate::ast::traversal::NextAction;
+use crate::transformer::render::Renderer;
+use crate::transformer::sink::Sink;
+
+/// The containers that add a prefix to each of the lines inside of them.
+#[derive(Debug)]
+enum Container {
+    BlockQuote,
+    List { tight: bool, ordered: bool },
+    ListItem { marker: String, started: bool },
+}
+
+/// A [`Renderer`] that writes the AST back out as Markdown in a consistent style, for normalizing
+/// documents. Emphasis is written with `*`, bullets with `-`, code blocks are fenced, and hard
+/// breaks use a backslash. Loose lists keep a blank line between their items, while tight lists
+/// are packed together, so the output parses back into lists with the same tightness.
+///
+/// Use it with [`render`](crate::transformer::render::render):
+///
+/// ```
+/// use pegmd::ast::parse_document;
+/// use pegmd::transformer::render::render;
+/// use pegmd::transformer::MarkdownRenderer;
+///
+/// let root = parse_document("Some __strong__ text").unwrap();
+/// let mut renderer = MarkdownRenderer::default();
+/// render(&root, &mut renderer);
+/// assert_eq!(renderer.into_sink(), "Some **strong** text\n");
+/// ```
+#[derive(Debug)]
+pub struct MarkdownRenderer<S: Sink = String> {
+    output: S,
+    error: Option<std::io::Error>,
+    containers: Vec<Container>,
+    at_line_start: bool,
+    // A block ended, so the next one needs to be separated from it
+    pending_block: bool,
+    // Soft breaks are held back until more text follows, so paragraphs don't end with one
+    pending_soft_break: bool,
+    // Only a heading's marker has been written on the current line, so its text starts the line
+    after_heading_marker: bool,
+    // The current line starts with a few digits, which a `.` or `)` would turn into a list marker
+    after_leading_digits: bool,
+    in_code: bool,
+}
+
+impl Default for MarkdownRenderer {
+    fn default() -> Self {
+        Self::with_sink(String::new())
+    }
+}
+
+impl<S: Sink> MarkdownRenderer<S> {
+    /// Create a Markdown renderer that writes its output to the provided sink.
+    pub fn with_sink(sink: S) -> Self {
+        Self {
+            output: sink,
+            error: None,
+            containers: Vec::new(),
+            at_line_start: true,
+            pending_block: false,
+            pending_soft_break: false,
+            after_heading_marker: false,
+            after_leading_digits: false,
+            in_code: false,
+        }
+    }
+
+    /// Consume the renderer, returning the sink that it wrote to.
+    pub fn into_sink(self) -> S {
+        self.output
+    }
+
+    fn write_raw(&mut self, s: &str) {
+        if self.error.is_none() {
+            if let Err(e) = self.output.write_str(s) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// The prefix for the current line. List items only show their marker on their first line,
+    /// and are indented by the marker's width after that.
+    fn prefix(&mut self) -> String {
+        let mut prefix = String::new();
+        for container in self.containers.iter_mut() {
+            match container {
+                Container::BlockQuote => prefix.push_str("> "),
+                Container::List { .. } => {}
+                Container::ListItem { marker, started } => {
+                    if *started {
+                        prefix.push_str(&" ".repeat(marker.len()));
+                    } else {
+                        prefix.push_str(marker);
+                        *started = true;
+                    }
+                }
+            }
+        }
+        prefix
+    }
+
+    fn end_line(&mut self) {
+        self.write_raw("\n");
+        self.at_line_start = true;
+    }
+
+    /// Write text that might span multiple lines, prefixing each of them.
+    fn emit(&mut self, s: &str) {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.end_line();
+            }
+            if !line.is_empty() {
+                if self.at_line_start {
+                    let prefix = self.prefix();
+                    self.write_raw(&prefix);
+                    self.at_line_start = false;
+                }
+                self.write_raw(line);
+            }
+        }
+    }
+
+    fn inline(&mut self, s: &str) -> NextAction {
+        self.after_heading_marker = false;
+        self.after_leading_digits = false;
+        if self.pending_soft_break {
+            self.pending_soft_break = false;
+            self.end_line();
+        }
+        self.emit(s);
+        self.next_action()
+    }
+
+    /// Separate a new block from whatever came before it in the same container. Blocks in tight
+    /// lists are only put on a new line, and everything else gets a blank line between them.
+    fn start_block(&mut self) {
+        self.pending_soft_break = false;
+        if !self.pending_block && self.at_line_start {
+            return;
+        }
+        self.pending_block = false;
+        if !self.at_line_start {
+            self.end_line();
+        }
+        let tight = self
+            .containers
+            .iter()
+            .rev()
+            .find_map(|container| match container {
+                Container::List { tight, .. } => Some(*tight),
+                Container::BlockQuote => Some(false),
+                Container::ListItem { .. } => None,
+            })
+            .unwrap_or(false);
+        if !tight {
+            let prefix = self.prefix();
+            self.write_raw(prefix.trim_end());
+            self.end_line();
+        }
+    }
+
+    fn end_block(&mut self) -> NextAction {
+        self.pending_soft_break = false;
+        self.pending_block = true;
+        self.next_action()
+    }
+
+    fn next_action(&self) -> NextAction {
+        match self.error {
+            Some(_) => NextAction::End,
+            None => NextAction::GotoNext,
+        }
+    }
+}
+
+impl<S: Sink + AsRef<[u8]>> std::fmt::Display for MarkdownRenderer<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match std::str::from_utf8(self.output.as_ref()) {
+            Ok(s) => write!(f, "{}", s),
+            Err(e) => write!(f, "Invalid UTF-8 contents in buffer: {e:?}"),
+        }
+    }
+}
+
+impl<'input, S: Sink> Renderer<'input> for MarkdownRenderer<S> {
+    fn exit_document(&mut self, _: &'input Document<'input>) -> NextAction {
+        if !self.at_line_start {
+            self.end_line();
+        }
+        self.next_action()
+    }
+
+    fn enter_paragraph(&mut self, _: &'input Paragraph<'input>) -> NextAction {
+        self.start_block();
+        self.next_action()
+    }
+
+    fn exit_paragraph(&mut self, _: &'input Paragraph<'input>) -> NextAction {
+        self.end_block()
+    }
+
+    fn enter_block_quote(&mut self, _: &'input BlockQuote<'input>) -> NextAction {
+        self.start_block();
+        self.containers.push(Container::BlockQuote);
+        self.next_action()
+    }
+
+    fn exit_block_quote(&mut self, _: &'input BlockQuote<'input>) -> NextAction {
+        self.containers.pop();
+        self.end_block()
+    }
+
+    fn enter_heading(&mut self, heading: &'input Heading<'input>) -> NextAction {
+        self.start_block();
+        self.inline(&format!("{} ", "#".repeat(heading.level() as usize)));
+        self.after_heading_marker = true;
+        self.next_action()
+    }
+
+    fn exit_heading(&mut self, heading: &'input Heading<'input>) -> NextAction {
+        if let Some(attributes) = heading.attributes().as_span() {
+            self.inline(&format!(" {attributes}"));
+        }
+        self.end_block()
+    }
+
+    fn enter_list(&mut self, list: &'input List<'input>) -> NextAction {
+        self.start_block();
+        self.containers.push(Container::List {
+            tight: list.tight(),
+            ordered: list.ordered(),
+        });
+        self.next_action()
+    }
+
+    fn exit_list(&mut self, _: &'input List<'input>) -> NextAction {
+        self.containers.pop();
+        self.end_block()
+    }
+
+    fn enter_list_item(&mut self, list_item: &'input ListItem<'input>) -> NextAction {
+        self.start_block();
+        let marker = match self.containers.last() {
+            Some(Container::List { ordered: true, .. }) => format!("{}. ", list_item.index()),
+            _ => "- ".to_owned(),
+        };
+        self.containers.push(Container::ListItem {
+            marker,
+            started: false,
+        });
+        match (list_item.checked(), list_item.children().is_empty()) {
+            (Some(checked), empty) => {
+                let checkbox = if checked { "[x] " } else { "[ ] " };
+                self.inline(if empty { checkbox.trim_end() } else { checkbox })
+            }
+            (None, _) => self.next_action(),
+        }
+    }
+
+    fn exit_list_item(&mut self, _: &'input ListItem<'input>) -> NextAction {
+        if let Some(Container::ListItem { started: false, .. }) = self.containers.last() {
+            // Empty items still need their marker
+            let prefix = self.prefix();
+            self.write_raw(prefix.trim_end());
+            self.at_line_start = false;
+        }
+        self.containers.pop();
+        self.end_block()
+    }
+
+    fn enter_line_block(&mut self, _: &'input LineBlock<'input>) -> NextAction {
+        self.start_block();
+        self.next_action()
+    }
+
+    fn exit_line_block(&mut self, _: &'input LineBlock<'input>) -> NextAction {
+        self.end_block()
+    }
+
+    fn enter_line(&mut self, line: &'input Line<'input>) -> NextAction {
+        if !self.at_line_start {
+            self.end_line();
+        }
+        let marker = format!("| {}", " ".repeat(line.indent() as usize));
+        if line.children().is_empty() {
+            // Empty lines are just the `|`, without trailing whitespace
+            self.inline(marker.trim_end())
+        } else {
+            self.inline(&marker)
+        }
+    }
+
+    fn enter_code_block(&mut self, code_block: &'input CodeBlock<'input>) -> NextAction {
+        self.start_block();
+        self.in_code = true;
+        let fence = code_fence(code_block.children(), 3);
+        self.inline(&format!("{fence}{}\n", code_block.language().unwrap_or("")))
+    }
+
+    fn exit_code_block(&mut self, code_block: &'input CodeBlock<'input>) -> NextAction {
+        self.in_code = false;
+        let fence = code_fence(code_block.children(), 3);
+        if !self.at_line_start {
+            self.end_line();
+        }
+        self.inline(&fence);
+        self.end_block()
+    }
+
+    fn enter_emphasis(&mut self, _: &'input Emphasis<'input>) -> NextAction {
+        self.inline("*")
+    }
+
+    fn exit_emphasis(&mut self, _: &'input Emphasis<'input>) -> NextAction {
+        self.inline("*")
+    }
+
+    fn enter_strong(&mut self, _: &'input Strong<'input>) -> NextAction {
+        self.inline("**")
+    }
+
+    fn exit_strong(&mut self, _: &'input Strong<'input>) -> NextAction {
+        self.inline("**")
+    }
+
+    fn enter_subscript(&mut self, _: &'input Subscript<'input>) -> NextAction {
+        self.inline("~")
+    }
+
+    fn exit_subscript(&mut self, _: &'input Subscript<'input>) -> NextAction {
+        self.inline("~")
+    }
+
+    fn enter_superscript(&mut self, _: &'input Superscript<'input>) -> NextAction {
+        self.inline("^")
+    }
+
+    fn exit_superscript(&mut self, _: &'input Superscript<'input>) -> NextAction {
+        self.inline("^")
+    }
+
+    fn enter_highlight(&mut self, _: &'input Highlight<'input>) -> NextAction {
+        self.inline("==")
+    }
+
+    fn exit_highlight(&mut self, _: &'input Highlight<'input>) -> NextAction {
+        self.inline("==")
+    }
+
+    fn enter_label(&mut self, _: &'input Label<'input>) -> NextAction {
+        self.inline("[")
+    }
+
+    fn exit_label(&mut self, _: &'input Label<'input>) -> NextAction {
+        self.inline("]")
+    }
+
+    fn enter_link(&mut self, link: &'input Link<'input>) -> NextAction {
+        if link.as_span().starts_with('<') {
+            self.inline(&format!("<{}>", link.source()));
+            return NextAction::SkipChildren.and(self.next_action());
+        }
+        self.inline("[")
+    }
+
+    fn exit_link(&mut self, link: &'input Link<'input>) -> NextAction {
+        let span = link.as_span();
+        let source = link.source();
+        let suffix = if span.starts_with('<') {
+            String::new()
+        } else if link.is_reference() && span.ends_with("[]") {
+            "][]".to_owned()
+        } else if link.is_reference() && span.len() == source.len() + 2 {
+            "]".to_owned()
+        } else if link.is_reference() {
+            format!("][{source}]")
+        } else {
+            format!("]({}{})", source, title_suffix(link.title()))
+        };
+        self.inline(&suffix)
+    }
+
+    fn enter_code(&mut self, code: &'input Code<'input>) -> NextAction {
+        self.in_code = true;
+        self.inline(&code_fence(code.children(), 1))
+    }
+
+    fn exit_code(&mut self, code: &'input Code<'input>) -> NextAction {
+        self.in_code = false;
+        self.inline(&code_fence(code.children(), 1))
+    }
+
+    fn text(&mut self, text: &'input Text<'input>) -> NextAction {
+        if self.in_code {
+            return self.inline(text.as_span());
+        }
+        let span = text.as_span();
+        let escaped = escape(span);
+        if self.at_line_start || self.pending_soft_break || self.after_heading_marker {
+            self.inline(&escape_line_start(&escaped, false));
+            self.after_leading_digits =
+                (1..=3).contains(&span.len()) && span.bytes().all(|b| b.is_ascii_digit());
+            self.next_action()
+        } else if self.after_leading_digits {
+            self.inline(&escape_line_start(&escaped, true))
+        } else {
+            self.inline(&escaped)
+        }
+    }
+
+    fn linebreak(&mut self, _: &'input Linebreak<'input>) -> NextAction {
+        self.inline("\\\n")
+    }
+
+    fn soft_linebreak(&mut self, _: &'input SoftLinebreak<'input>) -> NextAction {
+        self.pending_soft_break = true;
+        self.next_action()
+    }
+
+    fn image(&mut self, image: &'input Image<'input>) -> NextAction {
+        let description = image.as_span();
+        let source = image.source();
+        let image = match (image.is_reference(), image.title()) {
+            (true, _) if source == description => format!("![{description}]"),
+            (true, _) => format!("![{description}][{source}]"),
+            (false, title) => format!("![{description}]({source}{})", title_suffix(title)),
+        };
+        self.inline(&image)
+    }
+
+    fn thematic_break(&mut self, _: &'input ThematicBreak<'input>) -> NextAction {
+        self.start_block();
+        self.inline("---");
+        self.end_block()
+    }
+
+    fn reference(&mut self, reference: &'input Reference<'input>) -> NextAction {
+        self.start_block();
+        let title = title_suffix(reference.title());
+        self.inline(&format!(
+            "[{}]: {}{title}",
+            reference.name(),
+            reference.source()
+        ));
+        self.end_block()
+    }
+
+    fn inline_math(&mut self, inline_math: &'input InlineMath<'input>) -> NextAction {
+        self.inline(&format!("${}$", inline_math.expression()))
+    }
+
+    fn display_math(&mut self, display_math: &'input DisplayMath<'input>) -> NextAction {
+        self.start_block();
+        self.inline(&format!("$${}$$", display_math.expression()));
+        self.end_block()
+    }
+
+    fn footnote_reference(
+        &mut self,
+        footnote_reference: &'input FootnoteReference<'input>,
+    ) -> NextAction {
+        self.inline(&format!("[^{}]", footnote_reference.label()))
+    }
+
+    fn enter_footnote_definition(
+        &mut self,
+        footnote_definition: &'input FootnoteDefinition<'input>,
+    ) -> NextAction {
+        self.start_block();
+        self.inline(&format!("[^{}]: ", footnote_definition.label()))
+    }
+
+    fn exit_footnote_definition(&mut self, _: &'input FootnoteDefinition<'input>) -> NextAction {
+        self.end_block()
+    }
+
+    fn error_block(&mut self, error_block: &'input ErrorBlock<'input>) -> NextAction {
+        self.start_block();
+        self.inline(error_block.as_span().trim_end());
+        self.end_block()
+    }
+
+    fn include(&mut self, include: &'input Include<'input>) -> NextAction {
+        self.start_block();
+        self.inline(&format!("{{{{include: {}}}}}", include.name()));
+        self.end_block()
+    }
+}
+
+/// A run of backticks that's longer than any run inside of the code, so that it can't be closed
+/// early.
+fn code_fence(children: &[Node], minimum: usize) -> String {
+    let longest = children
+        .iter()
+        .flat_map(Node::text_leaves)
+        .flat_map(|text| text.split(|c| c != '`'))
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    "`".repeat(minimum.max(longest + 1))
+}
+
+fn title_suffix(title: Option<&str>) -> String {
+    match title {
+        Some(title) if title.contains('"') => format!(" ({title})"),
+        Some(title) => format!(r#" "{title}""#),
+        None => String::new(),
+    }
+}
+
+/// Backslash escape the characters that would otherwise start inline markup.
+fn escape(text: &str) -> std::borrow::Cow<'_, str> {
+    const SPECIAL: &[char] = &['\\', '*', '_', '`', '[', ']', '<', '~', '^', '$'];
+    if !text.contains(SPECIAL) {
+        return std::borrow::Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 4);
+    for c in text.chars() {
+        if SPECIAL.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    std::borrow::Cow::Owned(escaped)
+}
+
+/// Escape the start of text that begins a line, if it would otherwise be read as the marker of a
+/// heading, block quote, line block, or list item. Text that follows the digits of a would-be
+/// ordered list item is checked with `after_digits`, since those are usually separate text nodes.
+fn escape_line_start(text: &str, after_digits: bool) -> std::borrow::Cow<'_, str> {
+    let marker_end = |rest: &str| rest.is_empty() || rest.starts_with(char::is_whitespace);
+    let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let split = if after_digits || (1..=3).contains(&digits) {
+        let digits = if after_digits { 0 } else { digits };
+        if !text[digits..].starts_with(['.', ')']) || !marker_end(&text[digits + 1..]) {
+            return std::borrow::Cow::Borrowed(text);
+        }
+        digits
+    } else if text.starts_with(['#', '>', '|'])
+        || text.starts_with(['-', '+']) && marker_end(&text[1..])
+    {
+        0
+    } else {
+        return std::borrow::Cow::Borrowed(text);
+    };
+    std::borrow::Cow::Owned(format!("{}\\{}", &text[..split], &text[split..]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::parse_document;
+    use crate::transformer::render::render;
+
+    fn normalize(input: &str) -> String {
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let mut renderer = MarkdownRenderer::default();
+        render(&root, &mut renderer);
+        renderer.into_sink()
+    }
+
+    fn list_tightness(input: &str) -> Vec<bool> {
+        parse_document(input)
+            .unwrap_or_else(|e| panic!("Failed to parse document: {e}"))
+            .descendants_with_depth()
+            .filter_map(|(_, node)| node.is_tight())
+            .collect()
+    }
+
+    #[test]
+    pub fn loose_list_round_trip_test() {
+        let loose = "* First\n\n* Second\n\n  More\n\n* Third";
+        let output = normalize(loose);
+        assert_eq!(output, "- First\n\n- Second\n\n  More\n\n- Third\n");
+        assert_eq!(list_tightness(loose), vec![false]);
+        assert_eq!(list_tightness(&output), list_tightness(loose));
+
+        let tight = "1. First\n2. Second\n   - Nested\n   - List";
+        let output = normalize(tight);
+        assert_eq!(output, "1. First\n2. Second\n   - Nested\n   - List\n");
+        assert_eq!(list_tightness(tight), vec![true, true]);
+        assert_eq!(list_tightness(&output), list_tightness(tight));
+    }
+
+    #[test]
+    pub fn markup_round_trip_test() {
+        let input = "# Title\n\nSome __strong__ and _emphasized_ `code`\nwith a [link](/a \"T\")\n\n> Quoted  \n> text\n\n```rust\nlet x = 1;\n```\n";
+        let output = normalize(input);
+        assert_eq!(
+            output,
+            "# Title\n\nSome **strong** and *emphasized* `code`\nwith a [link](/a \"T\")\n\n> Quoted\\\n> text\n\n```rust\nlet x = 1;\n```\n"
+        );
+        assert_eq!(normalize(&output), output);
+
+        let original =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let normalized =
+            parse_document(&output).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        assert!(normalized.structurally_eq(&original));
+    }
+
+    #[test]
+    pub fn footnote_round_trip_test() {
+        let input = "A note[^1] here\n\n[^1]: The *note*";
+        let output = normalize(input);
+        assert_eq!(output, "A note[^1] here\n\n[^1]: The *note*\n");
+        assert_eq!(normalize(&output), output);
+    }
+
+    #[test]
+    pub fn line_block_round_trip_test() {
+        let input = "|   Indented\n|\n| Plain";
+        let output = normalize(input);
+        assert_eq!(output, "|   Indented\n|\n| Plain\n");
+        assert_eq!(normalize(&output), output);
+    }
+
+    #[test]
+    pub fn block_marker_escape_round_trip_test() {
+        let input =
+            "# \\# not a bigger heading\n\n1\\. not a list\n\n\\> not quoted\n\n- \\- literal dash";
+        let output = normalize(input);
+        assert_eq!(output, format!("{input}\n"));
+        assert_eq!(normalize(&output), output);
+
+        // Markers are only escaped at the start of a line
+        assert_eq!(normalize("C# and 3.5 - x"), "C# and 3.5 - x\n");
+    }
+
+    #[test]
+    pub fn task_list_round_trip_test() {
+        let input = "- [ ] todo\n- [X] done\n- [x]\n- plain";
+        let output = normalize(input);
+        assert_eq!(output, "- [ ] todo\n- [x] done\n- [x]\n- plain\n");
+        assert_eq!(normalize(&output), output);
+    }
+
+    #[test]
+    pub fn include_round_trip_test() {
+        let input = "Before\n\n{{ include:  parts/intro.md }}\n\nAfter";
+        let output = normalize(input);
+        assert_eq!(output, "Before\n\n{{include: parts/intro.md}}\n\nAfter\n");
+        assert_eq!(normalize(&output), output);
+    }
+}
@@ -1,5 +1,34 @@
+pub mod render;
+pub mod sink;
+
 #[cfg(feature = "html")]
 mod html;
 
 #[cfg(feature = "html")]
-pub use html::HTMLRenderer;
+pub use html::{
+    render_markdown, render_to_html, render_to_html_with_toc, try_render_to_html, HTMLRenderer,
+    HTMLRendererBuilder, Html, InlineFeatures, LinkResolver, RendererConfig, TocEntry,
+    UnresolvedReference,
+};
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "markdown")]
+mod markdown;
+
+#[cfg(feature = "markdown")]
+pub use markdown::MarkdownRenderer;
+
+#[cfg(feature = "plaintext")]
+mod plaintext;
+
+#[cfg(feature = "plaintext")]
+pub use plaintext::PlainTextRenderer;
+
+/// Whether `c` is whitespace that renderers are free to collapse or drop. Non-breaking spaces are
+/// left alone, since they're only ever written on purpose to keep words together.
+#[cfg(any(feature = "html", feature = "plaintext"))]
+pub(crate) fn is_collapsible_whitespace(c: char) -> bool {
+    c.is_whitespace() && !matches!(c, '\u{a0}' | '\u{2007}' | '\u{202f}')
+}
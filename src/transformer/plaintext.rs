@@ -0,0 +1,285 @@
+use crate::ast::model::{
+    BlockQuote, CodeBlock, DisplayMath, ErrorBlock, FootnoteDefinition, Heading, Image, InlineMath,
+    Line, LineBlock, Linebreak, List, ListItem, Paragraph, SoftLinebreak, Text, ThematicBreak,
+};
+use crate::ast::traversal::NextAction;
+use crate::transformer::is_collapsible_whitespace;
+use crate::transformer::render::Renderer;
+use crate::transformer::sink::Sink;
+
+/// The whitespace owed between what's been written and whatever comes next. Separators only grow
+/// until the next word is written, so a block boundary wins over the space before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Separator {
+    None,
+    Space,
+    Linebreaks(usize),
+    Block,
+}
+
+/// A [`Renderer`] that writes only the text of a document, for things like search indexes and
+/// previews. Whitespace is always written the same way no matter how the source was formatted:
+/// words are separated by single spaces, each hard break becomes a `\n`, blocks are separated by
+/// `\n\n`, and the output never starts or ends with whitespace. Soft breaks are just a space.
+///
+/// The lines of a code block are kept on lines of their own, while formatting like emphasis and
+/// links is dropped in favor of its text. Images are written as their description and math as its
+/// expression, and reference definitions are left out entirely.
+///
+/// ```
+/// use pegmd::ast::parse_document;
+/// use pegmd::transformer::render::render;
+/// use pegmd::transformer::PlainTextRenderer;
+///
+/// let root = parse_document("# Title\n\nSome   *spaced*\ntext").unwrap();
+/// let mut renderer = PlainTextRenderer::default();
+/// render(&root, &mut renderer);
+/// assert_eq!(renderer.into_sink(), "Title\n\nSome spaced text");
+/// ```
+#[derive(Debug)]
+pub struct PlainTextRenderer<S: Sink = String> {
+    output: S,
+    error: Option<std::io::Error>,
+    separator: Separator,
+    // Nothing is written before the first word, so that the output doesn't start with whitespace
+    started: bool,
+    in_code_block: bool,
+}
+
+impl Default for PlainTextRenderer {
+    fn default() -> Self {
+        Self::with_sink(String::new())
+    }
+}
+
+impl<S: Sink> PlainTextRenderer<S> {
+    /// Create a plain text renderer that writes its output to the provided sink.
+    pub fn with_sink(sink: S) -> Self {
+        Self {
+            output: sink,
+            error: None,
+            separator: Separator::None,
+            started: false,
+            in_code_block: false,
+        }
+    }
+
+    /// Consume the renderer, returning the sink that it wrote to.
+    pub fn into_sink(self) -> S {
+        self.output
+    }
+
+    fn write_raw(&mut self, s: &str) {
+        if self.error.is_none() {
+            if let Err(e) = self.output.write_str(s) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn separate(&mut self, separator: Separator) {
+        self.separator = match (self.separator, separator) {
+            (Separator::Linebreaks(a), Separator::Linebreaks(b)) => Separator::Linebreaks(a + b),
+            (current, separator) => current.max(separator),
+        };
+    }
+
+    fn word(&mut self, word: &str) {
+        if self.started {
+            match self.separator {
+                Separator::None => {}
+                Separator::Space => self.write_raw(" "),
+                Separator::Linebreaks(count) => self.write_raw(&"\n".repeat(count)),
+                Separator::Block => self.write_raw("\n\n"),
+            }
+        }
+        self.write_raw(word);
+        self.separator = Separator::None;
+        self.started = true;
+    }
+
+    /// Write text, collapsing each run of whitespace in it into a single space. Non-breaking
+    /// spaces are kept as part of the words around them.
+    fn words(&mut self, text: &str) -> NextAction {
+        for (i, word) in text.split(is_collapsible_whitespace).enumerate() {
+            if i > 0 {
+                self.separate(Separator::Space);
+            }
+            if !word.is_empty() {
+                self.word(word);
+            }
+        }
+        self.next_action()
+    }
+
+    fn block(&mut self) -> NextAction {
+        self.separate(Separator::Block);
+        self.next_action()
+    }
+
+    fn next_action(&self) -> NextAction {
+        match self.error {
+            Some(_) => NextAction::End,
+            None => NextAction::GotoNext,
+        }
+    }
+}
+
+impl<S: Sink + AsRef<[u8]>> std::fmt::Display for PlainTextRenderer<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match std::str::from_utf8(self.output.as_ref()) {
+            Ok(s) => write!(f, "{}", s),
+            Err(e) => write!(f, "Invalid UTF-8 contents in buffer: {e:?}"),
+        }
+    }
+}
+
+impl<'input, S: Sink> Renderer<'input> for PlainTextRenderer<S> {
+    fn enter_paragraph(&mut self, _: &'input Paragraph<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn exit_paragraph(&mut self, _: &'input Paragraph<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn enter_block_quote(&mut self, _: &'input BlockQuote<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn exit_block_quote(&mut self, _: &'input BlockQuote<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn enter_heading(&mut self, _: &'input Heading<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn exit_heading(&mut self, _: &'input Heading<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn enter_list(&mut self, _: &'input List<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn exit_list(&mut self, _: &'input List<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn enter_list_item(&mut self, _: &'input ListItem<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn exit_list_item(&mut self, _: &'input ListItem<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn enter_line_block(&mut self, _: &'input LineBlock<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn exit_line_block(&mut self, _: &'input LineBlock<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn enter_line(&mut self, _: &'input Line<'input>) -> NextAction {
+        self.separate(Separator::Linebreaks(1));
+        self.next_action()
+    }
+
+    fn enter_code_block(&mut self, _: &'input CodeBlock<'input>) -> NextAction {
+        self.in_code_block = true;
+        self.block()
+    }
+
+    fn exit_code_block(&mut self, _: &'input CodeBlock<'input>) -> NextAction {
+        self.in_code_block = false;
+        self.block()
+    }
+
+    fn enter_footnote_definition(&mut self, _: &'input FootnoteDefinition<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn exit_footnote_definition(&mut self, _: &'input FootnoteDefinition<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn text(&mut self, text: &'input Text<'input>) -> NextAction {
+        if !self.in_code_block {
+            return self.words(text.as_span());
+        }
+        for (i, line) in text.as_span().split('\n').enumerate() {
+            if i > 0 {
+                self.separate(Separator::Linebreaks(1));
+            }
+            self.words(line);
+        }
+        self.next_action()
+    }
+
+    fn linebreak(&mut self, _: &'input Linebreak<'input>) -> NextAction {
+        self.separate(Separator::Linebreaks(1));
+        self.next_action()
+    }
+
+    fn soft_linebreak(&mut self, _: &'input SoftLinebreak<'input>) -> NextAction {
+        self.separate(Separator::Space);
+        self.next_action()
+    }
+
+    fn image(&mut self, image: &'input Image<'input>) -> NextAction {
+        self.words(image.as_span())
+    }
+
+    fn thematic_break(&mut self, _: &'input ThematicBreak<'input>) -> NextAction {
+        self.block()
+    }
+
+    fn inline_math(&mut self, inline_math: &'input InlineMath<'input>) -> NextAction {
+        self.words(inline_math.expression())
+    }
+
+    fn display_math(&mut self, display_math: &'input DisplayMath<'input>) -> NextAction {
+        self.block();
+        self.words(display_math.expression());
+        self.block()
+    }
+
+    fn error_block(&mut self, error_block: &'input ErrorBlock<'input>) -> NextAction {
+        self.block();
+        self.words(error_block.as_span());
+        self.block()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::parse_document;
+    use crate::transformer::render::render;
+
+    #[test]
+    pub fn whitespace_test() {
+        let input = "#  A   *heading*  \n\n  Some\ttext that\nwraps  \nwith a break\\\nand   another\n\n- One\n- Two\n\n```\nlet x  = 1;\nlet y = 2;\n```\n\n---\n\n> Quoted  \n> text ";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let mut renderer = PlainTextRenderer::default();
+        render(&root, &mut renderer);
+        assert_eq!(
+            renderer.into_sink(),
+            "A heading\n\nSome text that wraps\nwith a break\nand another\n\nOne\n\nTwo\n\nlet x = 1;\nlet y = 2;\n\nQuoted\ntext"
+        );
+    }
+
+    #[test]
+    pub fn non_breaking_space_test() {
+        let input = "Ms.\u{a0}Lee   is co\u{ad}operative";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let mut renderer = PlainTextRenderer::default();
+        render(&root, &mut renderer);
+        assert_eq!(renderer.into_sink(), "Ms.\u{a0}Lee is co\u{ad}operative");
+    }
+}
@@ -0,0 +1,346 @@
+use std::cell::RefCell;
+
+use crate::ast::model::{
+    BlockQuote, Code, CodeBlock, DisplayMath, Document, Emphasis, ErrorBlock, FootnoteDefinition,
+    FootnoteReference, Heading, Highlight, Image, Include, InlineMath, Label, Line, LineBlock,
+    Linebreak, Link, List, ListItem, Node, Paragraph, Reference, SoftLinebreak, Strong, Subscript,
+    Superscript, Text, ThematicBreak,
+};
+use crate::ast::traversal::{Direction, NextAction, TraversalContext, Visitor};
+
+/// Output formats that are generated from the AST one node at a time. Each node with children
+/// has an `enter_` hook that's called before its children and an `exit_` hook that's called after
+/// them, while nodes without children have a single hook. Every hook defaults to doing nothing, so
+/// a renderer only needs to implement the hooks for the nodes it cares about.
+///
+/// Unlike a [`Visitor`], hooks get a mutable reference to the renderer and the node's concrete
+/// type, so there's no need for interior mutability or matching on [`Node`]. Use [`render`] to
+/// walk a tree with a renderer. The returned [`NextAction`] controls the walk the same way it
+/// does for [`Node::traverse`].
+#[allow(unused_variables)]
+pub trait Renderer<'input> {
+    fn enter_document(&mut self, document: &'input Document<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_document(&mut self, document: &'input Document<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_paragraph(&mut self, paragraph: &'input Paragraph<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_paragraph(&mut self, paragraph: &'input Paragraph<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_block_quote(&mut self, block_quote: &'input BlockQuote<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_block_quote(&mut self, block_quote: &'input BlockQuote<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_heading(&mut self, heading: &'input Heading<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_heading(&mut self, heading: &'input Heading<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_list(&mut self, list: &'input List<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_list(&mut self, list: &'input List<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_list_item(&mut self, list_item: &'input ListItem<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_list_item(&mut self, list_item: &'input ListItem<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_line_block(&mut self, line_block: &'input LineBlock<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_line_block(&mut self, line_block: &'input LineBlock<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_line(&mut self, line: &'input Line<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_line(&mut self, line: &'input Line<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_code_block(&mut self, code_block: &'input CodeBlock<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_code_block(&mut self, code_block: &'input CodeBlock<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_emphasis(&mut self, emphasis: &'input Emphasis<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_emphasis(&mut self, emphasis: &'input Emphasis<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_strong(&mut self, strong: &'input Strong<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_strong(&mut self, strong: &'input Strong<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_subscript(&mut self, subscript: &'input Subscript<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_subscript(&mut self, subscript: &'input Subscript<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_superscript(&mut self, superscript: &'input Superscript<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_superscript(&mut self, superscript: &'input Superscript<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_highlight(&mut self, highlight: &'input Highlight<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_highlight(&mut self, highlight: &'input Highlight<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_label(&mut self, label: &'input Label<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_label(&mut self, label: &'input Label<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_link(&mut self, link: &'input Link<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_link(&mut self, link: &'input Link<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_code(&mut self, code: &'input Code<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_code(&mut self, code: &'input Code<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn text(&mut self, text: &'input Text<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn linebreak(&mut self, linebreak: &'input Linebreak<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn soft_linebreak(&mut self, soft_linebreak: &'input SoftLinebreak<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn image(&mut self, image: &'input Image<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn thematic_break(&mut self, thematic_break: &'input ThematicBreak<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn reference(&mut self, reference: &'input Reference<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn inline_math(&mut self, inline_math: &'input InlineMath<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn display_math(&mut self, display_math: &'input DisplayMath<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn error_block(&mut self, error_block: &'input ErrorBlock<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn footnote_reference(
+        &mut self,
+        footnote_reference: &'input FootnoteReference<'input>,
+    ) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn enter_footnote_definition(
+        &mut self,
+        footnote_definition: &'input FootnoteDefinition<'input>,
+    ) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn exit_footnote_definition(
+        &mut self,
+        footnote_definition: &'input FootnoteDefinition<'input>,
+    ) -> NextAction {
+        NextAction::GotoNext
+    }
+
+    fn include(&mut self, include: &'input Include<'input>) -> NextAction {
+        NextAction::GotoNext
+    }
+}
+
+/// Walk the tree rooted at `root`, calling the renderer's hook for each node that's visited.
+///
+/// ### Parameters
+/// - `root` - The node to start rendering from, usually a document.
+/// - `renderer` - Any implementor of the [`Renderer`] trait
+///
+/// ### Returns
+/// The last action returned by the renderer, see [`Node::traverse`].
+pub fn render<'input, R: Renderer<'input>>(
+    root: &'input Node<'input>,
+    renderer: &mut R,
+) -> NextAction {
+    root.traverse(&Dispatcher(RefCell::new(renderer)))
+}
+
+/// Adapts a renderer to a visitor so that rendering gets the same guarantees as any other
+/// traversal, like exit hooks still being called after the walk is ended early.
+struct Dispatcher<'r, R>(RefCell<&'r mut R>);
+
+impl<'r, 'input, R: Renderer<'input>> Visitor<'input> for Dispatcher<'r, R> {
+    fn visit(
+        &self,
+        node: &'input Node<'input>,
+        direction: Direction,
+        _: &TraversalContext<'input>,
+    ) -> NextAction {
+        let mut renderer = self.0.borrow_mut();
+        match (node, direction) {
+            (Node::Document(n), Direction::Entering) => renderer.enter_document(n),
+            (Node::Document(n), Direction::Exiting) => renderer.exit_document(n),
+            (Node::Paragraph(n), Direction::Entering) => renderer.enter_paragraph(n),
+            (Node::Paragraph(n), Direction::Exiting) => renderer.exit_paragraph(n),
+            (Node::BlockQuote(n), Direction::Entering) => renderer.enter_block_quote(n),
+            (Node::BlockQuote(n), Direction::Exiting) => renderer.exit_block_quote(n),
+            (Node::Heading(n), Direction::Entering) => renderer.enter_heading(n),
+            (Node::Heading(n), Direction::Exiting) => renderer.exit_heading(n),
+            (Node::List(n), Direction::Entering) => renderer.enter_list(n),
+            (Node::List(n), Direction::Exiting) => renderer.exit_list(n),
+            (Node::ListItem(n), Direction::Entering) => renderer.enter_list_item(n),
+            (Node::ListItem(n), Direction::Exiting) => renderer.exit_list_item(n),
+            (Node::LineBlock(n), Direction::Entering) => renderer.enter_line_block(n),
+            (Node::LineBlock(n), Direction::Exiting) => renderer.exit_line_block(n),
+            (Node::Line(n), Direction::Entering) => renderer.enter_line(n),
+            (Node::Line(n), Direction::Exiting) => renderer.exit_line(n),
+            (Node::CodeBlock(n), Direction::Entering) => renderer.enter_code_block(n),
+            (Node::CodeBlock(n), Direction::Exiting) => renderer.exit_code_block(n),
+            (Node::Emphasis(n), Direction::Entering) => renderer.enter_emphasis(n),
+            (Node::Emphasis(n), Direction::Exiting) => renderer.exit_emphasis(n),
+            (Node::Strong(n), Direction::Entering) => renderer.enter_strong(n),
+            (Node::Strong(n), Direction::Exiting) => renderer.exit_strong(n),
+            (Node::Subscript(n), Direction::Entering) => renderer.enter_subscript(n),
+            (Node::Subscript(n), Direction::Exiting) => renderer.exit_subscript(n),
+            (Node::Superscript(n), Direction::Entering) => renderer.enter_superscript(n),
+            (Node::Superscript(n), Direction::Exiting) => renderer.exit_superscript(n),
+            (Node::Highlight(n), Direction::Entering) => renderer.enter_highlight(n),
+            (Node::Highlight(n), Direction::Exiting) => renderer.exit_highlight(n),
+            (Node::Label(n), Direction::Entering) => renderer.enter_label(n),
+            (Node::Label(n), Direction::Exiting) => renderer.exit_label(n),
+            (Node::Link(n), Direction::Entering) => renderer.enter_link(n),
+            (Node::Link(n), Direction::Exiting) => renderer.exit_link(n),
+            (Node::Code(n), Direction::Entering) => renderer.enter_code(n),
+            (Node::Code(n), Direction::Exiting) => renderer.exit_code(n),
+            (Node::Text(n), _) => renderer.text(n),
+            (Node::Linebreak(n), _) => renderer.linebreak(n),
+            (Node::SoftLinebreak(n), _) => renderer.soft_linebreak(n),
+            (Node::Image(n), _) => renderer.image(n),
+            (Node::ThematicBreak(n), _) => renderer.thematic_break(n),
+            (Node::Reference(n), _) => renderer.reference(n),
+            (Node::InlineMath(n), _) => renderer.inline_math(n),
+            (Node::DisplayMath(n), _) => renderer.display_math(n),
+            (Node::ErrorBlock(n), _) => renderer.error_block(n),
+            (Node::FootnoteReference(n), _) => renderer.footnote_reference(n),
+            (Node::FootnoteDefinition(n), Direction::Entering) => {
+                renderer.enter_footnote_definition(n)
+            }
+            (Node::FootnoteDefinition(n), Direction::Exiting) => {
+                renderer.exit_footnote_definition(n)
+            }
+            (Node::Include(n), _) => renderer.include(n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::parse_document;
+
+    /// Renders headings as an outline, ignoring everything else.
+    #[derive(Default)]
+    struct Outline {
+        output: String,
+        in_heading: bool,
+    }
+
+    impl<'input> Renderer<'input> for Outline {
+        fn enter_heading(&mut self, heading: &'input Heading<'input>) -> NextAction {
+            self.in_heading = true;
+            self.output
+                .push_str(&"  ".repeat(heading.level() as usize - 1));
+            NextAction::GotoNext
+        }
+
+        fn exit_heading(&mut self, _heading: &'input Heading<'input>) -> NextAction {
+            self.in_heading = false;
+            self.output.push('\n');
+            NextAction::GotoNext
+        }
+
+        fn text(&mut self, text: &'input Text<'input>) -> NextAction {
+            if self.in_heading {
+                self.output.push_str(text.as_span());
+            }
+            NextAction::GotoNext
+        }
+    }
+
+    #[test]
+    pub fn custom_renderer_test() {
+        let input = "# Title\n\nSome text\n\n## First *section*\n\nMore text\n\n## Second\n";
+        let root =
+            parse_document(input).unwrap_or_else(|e| panic!("Failed to parse document: {e}"));
+        let mut outline = Outline::default();
+        render(&root, &mut outline);
+        assert_eq!(outline.output, "Title\n  First section\n  Second\n");
+    }
+}
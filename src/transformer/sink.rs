@@ -0,0 +1,69 @@
+use std::fmt::Arguments;
+
+/// A destination for rendered output. Renderers only ever emit valid UTF-8 text, so a sink
+/// only needs to be able to accept string slices. Implementations are provided for [`String`],
+/// [`Vec<u8>`], and for any [`std::io::Write`] implementor through the [`IoSink`] adapter.
+///
+/// The trait also provides a `write_fmt` method so that sinks can be used with the `write!`
+/// macro, just like [`std::io::Write`] and [`std::fmt::Write`].
+pub trait Sink {
+    fn write_str(&mut self, s: &str) -> std::io::Result<()>;
+
+    /// Push any output that the sink has buffered on to its destination. Sinks that write straight
+    /// to memory have nothing to flush, which is the default.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn write_fmt(&mut self, args: Arguments<'_>) -> std::io::Result<()> {
+        // Avoid allocating if the arguments are just a static string
+        match args.as_str() {
+            Some(s) => self.write_str(s),
+            None => self.write_str(&args.to_string()),
+        }
+    }
+}
+
+impl Sink for String {
+    fn write_str(&mut self, s: &str) -> std::io::Result<()> {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+impl Sink for Vec<u8> {
+    fn write_str(&mut self, s: &str) -> std::io::Result<()> {
+        self.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn write_str(&mut self, s: &str) -> std::io::Result<()> {
+        (**self).write_str(s)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Adapts any [`std::io::Write`] implementor, like a file or a socket, into a [`Sink`].
+#[derive(Debug, Default)]
+pub struct IoSink<W: std::io::Write>(pub W);
+
+impl<W: std::io::Write> IoSink<W> {
+    pub fn into_inner(self) -> W {
+        self.0
+    }
+}
+
+impl<W: std::io::Write> Sink for IoSink<W> {
+    fn write_str(&mut self, s: &str) -> std::io::Result<()> {
+        self.0.write_all(s.as_bytes())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
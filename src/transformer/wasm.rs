@@ -0,0 +1,19 @@
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// [`render_markdown`](super::render_markdown) exported to JavaScript through `wasm-bindgen`, so
+/// that a page can render markdown in the browser with `render_markdown(input)`.
+#[wasm_bindgen]
+pub fn render_markdown(input: &str) -> String {
+    super::render_markdown(input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn wasm_render_markdown_test() {
+        let input = "# Title\n\nSome *text*\n\n  ";
+        assert_eq!(render_markdown(input), super::super::render_markdown(input));
+    }
+}